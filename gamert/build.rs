@@ -22,51 +22,42 @@ fn main() {
     //     }
     // }
 
-    // Compile vertex shader
-    let vert_result = std::process::Command::new("glslc")
-        .arg("src/renderers/shaders/mesh_painter.vert")
-        .arg("-o")
-        .arg("src/renderers/shaders/mesh_painter.vert.spv")
-        .output();
+    // Compile every shader next to its source
+    for shader in [
+        "mesh_painter.vert",
+        "mesh_painter.frag",
+        "fullscreen.vert",
+        "post_process_passthrough.frag",
+        "text.vert",
+        "text.frag",
+        "mip_downsample.comp",
+        "debug_lines.vert",
+        "debug_lines.frag",
+        "tone_map.frag",
+        "skybox.vert",
+        "skybox.frag",
+    ] {
+        let result = std::process::Command::new("glslc")
+            .arg(format!("src/renderers/shaders/{shader}"))
+            .arg("-o")
+            .arg(format!("src/renderers/shaders/{shader}.spv"))
+            .output();
 
-    match vert_result {
-        Ok(output) => {
-            if !output.status.success() {
-                println!("cargo::warning=Vertex shader compilation failed:");
-                println!(
-                    "cargo::warning=stderr: {}",
-                    String::from_utf8_lossy(&output.stderr)
-                );
-                panic!("Failed to compile vertex shader");
+        match result {
+            Ok(output) => {
+                if !output.status.success() {
+                    println!("cargo::warning=Shader {shader} compilation failed:");
+                    println!(
+                        "cargo::warning=stderr: {}",
+                        String::from_utf8_lossy(&output.stderr)
+                    );
+                    panic!("Failed to compile shader {shader}");
+                }
+                println!("cargo::warning=Shader {shader} compiled successfully");
             }
-            println!("cargo::warning=Vertex shader compiled successfully");
-        }
-        Err(e) => {
-            panic!("Failed to execute glslc for vertex shader: {}", e);
-        }
-    }
-
-    // Compile fragment shader
-    let frag_result = std::process::Command::new("glslc")
-        .arg("src/renderers/shaders/mesh_painter.frag")
-        .arg("-o")
-        .arg("src/renderers/shaders/mesh_painter.frag.spv")
-        .output();
-
-    match frag_result {
-        Ok(output) => {
-            if !output.status.success() {
-                println!("cargo::warning=Fragment shader compilation failed:");
-                println!(
-                    "cargo::warning=stderr: {}",
-                    String::from_utf8_lossy(&output.stderr)
-                );
-                panic!("Failed to compile fragment shader");
+            Err(e) => {
+                panic!("Failed to execute glslc for shader {shader}: {}", e);
             }
-            println!("cargo::warning=Fragment shader compiled successfully");
-        }
-        Err(e) => {
-            panic!("Failed to execute glslc for fragment shader: {}", e);
         }
     }
 
@@ -3,9 +3,10 @@ use std::sync::Arc;
 use ash::vk;
 use crossbeam::channel::Receiver;
 use crossbeam::channel::Sender;
+use gpu_allocator::AllocatorReport;
 use thiserror::Error;
 
-use crate::Painter;
+use crate::{Buffer, Painter, buffer::BufferError};
 
 pub use gpu_allocator::vulkan::Allocation as RawAllocation;
 pub use gpu_allocator::vulkan::Allocator as RawAllocator;
@@ -26,6 +27,18 @@ pub enum GAllocatorError {
     MemoryWriteError,
     #[error("Error getting lock to to-be-deleted list for the allocator: {0}")]
     TbdListLockError(String),
+    #[error("Error creating staging buffer: {0}")]
+    StagingBufferError(Box<BufferError>),
+}
+
+const STAGING_CHUNK_SIZE: u64 = 16 * 1024 * 1024;
+const STAGING_ALIGNMENT: u64 = 16;
+
+#[derive(Debug, Clone, Copy)]
+pub struct StagingHandle {
+    pub chunk: usize,
+    pub offset: u64,
+    pub size: u64,
 }
 
 pub struct GAllocator {
@@ -33,6 +46,9 @@ pub struct GAllocator {
     pub(crate) allocator: RawAllocator,
     delete_event_receiver: Receiver<RawAllocation>,
     delete_event_sender: Sender<RawAllocation>,
+    staging_chunks: Vec<Buffer>,
+    staging_chunk: usize,
+    staging_offset: u64,
 }
 
 impl GAllocator {
@@ -53,21 +69,35 @@ impl GAllocator {
             allocator,
             delete_event_receiver: r,
             delete_event_sender: s,
+            staging_chunks: vec![],
+            staging_chunk: 0,
+            staging_offset: 0,
         })
     }
 
+    /// Frees the allocations of buffers and images dropped since the last call. The GPU must
+    /// be done with them, e.g. after waiting for the frames that used them.
     pub fn process_free_events(&mut self) -> Result<(), GAllocatorError> {
         loop {
             let Ok(tbd) = self.delete_event_receiver.try_recv() else {
                 break;
             };
-            self.allocator
-                .free(tbd)
-                .map_err(GAllocatorError::MemoryFreeError)?;
+            self.free(tbd)?;
         }
         Ok(())
     }
 
+    pub fn free(&mut self, allocation: RawAllocation) -> Result<(), GAllocatorError> {
+        self.allocator
+            .free(allocation)
+            .map_err(GAllocatorError::MemoryFreeError)
+    }
+
+    /// Where buffers and images send their allocation when dropped.
+    pub(crate) fn free_sender(&self) -> Sender<RawAllocation> {
+        self.delete_event_sender.clone()
+    }
+
     pub fn allocate_mem(
         &mut self,
         name: &str,
@@ -91,4 +121,71 @@ impl GAllocator {
             .map_err(GAllocatorError::MemoryAllocationError)?;
         Ok(allocation)
     }
+
+    /// Copies `data` into a persistently mapped staging chunk and returns where it landed.
+    /// Chunks are reused across uploads and only grow when the current ones are full.
+    /// Call `reset_staging` once the GPU has finished consuming all staged data.
+    pub fn stage_upload(&mut self, data: &[u8]) -> Result<StagingHandle, GAllocatorError> {
+        let size = data.len() as u64;
+        loop {
+            let offset = self.staging_offset.next_multiple_of(STAGING_ALIGNMENT);
+            let Some(chunk) = self.staging_chunks.get_mut(self.staging_chunk) else {
+                let painter = self.painter.clone();
+                let chunk = painter
+                    .create_buffer(
+                        size.max(STAGING_CHUNK_SIZE),
+                        vk::BufferUsageFlags::TRANSFER_SRC,
+                        Some(self),
+                        Some(true),
+                    )
+                    .map_err(|e| GAllocatorError::StagingBufferError(Box::new(e)))?;
+                self.staging_chunks.push(chunk);
+                self.staging_offset = 0;
+                continue;
+            };
+            if offset + size > chunk.size {
+                self.staging_chunk += 1;
+                self.staging_offset = 0;
+                continue;
+            }
+            chunk
+                .write_to_mem_at(offset as usize, data)
+                .map_err(|e| GAllocatorError::StagingBufferError(Box::new(e)))?;
+            self.staging_offset = offset + size;
+            return Ok(StagingHandle {
+                chunk: self.staging_chunk,
+                offset,
+                size,
+            });
+        }
+    }
+
+    pub fn staging_buffer(&self, handle: &StagingHandle) -> &Buffer {
+        &self.staging_chunks[handle.chunk]
+    }
+
+    /// Rewinds the staging ring. All previously returned handles become invalid.
+    pub fn reset_staging(&mut self) {
+        self.staging_chunk = 0;
+        self.staging_offset = 0;
+    }
+
+    /// Every live allocation and memory block. Allocations dropped since the last
+    /// `process_free_events` are still counted as live.
+    pub fn memory_report(&self) -> AllocatorReport {
+        self.allocator.generate_report()
+    }
+
+    /// One line summary of `memory_report` for logging.
+    pub fn generate_report_string(&self) -> String {
+        let report = self.memory_report();
+        format!(
+            "{} allocations using {:.2} MiB of {:.2} MiB reserved in {} blocks, {} frees pending",
+            report.allocations.len(),
+            report.total_allocated_bytes as f64 / (1024.0 * 1024.0),
+            report.total_reserved_bytes as f64 / (1024.0 * 1024.0),
+            report.blocks.len(),
+            self.delete_event_receiver.len(),
+        )
+    }
 }
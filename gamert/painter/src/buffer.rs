@@ -3,7 +3,7 @@ use crossbeam::channel::Sender;
 use thiserror::Error;
 
 use crate::{
-    GAllocator, Painter,
+    CommandBuffer, GAllocator, GpuCommand, Painter,
     allocator::{GAllocatorError, RawAllocation},
     painter::PainterDelete,
 };
@@ -20,30 +20,145 @@ pub enum BufferError {
     MemoryBindError(vk::Result),
     #[error("Buffer is not host visible/writable")]
     MemoryWriteError,
+    #[error("Buffer is not host visible/readable")]
+    MemoryReadError,
+    #[error("Error creating Vulkan buffer view: {0}")]
+    ViewCreateError(vk::Result),
+}
+
+/// How a command uses a buffer, for the barriers `Painter::record_cmd_buffer` inserts
+/// between uses. Like `ImageAccess`, without layouts to change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferAccess {
+    /// Not used yet in the recording. Earlier submissions are expected to be waited on
+    /// with semaphores or fences.
+    None,
+    TransferRead,
+    TransferWrite,
+    /// Read as a vertex or index buffer.
+    VertexInput,
+    IndirectRead,
+    ShaderRead,
+    ShaderWrite,
+}
+
+impl BufferAccess {
+    pub fn to_access_flags(&self) -> vk::AccessFlags {
+        match self {
+            BufferAccess::None => vk::AccessFlags::empty(),
+            BufferAccess::TransferRead => vk::AccessFlags::TRANSFER_READ,
+            BufferAccess::TransferWrite => vk::AccessFlags::TRANSFER_WRITE,
+            BufferAccess::VertexInput => {
+                vk::AccessFlags::VERTEX_ATTRIBUTE_READ | vk::AccessFlags::INDEX_READ
+            }
+            BufferAccess::IndirectRead => vk::AccessFlags::INDIRECT_COMMAND_READ,
+            BufferAccess::ShaderRead => {
+                vk::AccessFlags::SHADER_READ | vk::AccessFlags::UNIFORM_READ
+            }
+            BufferAccess::ShaderWrite => vk::AccessFlags::SHADER_WRITE,
+        }
+    }
+
+    pub fn get_pipeline_stage(&self) -> vk::PipelineStageFlags {
+        match self {
+            // Whatever earlier submissions on the queue are still doing
+            BufferAccess::None => vk::PipelineStageFlags::ALL_COMMANDS,
+            BufferAccess::TransferRead => vk::PipelineStageFlags::TRANSFER,
+            BufferAccess::TransferWrite => vk::PipelineStageFlags::TRANSFER,
+            BufferAccess::VertexInput => vk::PipelineStageFlags::VERTEX_INPUT,
+            BufferAccess::IndirectRead => vk::PipelineStageFlags::DRAW_INDIRECT,
+            BufferAccess::ShaderRead | BufferAccess::ShaderWrite => {
+                vk::PipelineStageFlags::VERTEX_SHADER | vk::PipelineStageFlags::FRAGMENT_SHADER
+            }
+        }
+    }
+
+    pub fn is_write(&self) -> bool {
+        matches!(
+            self,
+            BufferAccess::TransferWrite | BufferAccess::ShaderWrite
+        )
+    }
 }
 
 pub struct Buffer {
     pub buffer: vk::Buffer,
     pub size: u64,
     bound_mem: Option<RawAllocation>,
+    /// Set along with `bound_mem`, returns it to its `GAllocator` on drop.
+    mem_free_sender: Option<Sender<RawAllocation>>,
+    /// Views made with `create_texel_view`, destroyed along with the buffer.
+    texel_views: Vec<vk::BufferView>,
     delete_sender: Sender<PainterDelete>,
 }
 
 impl Buffer {
     pub fn write_to_mem(&mut self, data: &[u8]) -> Result<(), BufferError> {
+        self.write_to_mem_at(0, data)
+    }
+
+    pub fn write_to_mem_at(&mut self, offset: usize, data: &[u8]) -> Result<(), BufferError> {
         let mapped_ptr = self
             .bound_mem
             .as_mut()
             .ok_or(BufferError::MemoryNotAllocatedError)?
             .mapped_slice_mut()
             .ok_or(BufferError::MemoryWriteError)?;
-        mapped_ptr[..data.len()].copy_from_slice(data);
+        mapped_ptr[offset..offset + data.len()].copy_from_slice(data);
         Ok(())
     }
+
+    pub fn read_from_mem(&self) -> Result<Vec<u8>, BufferError> {
+        let mapped_ptr = self
+            .bound_mem
+            .as_ref()
+            .ok_or(BufferError::MemoryNotAllocatedError)?
+            .mapped_slice()
+            .ok_or(BufferError::MemoryReadError)?;
+        Ok(mapped_ptr[..self.size as usize].to_vec())
+    }
+
+    /// Views the whole buffer as texels of `format`, for binding as a
+    /// `ShaderInputType::UniformTexelBuffer` or `StorageTexelBuffer`. The buffer needs the
+    /// matching `*_TEXEL_BUFFER` usage. The view lives as long as the buffer.
+    pub fn create_texel_view(
+        &mut self,
+        painter: &Painter,
+        format: vk::Format,
+    ) -> Result<vk::BufferView, BufferError> {
+        let buffer_view = unsafe {
+            painter
+                .device
+                .create_buffer_view(
+                    &vk::BufferViewCreateInfo::default()
+                        .buffer(self.buffer)
+                        .format(format)
+                        .range(vk::WHOLE_SIZE),
+                    None,
+                )
+                .map_err(BufferError::ViewCreateError)?
+        };
+        self.texel_views.push(buffer_view);
+        Ok(buffer_view)
+    }
 }
 
 impl Drop for Buffer {
     fn drop(&mut self) {
+        if let (Some(allocation), Some(mem_free_sender)) =
+            (self.bound_mem.take(), self.mem_free_sender.as_ref())
+        {
+            // Only fails once the allocator is gone, which frees all of its memory anyway
+            let _ = mem_free_sender.try_send(allocation);
+        }
+        for &buffer_view in &self.texel_views {
+            let _ = self
+                .delete_sender
+                .try_send(PainterDelete::BufferView(buffer_view))
+                .inspect_err(|e| {
+                    eprintln!("error sending drop signal for buffer view {buffer_view:?}: {e}")
+                });
+        }
         let _ = self
             .delete_sender
             .try_send(PainterDelete::Buffer(self.buffer))
@@ -75,24 +190,93 @@ impl Painter {
                 .map_err(BufferError::CreateError)?
         };
 
-        if let Some(mem_allocator) = mem_allocator {
-            let requirements = unsafe { self.device.get_buffer_memory_requirements(buffer) };
-            let gpu_local = !mem_host_visible.unwrap_or(false);
-            let allocation = mem_allocator
-                .allocate_mem(&format!("{:?}", buffer), requirements, gpu_local)
-                .map_err(BufferError::MemoryAllocationError)?;
-            unsafe {
-                self.device
-                    .bind_buffer_memory(buffer, allocation.memory(), allocation.offset())
-                    .map_err(BufferError::MemoryBindError)?;
+        let mem_free_sender = mem_allocator
+            .as_ref()
+            .map(|allocator| allocator.free_sender());
+        let bound_mem = match mem_allocator {
+            Some(mem_allocator) => {
+                let requirements = unsafe { self.device.get_buffer_memory_requirements(buffer) };
+                let gpu_local = !mem_host_visible.unwrap_or(false);
+                let allocation = mem_allocator
+                    .allocate_mem(&format!("{:?}", buffer), requirements, gpu_local)
+                    .map_err(BufferError::MemoryAllocationError)?;
+                unsafe {
+                    self.device
+                        .bind_buffer_memory(buffer, allocation.memory(), allocation.offset())
+                        .map_err(BufferError::MemoryBindError)?;
+                }
+                Some(allocation)
             }
-        }
+            None => None,
+        };
 
         Ok(Buffer {
             buffer,
             size,
-            bound_mem: None,
+            bound_mem,
+            mem_free_sender,
+            texel_views: vec![],
             delete_sender: self.delete_signal_sender.clone(),
         })
     }
+
+    /// Creates a buffer holding `draw_count` `vk::DrawIndexedIndirectCommand`s. It is also
+    /// usable as a storage buffer so a compute pass can write the draw parameters.
+    pub fn create_indirect_buffer(
+        &self,
+        draw_count: u32,
+        mem_allocator: Option<&mut GAllocator>,
+        mem_host_visible: Option<bool>,
+    ) -> Result<Buffer, BufferError> {
+        self.create_buffer(
+            draw_count as u64 * size_of::<vk::DrawIndexedIndirectCommand>() as u64,
+            vk::BufferUsageFlags::INDIRECT_BUFFER
+                | vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::TRANSFER_DST,
+            mem_allocator,
+            mem_host_visible,
+        )
+    }
+
+    /// Reads back a buffer the host can't map by copying it into a temporary host visible
+    /// buffer. Blocks until the copy is done. `buffer` needs `TRANSFER_SRC` usage.
+    pub fn read_buffer_via_staging(
+        &self,
+        buffer: &Buffer,
+        mem_allocator: &mut GAllocator,
+        command_buffer: &CommandBuffer,
+    ) -> Result<Vec<u8>, String> {
+        let staging_buffer = self
+            .create_buffer(
+                buffer.size,
+                vk::BufferUsageFlags::TRANSFER_DST,
+                Some(mem_allocator),
+                Some(true),
+            )
+            .map_err(|e| format!("at create read back buffer: {e}"))?;
+        self.record_cmd_buffer(
+            command_buffer,
+            &[GpuCommand::CopyBuffer {
+                src: buffer,
+                dst: &staging_buffer,
+                size: buffer.size,
+                src_offset: 0,
+                dst_offset: 0,
+            }],
+            true,
+        )
+        .map_err(|e| format!("at record read back commands: {e}"))?;
+
+        let fence = self
+            .create_cpu_future(false)
+            .map_err(|e| format!("at create read back fence: {e}"))?;
+        self.submit_cmd_buffer(command_buffer, vec![], vec![], vec![], Some(&fence))
+            .map_err(|e| format!("at submit read back commands: {e}"))?;
+        self.cpu_future_wait(&fence)
+            .map_err(|e| format!("at read back fence wait: {e}"))?;
+
+        staging_buffer
+            .read_from_mem()
+            .map_err(|e| format!("at read back buffer mem: {e}"))
+    }
 }
@@ -0,0 +1,215 @@
+use std::sync::Arc;
+
+use ash::vk;
+use thiserror::Error;
+
+use crate::{Buffer, GAllocator, Painter, buffer::BufferError};
+
+#[derive(Debug, Error)]
+pub enum BufferArenaError {
+    #[error("Error creating arena chunk buffer: {0}")]
+    ChunkCreateError(BufferError),
+    #[error("Region of {0} bytes doesn't fit in an arena chunk")]
+    RegionTooLarge(u64),
+    #[error("Error writing to arena region: {0}")]
+    WriteError(BufferError),
+}
+
+/// Range of one of the arena's chunks, bound with `BufferArena::buffer` and `offset`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferRegion {
+    pub chunk: usize,
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// Hands out ranges of a few large buffers so many small ones, like per mesh vertex and
+/// index data, don't each need their own buffer and allocation. A new chunk is only created
+/// when no free range of the existing ones is big enough.
+pub struct BufferArena {
+    painter: Arc<Painter>,
+    usage: vk::BufferUsageFlags,
+    host_visible: bool,
+    chunk_size: u64,
+    alignment: u64,
+    chunks: Vec<Buffer>,
+    /// Free ranges of every chunk as `(offset, size)`, sorted by offset and never adjacent.
+    free_ranges: Vec<Vec<(u64, u64)>>,
+}
+
+impl BufferArena {
+    /// Region offsets are multiples of `alignment`, which has to be a power of two, e.g. the
+    /// index size for index buffers.
+    pub fn new(
+        painter: Arc<Painter>,
+        usage: vk::BufferUsageFlags,
+        chunk_size: u64,
+        alignment: u64,
+        host_visible: bool,
+    ) -> Self {
+        assert!(
+            alignment.is_power_of_two(),
+            "buffer arena alignment {alignment} is not a power of two"
+        );
+        Self {
+            painter,
+            usage,
+            host_visible,
+            chunk_size,
+            alignment,
+            chunks: vec![],
+            free_ranges: vec![],
+        }
+    }
+
+    pub fn allocate(
+        &mut self,
+        allocator: &mut GAllocator,
+        size: u64,
+    ) -> Result<BufferRegion, BufferArenaError> {
+        if size > self.chunk_size {
+            return Err(BufferArenaError::RegionTooLarge(size));
+        }
+        for (chunk, free_ranges) in self.free_ranges.iter_mut().enumerate() {
+            if let Some(offset) = take_range(free_ranges, size, self.alignment) {
+                return Ok(BufferRegion {
+                    chunk,
+                    offset,
+                    size,
+                });
+            }
+        }
+
+        let buffer = self
+            .painter
+            .create_buffer(
+                self.chunk_size,
+                self.usage,
+                Some(allocator),
+                Some(self.host_visible),
+            )
+            .map_err(BufferArenaError::ChunkCreateError)?;
+        self.chunks.push(buffer);
+        let mut free_ranges = vec![(0, self.chunk_size)];
+        let offset = take_range(&mut free_ranges, size, self.alignment)
+            .ok_or(BufferArenaError::RegionTooLarge(size))?;
+        self.free_ranges.push(free_ranges);
+        Ok(BufferRegion {
+            chunk: self.chunks.len() - 1,
+            offset,
+            size,
+        })
+    }
+
+    /// The GPU must be done with the region, it can be handed out again right away.
+    pub fn free(&mut self, region: BufferRegion) {
+        release_range(
+            &mut self.free_ranges[region.chunk],
+            region.offset,
+            region.size,
+        );
+    }
+
+    pub fn buffer(&self, region: &BufferRegion) -> &Buffer {
+        &self.chunks[region.chunk]
+    }
+
+    /// Only for arenas created host visible.
+    pub fn write(&mut self, region: &BufferRegion, data: &[u8]) -> Result<(), BufferArenaError> {
+        if data.len() as u64 > region.size {
+            return Err(BufferArenaError::RegionTooLarge(data.len() as u64));
+        }
+        self.chunks[region.chunk]
+            .write_to_mem_at(region.offset as usize, data)
+            .map_err(BufferArenaError::WriteError)
+    }
+}
+
+/// First fit: carves an aligned `size` bytes out of the free ranges, keeping what's left of
+/// the range on either side.
+fn take_range(free_ranges: &mut Vec<(u64, u64)>, size: u64, alignment: u64) -> Option<u64> {
+    let idx = free_ranges.iter().position(|&(offset, free)| {
+        offset
+            .checked_next_multiple_of(alignment)
+            .and_then(|aligned_offset| aligned_offset.checked_add(size))
+            .is_some_and(|end| end <= offset + free)
+    })?;
+    let (offset, free) = free_ranges.remove(idx);
+    let aligned_offset = offset.next_multiple_of(alignment);
+    let end = aligned_offset + size;
+    if end < offset + free {
+        free_ranges.insert(idx, (end, offset + free - end));
+    }
+    if aligned_offset > offset {
+        free_ranges.insert(idx, (offset, aligned_offset - offset));
+    }
+    Some(aligned_offset)
+}
+
+/// Puts a range back, merged with the free ranges right before and after it.
+fn release_range(free_ranges: &mut Vec<(u64, u64)>, offset: u64, size: u64) {
+    let idx = free_ranges.partition_point(|&(free_offset, _)| free_offset < offset);
+    free_ranges.insert(idx, (offset, size));
+    // Merge with the next range, then with the previous one
+    if idx + 1 < free_ranges.len() {
+        let (offset, size) = free_ranges[idx];
+        if offset + size == free_ranges[idx + 1].0 {
+            free_ranges[idx].1 += free_ranges.remove(idx + 1).1;
+        }
+    }
+    if idx > 0 {
+        let (offset, size) = free_ranges[idx - 1];
+        if offset + size == free_ranges[idx].0 {
+            free_ranges[idx - 1].1 += free_ranges.remove(idx).1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{release_range, take_range};
+
+    #[test]
+    fn allocations_are_aligned() {
+        let mut free_ranges = vec![(0, 256)];
+        assert_eq!(take_range(&mut free_ranges, 10, 16), Some(0));
+        assert_eq!(take_range(&mut free_ranges, 10, 16), Some(16));
+        // The padding before the aligned offset stays free
+        assert_eq!(free_ranges, vec![(10, 6), (26, 230)]);
+        assert_eq!(take_range(&mut free_ranges, 4, 4), Some(12));
+    }
+
+    #[test]
+    fn exhausted_ranges_return_none() {
+        let mut free_ranges = vec![(0, 64)];
+        assert_eq!(take_range(&mut free_ranges, 48, 16), Some(0));
+        assert_eq!(take_range(&mut free_ranges, 32, 16), None);
+        assert_eq!(take_range(&mut free_ranges, 16, 16), Some(48));
+        assert_eq!(free_ranges, vec![]);
+        assert_eq!(take_range(&mut free_ranges, 1, 1), None);
+    }
+
+    #[test]
+    fn huge_sizes_do_not_overflow() {
+        let mut free_ranges = vec![(8, 64)];
+        assert_eq!(take_range(&mut free_ranges, u64::MAX, 16), None);
+        assert_eq!(take_range(&mut free_ranges, 16, 1 << 63), None);
+        assert_eq!(free_ranges, vec![(8, 64)]);
+    }
+
+    #[test]
+    fn freed_neighbours_merge() {
+        let mut free_ranges = vec![(0, 64)];
+        let a = take_range(&mut free_ranges, 16, 16).unwrap();
+        let b = take_range(&mut free_ranges, 16, 16).unwrap();
+        let c = take_range(&mut free_ranges, 16, 16).unwrap();
+        assert_eq!(free_ranges, vec![(48, 16)]);
+
+        release_range(&mut free_ranges, a, 16);
+        assert_eq!(free_ranges, vec![(0, 16), (48, 16)]);
+        release_range(&mut free_ranges, c, 16);
+        assert_eq!(free_ranges, vec![(0, 16), (32, 32)]);
+        release_range(&mut free_ranges, b, 16);
+        assert_eq!(free_ranges, vec![(0, 64)]);
+    }
+}
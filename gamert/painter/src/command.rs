@@ -1,19 +1,159 @@
-use ash::vk;
+use std::ffi::CString;
+
+use ash::{ext, vk};
 use crossbeam::channel::Sender;
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
 use thiserror::Error;
 
 use crate::{
-    Buffer, CpuFuture, GpuFuture, Image2d, ImageAccess, Painter, RenderOutput,
-    image::is_format_depth, painter::PainterDelete,
+    Buffer, BufferAccess, ComputePipeline, CpuFuture, GpuFuture, Image2d, ImageAccess, Painter,
+    RenderOutput, image::is_format_depth, painter::PainterDelete,
 };
 
-pub struct ImageTransitionInfo<'a> {
-    pub image: &'a Image2d,
+#[derive(Debug, Clone, Copy)]
+pub struct ImageTransitionInfo {
+    pub image: vk::Image,
+    pub format: vk::Format,
+    pub subresource_range: vk::ImageSubresourceRange,
+    /// Access the image is assumed to be in if it hasn't been seen yet in this recording.
+    /// `None` means its contents are undefined.
     pub old_access: Option<ImageAccess>,
     pub new_access: Option<ImageAccess>,
 }
 
+impl ImageTransitionInfo {
+    fn new(
+        image: &Image2d,
+        old_access: Option<ImageAccess>,
+        new_access: Option<ImageAccess>,
+    ) -> Self {
+        Self {
+            image: image.image,
+            format: image.format,
+            subresource_range: image.get_subresource_range(),
+            old_access,
+            new_access,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ImageBarrier {
+    pub image: vk::Image,
+    pub format: vk::Format,
+    pub subresource_range: vk::ImageSubresourceRange,
+    pub old_access: ImageAccess,
+    pub new_access: ImageAccess,
+}
+
+/// Works out the barriers needed before a command with `transitions` runs and updates
+/// `image_accesses` to the accesses it leaves the images in.
+pub(crate) fn plan_image_barriers(
+    image_accesses: &mut HashMap<vk::Image, ImageAccess>,
+    transitions: &[ImageTransitionInfo],
+) -> Vec<ImageBarrier> {
+    let mut barriers = vec![];
+    for transition in transitions {
+        let current_access = *image_accesses
+            .entry(transition.image)
+            .or_insert(transition.old_access.unwrap_or(ImageAccess::None));
+        let Some(new_access) = transition.new_access else {
+            continue;
+        };
+        if current_access == new_access {
+            continue;
+        }
+        image_accesses.insert(transition.image, new_access);
+        barriers.push(ImageBarrier {
+            image: transition.image,
+            format: transition.format,
+            subresource_range: transition.subresource_range,
+            old_access: current_access,
+            new_access,
+        });
+    }
+    barriers
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BufferTransitionInfo {
+    pub buffer: vk::Buffer,
+    /// Access the buffer is assumed to be in if it hasn't been seen yet in this recording.
+    pub old_access: Option<BufferAccess>,
+    pub new_access: BufferAccess,
+}
+
+impl BufferTransitionInfo {
+    fn new(buffer: &Buffer, old_access: Option<BufferAccess>, new_access: BufferAccess) -> Self {
+        Self {
+            buffer: buffer.buffer,
+            old_access,
+            new_access,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BufferBarrier {
+    pub buffer: vk::Buffer,
+    pub old_access: BufferAccess,
+    pub new_access: BufferAccess,
+}
+
+/// Buffer accesses tracked through a recording.
+#[derive(Debug, Default)]
+pub(crate) struct BufferAccesses {
+    /// Last known access of every buffer touched.
+    current: HashMap<vk::Buffer, BufferAccess>,
+    /// Buffers written at some point, made visible to everything after the recording.
+    written: HashSet<vk::Buffer>,
+}
+
+impl BufferAccesses {
+    fn clear(&mut self) {
+        self.current.clear();
+        self.written.clear();
+    }
+}
+
+/// `plan_image_barriers` for buffers. Every change of access and every access after a write
+/// gets a barrier, so barriers chain back to the last write. Only a read repeating the last
+/// read needs none. Reads of buffers first seen here need none either, their writes were made
+/// visible by the recording that did them or by the host write before submit.
+pub(crate) fn plan_buffer_barriers(
+    buffer_accesses: &mut BufferAccesses,
+    transitions: &[BufferTransitionInfo],
+) -> Vec<BufferBarrier> {
+    let mut barriers = vec![];
+    for transition in transitions {
+        let seen = buffer_accesses.current.contains_key(&transition.buffer);
+        let current_access = *buffer_accesses
+            .current
+            .entry(transition.buffer)
+            .or_insert(transition.old_access.unwrap_or(BufferAccess::None));
+        let new_access = transition.new_access;
+        if new_access.is_write() {
+            buffer_accesses.written.insert(transition.buffer);
+        }
+        // A hint declaring the access of a buffer first seen here isn't a use of it
+        if current_access == new_access && (!seen || !new_access.is_write()) {
+            continue;
+        }
+        buffer_accesses
+            .current
+            .insert(transition.buffer, new_access);
+        if current_access == BufferAccess::None && !new_access.is_write() {
+            continue;
+        }
+        barriers.push(BufferBarrier {
+            buffer: transition.buffer,
+            old_access: current_access,
+            new_access,
+        });
+    }
+    barriers
+}
+
 pub enum GpuRenderPassCommand<'a> {
     BindPipeline {
         pipeline: usize,
@@ -22,27 +162,143 @@ pub enum GpuRenderPassCommand<'a> {
         pipeline_layout: usize,
         descriptor_sets: Vec<vk::DescriptorSet>,
     },
+    /// Like `BindShaderInput`, with one byte offset per `*Dynamic` binding across the sets, in
+    /// set then binding order. Offsets have to be multiples of the device's
+    /// `min_*_buffer_offset_alignment`.
+    BindShaderInputDynamic {
+        pipeline_layout: usize,
+        descriptor_sets: Vec<vk::DescriptorSet>,
+        dynamic_offsets: Vec<u32>,
+    },
+    /// Buffers with the byte offset their vertex data starts at, e.g. a `BufferRegion` offset
+    /// or where instance data begins in a shared buffer.
     BindVertexBuffers {
-        buffers: Vec<&'a Buffer>,
+        buffers: Vec<(&'a Buffer, vk::DeviceSize)>,
     },
+    /// `offset` is in bytes and has to be a multiple of the index size.
     BindIndexBuffer {
         buffer: &'a Buffer,
+        offset: vk::DeviceSize,
+        index_type: vk::IndexType,
     },
     SetPushConstant {
         pipeline_layout: usize,
+        stage_flags: vk::ShaderStageFlags,
+        offset: u32,
         data: Vec<u8>,
     },
+    /// Indexed draw. `instance_count` is 1 and `first_instance` 0 for a plain draw, others
+    /// draw the geometry once per instance with `gl_InstanceIndex` counting from
+    /// `first_instance`.
     Draw {
         count: u32,
         vertex_offset: i32,
         index_offset: u32,
+        instance_count: u32,
+        first_instance: u32,
+    },
+    /// Non indexed draw, for geometry generated in the vertex shader.
+    DrawVertices {
+        count: u32,
+        first_vertex: u32,
+    },
+    DrawIndexedIndirect {
+        buffer: &'a Buffer,
+        offset: vk::DeviceSize,
+        draw_count: u32,
+        stride: u32,
     },
+    /// For pipelines built with `RenderPipelineBuilder::stencil_test`.
+    SetStencilReference {
+        value: u32,
+    },
+    /// Counts samples passing depth testing until the matching `EndQuery`.
+    BeginQuery {
+        query_pool: vk::QueryPool,
+        index: u32,
+    },
+    EndQuery {
+        query_pool: vk::QueryPool,
+        index: u32,
+    },
+    /// Must be the only kind of command in a render pass that uses it.
+    ExecuteSecondary {
+        buffers: Vec<&'a CommandBuffer>,
+    },
+    /// Opens a labelled group of commands in captures from tools like RenderDoc, closed by
+    /// the matching `PopDebugLabel` in the same render pass. No-op without debug utils.
+    PushDebugLabel {
+        name: String,
+        color: [f32; 4],
+    },
+    PopDebugLabel,
+}
+
+/// Records a label push, or a pop for `None`.
+unsafe fn record_debug_label(
+    debug_utils_device: Option<&ext::debug_utils::Device>,
+    command_buffer: vk::CommandBuffer,
+    label: Option<(&str, [f32; 4])>,
+) {
+    let Some(debug_utils_device) = debug_utils_device else {
+        return;
+    };
+    unsafe {
+        match label {
+            Some((name, color)) => {
+                let name = CString::new(name).unwrap_or_default();
+                debug_utils_device.cmd_begin_debug_utils_label(
+                    command_buffer,
+                    &vk::DebugUtilsLabelEXT::default()
+                        .label_name(&name)
+                        .color(color),
+                );
+            }
+            None => debug_utils_device.cmd_end_debug_utils_label(command_buffer),
+        }
+    }
 }
 
 impl<'a> GpuRenderPassCommand<'a> {
+    /// Buffers the command reads. Barriers can't go inside the render pass, so they are
+    /// inserted before it begins.
+    pub fn buffer_access_transitions(&self) -> Vec<BufferTransitionInfo> {
+        match self {
+            GpuRenderPassCommand::BindVertexBuffers { buffers } => buffers
+                .iter()
+                .map(|(buffer, _)| {
+                    BufferTransitionInfo::new(buffer, None, BufferAccess::VertexInput)
+                })
+                .collect(),
+            GpuRenderPassCommand::BindIndexBuffer {
+                buffer,
+                offset: _,
+                index_type: _,
+            } => vec![BufferTransitionInfo::new(
+                buffer,
+                None,
+                BufferAccess::VertexInput,
+            )],
+            GpuRenderPassCommand::DrawIndexedIndirect {
+                buffer,
+                offset: _,
+                draw_count: _,
+                stride: _,
+            } => vec![BufferTransitionInfo::new(
+                buffer,
+                None,
+                BufferAccess::IndirectRead,
+            )],
+            // Buffers used by secondaries or through shader inputs need a
+            // `GpuCommand::BufferAccessHint`
+            _ => vec![],
+        }
+    }
+
     pub fn apply_command(
         &self,
         device: &ash::Device,
+        debug_utils_device: Option<&ext::debug_utils::Device>,
         command_buffer: vk::CommandBuffer,
         pipelines: &[vk::Pipeline],
         pipeline_layouts: &[vk::PipelineLayout],
@@ -69,12 +325,25 @@ impl<'a> GpuRenderPassCommand<'a> {
                         &[],
                     );
                 }
+                GpuRenderPassCommand::BindShaderInputDynamic {
+                    pipeline_layout,
+                    descriptor_sets,
+                    dynamic_offsets,
+                } => {
+                    device.cmd_bind_descriptor_sets(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        pipeline_layouts[*pipeline_layout],
+                        0,
+                        descriptor_sets,
+                        dynamic_offsets,
+                    );
+                }
                 GpuRenderPassCommand::BindVertexBuffers { buffers } => {
-                    let buffers = buffers
+                    let (buffers, offsets): (Vec<_>, Vec<_>) = buffers
                         .iter()
-                        .map(|buffer| buffer.buffer)
-                        .collect::<Vec<_>>();
-                    let offsets = vec![0; buffers.len()];
+                        .map(|(buffer, offset)| (buffer.buffer, *offset))
+                        .unzip();
                     device.cmd_bind_vertex_buffers(
                         command_buffer,
                         0,
@@ -82,23 +351,29 @@ impl<'a> GpuRenderPassCommand<'a> {
                         &offsets,
                     );
                 }
-                GpuRenderPassCommand::BindIndexBuffer { buffer } => {
+                GpuRenderPassCommand::BindIndexBuffer {
+                    buffer,
+                    offset,
+                    index_type,
+                } => {
                     device.cmd_bind_index_buffer(
                         command_buffer,
                         buffer.buffer,
-                        0,
-                        vk::IndexType::UINT32,
+                        *offset,
+                        *index_type,
                     );
                 }
                 GpuRenderPassCommand::SetPushConstant {
                     pipeline_layout,
+                    stage_flags,
+                    offset,
                     data,
                 } => {
                     device.cmd_push_constants(
                         command_buffer,
                         pipeline_layouts[*pipeline_layout],
-                        vk::ShaderStageFlags::ALL,
-                        0,
+                        *stage_flags,
+                        *offset,
                         data,
                     );
                 }
@@ -106,34 +381,104 @@ impl<'a> GpuRenderPassCommand<'a> {
                     count,
                     vertex_offset,
                     index_offset,
+                    instance_count,
+                    first_instance,
                 } => {
                     device.cmd_draw_indexed(
                         command_buffer,
                         *count,
-                        1,
+                        *instance_count,
                         *index_offset,
                         *vertex_offset,
-                        0,
+                        *first_instance,
                     );
                 }
+                GpuRenderPassCommand::DrawVertices {
+                    count,
+                    first_vertex,
+                } => {
+                    device.cmd_draw(command_buffer, *count, 1, *first_vertex, 0);
+                }
+                GpuRenderPassCommand::DrawIndexedIndirect {
+                    buffer,
+                    offset,
+                    draw_count,
+                    stride,
+                } => {
+                    device.cmd_draw_indexed_indirect(
+                        command_buffer,
+                        buffer.buffer,
+                        *offset,
+                        *draw_count,
+                        *stride,
+                    );
+                }
+                GpuRenderPassCommand::SetStencilReference { value } => {
+                    device.cmd_set_stencil_reference(
+                        command_buffer,
+                        vk::StencilFaceFlags::FRONT_AND_BACK,
+                        *value,
+                    );
+                }
+                GpuRenderPassCommand::BeginQuery { query_pool, index } => {
+                    device.cmd_begin_query(
+                        command_buffer,
+                        *query_pool,
+                        *index,
+                        vk::QueryControlFlags::empty(),
+                    );
+                }
+                GpuRenderPassCommand::EndQuery { query_pool, index } => {
+                    device.cmd_end_query(command_buffer, *query_pool, *index);
+                }
+                GpuRenderPassCommand::ExecuteSecondary { buffers } => {
+                    let buffers = buffers
+                        .iter()
+                        .map(|buffer| buffer.command_buffer)
+                        .collect::<Vec<_>>();
+                    device.cmd_execute_commands(command_buffer, &buffers);
+                }
+                GpuRenderPassCommand::PushDebugLabel { name, color } => {
+                    record_debug_label(debug_utils_device, command_buffer, Some((name, *color)));
+                }
+                GpuRenderPassCommand::PopDebugLabel => {
+                    record_debug_label(debug_utils_device, command_buffer, None);
+                }
             }
         }
     }
 }
 
 pub enum GpuCommand<'a> {
+    /// Transitions the image from undefined contents to `access`, unless it was already
+    /// used earlier in the recording.
     ImageAccessInit {
         image: &'a Image2d,
         access: ImageAccess,
     },
+    /// Declares the image is in `access` if it is first seen here, otherwise transitions
+    /// it to `access`. Only needed to keep contents from before the recording, or to
+    /// leave the image in a specific access at the end.
     ImageAccessHint {
         image: &'a Image2d,
         access: ImageAccess,
     },
+    /// `ImageAccessHint` for buffers. Also declares uses the recording can't see, like
+    /// shader inputs or secondary command buffers, so later commands wait for them.
+    BufferAccessHint {
+        buffer: &'a Buffer,
+        access: BufferAccess,
+    },
     BlitFullImage {
         src: &'a Image2d,
         dst: &'a Image2d,
     },
+    /// Averages the samples of a multisampled `src` into the single sampled `dst`, for
+    /// resolving outside a render pass. Both need the same format and extent.
+    ResolveImage {
+        src: &'a Image2d,
+        dst: &'a Image2d,
+    },
     RunRenderPass {
         render_pass: vk::RenderPass,
         render_output: &'a RenderOutput,
@@ -142,52 +487,273 @@ pub enum GpuCommand<'a> {
         pipeline_layouts: Vec<vk::PipelineLayout>,
         commands: Vec<GpuRenderPassCommand<'a>>,
     },
+    /// Block compressed images take tightly packed blocks, with the partial blocks at the
+    /// right and bottom edges stored whole.
     CopyBufferToImageComplete {
         buffer: &'a Buffer,
+        buffer_offset: vk::DeviceSize,
+        image: &'a Image2d,
+    },
+    CopyBufferToImageArrayLayer {
+        buffer: &'a Buffer,
+        buffer_offset: vk::DeviceSize,
+        image: &'a Image2d,
+        layer: u32,
+    },
+    ClearColorImage {
         image: &'a Image2d,
+        color: vk::ClearColorValue,
+    },
+    /// Queries have to be reset before they are written again.
+    ResetQueryPool {
+        query_pool: vk::QueryPool,
+        count: u32,
+    },
+    WriteTimestamp {
+        query_pool: vk::QueryPool,
+        index: u32,
+        stage: vk::PipelineStageFlags,
+    },
+    CopyBuffer {
+        src: &'a Buffer,
+        dst: &'a Buffer,
+        size: vk::DeviceSize,
+        src_offset: vk::DeviceSize,
+        dst_offset: vk::DeviceSize,
+    },
+    ClearDepthImage {
+        image: &'a Image2d,
+        depth: f32,
+        stencil: u32,
+    },
+    /// Copies the whole image into `buffer` with tightly packed rows, made visible to the
+    /// host once the recording completes.
+    CopyImageToBuffer {
+        image: &'a Image2d,
+        buffer: &'a Buffer,
+        buffer_offset: vk::DeviceSize,
     },
+    /// Like `GpuRenderPassCommand::PushDebugLabel`, for groups of passes and transfers.
+    PushDebugLabel {
+        name: String,
+        color: [f32; 4],
+    },
+    PopDebugLabel,
+    /// Fills every mip level after the first from the one above it. Level 0 has to be written
+    /// already, every level is left in `TransferWrite` like a copy into level 0 leaves them.
+    DownsampleMipmaps {
+        downsample: MipDownsample<'a>,
+    },
+}
+
+/// Compute shader `GpuCommand::DownsampleMipmaps` writes each level of an image with, for
+/// formats that can't be blitted with linear filtering. It is dispatched in 8x8 workgroups,
+/// one invocation per texel of the level written.
+pub struct MipDownsample<'a> {
+    image: &'a Image2d,
+    pipeline: &'a ComputePipeline,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+}
+
+impl<'a> MipDownsample<'a> {
+    /// `descriptor_sets` are set 0 for every level of `image` after the first, binding the
+    /// level above as a sampled image at 0 and the level as a storage image at 1, see
+    /// `Image2d::level_views`.
+    pub fn new(
+        image: &'a Image2d,
+        pipeline: &'a ComputePipeline,
+        descriptor_sets: Vec<vk::DescriptorSet>,
+    ) -> Result<Self, String> {
+        let level_count = image.mip_levels.saturating_sub(1) as usize;
+        if descriptor_sets.len() != level_count {
+            return Err(format!(
+                "at mip downsample: {} descriptor sets for {level_count} levels",
+                descriptor_sets.len()
+            ));
+        }
+        Ok(Self {
+            image,
+            pipeline,
+            descriptor_sets,
+        })
+    }
+}
+
+/// Barrier on `levels` of `image`, for commands moving its levels through accesses of their
+/// own. The stages are up to the caller.
+fn level_barrier(
+    image: &Image2d,
+    levels: std::ops::Range<u32>,
+    old_access: ImageAccess,
+    new_access: ImageAccess,
+) -> vk::ImageMemoryBarrier<'static> {
+    let is_depth_image = is_format_depth(image.format);
+    vk::ImageMemoryBarrier::default()
+        .image(image.image)
+        .src_access_mask(old_access.to_access_flags(is_depth_image))
+        .dst_access_mask(new_access.to_access_flags(is_depth_image))
+        .old_layout(old_access.get_image_layout(is_depth_image))
+        .new_layout(new_access.get_image_layout(is_depth_image))
+        .subresource_range(
+            image
+                .get_level_subresource_range(levels.start)
+                .level_count(levels.len() as u32),
+        )
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
 }
 
 impl<'a> GpuCommand<'a> {
     pub fn access_transitions(&self) -> Vec<ImageTransitionInfo> {
         match self {
-            Self::ImageAccessInit { image, access } => vec![ImageTransitionInfo {
+            Self::ImageAccessInit { image, access } => vec![ImageTransitionInfo::new(
                 image,
-                old_access: Some(ImageAccess::None),
-                new_access: Some(*access),
-            }],
-            Self::ImageAccessHint { image, access } => vec![ImageTransitionInfo {
+                Some(ImageAccess::None),
+                Some(*access),
+            )],
+            Self::ImageAccessHint { image, access } => vec![ImageTransitionInfo::new(
                 image,
-                old_access: None,
-                new_access: Some(*access),
-            }],
-            Self::BlitFullImage { src, dst } => vec![
-                ImageTransitionInfo {
-                    image: src,
-                    old_access: None,
-                    new_access: Some(ImageAccess::TransferRead),
-                },
-                ImageTransitionInfo {
-                    image: dst,
-                    old_access: None,
-                    new_access: Some(ImageAccess::TransferWrite),
-                },
+                Some(*access),
+                Some(*access),
+            )],
+            Self::BufferAccessHint {
+                buffer: _,
+                access: _,
+            } => vec![],
+            Self::BlitFullImage { src, dst } | Self::ResolveImage { src, dst } => vec![
+                ImageTransitionInfo::new(src, None, Some(ImageAccess::TransferRead)),
+                ImageTransitionInfo::new(dst, None, Some(ImageAccess::TransferWrite)),
             ],
             Self::RunRenderPass {
                 render_pass: _,
-                render_output: _,
+                render_output,
                 clear_values: _,
                 pipelines: _,
                 pipeline_layouts: _,
                 commands: _,
-            } => vec![],
-            Self::CopyBufferToImageComplete { buffer: _, image } => vec![ImageTransitionInfo {
+            } => render_output
+                .attachments
+                .iter()
+                .map(|&(image, format, subresource_range)| ImageTransitionInfo {
+                    image,
+                    format,
+                    subresource_range,
+                    old_access: None,
+                    new_access: Some(ImageAccess::PipelineAttachment),
+                })
+                .collect(),
+            Self::CopyBufferToImageComplete {
+                buffer: _,
+                buffer_offset: _,
+                image,
+            } => vec![ImageTransitionInfo::new(
+                image,
+                None,
+                Some(ImageAccess::TransferWrite),
+            )],
+            Self::CopyBufferToImageArrayLayer {
+                buffer: _,
+                buffer_offset: _,
+                image,
+                layer: _,
+            } => vec![ImageTransitionInfo::new(
+                image,
+                None,
+                Some(ImageAccess::TransferWrite),
+            )],
+            Self::ClearColorImage { image, color: _ } => vec![ImageTransitionInfo::new(
+                image,
+                None,
+                Some(ImageAccess::TransferWrite),
+            )],
+            Self::ClearDepthImage {
                 image,
-                old_access: None,
-                new_access: Some(ImageAccess::TransferWrite),
-            }],
+                depth: _,
+                stencil: _,
+            } => vec![ImageTransitionInfo::new(
+                image,
+                None,
+                Some(ImageAccess::TransferWrite),
+            )],
+            Self::CopyImageToBuffer {
+                image,
+                buffer: _,
+                buffer_offset: _,
+            } => vec![ImageTransitionInfo::new(
+                image,
+                None,
+                Some(ImageAccess::TransferRead),
+            )],
+            Self::ResetQueryPool {
+                query_pool: _,
+                count: _,
+            } => vec![],
+            Self::WriteTimestamp {
+                query_pool: _,
+                index: _,
+                stage: _,
+            } => vec![],
+            Self::CopyBuffer {
+                src: _,
+                dst: _,
+                size: _,
+                src_offset: _,
+                dst_offset: _,
+            } => vec![],
+            Self::PushDebugLabel { name: _, color: _ } => vec![],
+            Self::PopDebugLabel => vec![],
+            Self::DownsampleMipmaps { downsample } => vec![ImageTransitionInfo::new(
+                downsample.image,
+                None,
+                Some(ImageAccess::TransferWrite),
+            )],
         }
     }
+
+    pub fn buffer_access_transitions(&self) -> Vec<BufferTransitionInfo> {
+        match self {
+            Self::BufferAccessHint { buffer, access } => {
+                vec![BufferTransitionInfo::new(buffer, Some(*access), *access)]
+            }
+            Self::RunRenderPass { commands, .. } => commands
+                .iter()
+                .flat_map(|rp_command| rp_command.buffer_access_transitions())
+                .collect(),
+            Self::CopyBufferToImageComplete { buffer, .. }
+            | Self::CopyBufferToImageArrayLayer { buffer, .. } => vec![BufferTransitionInfo::new(
+                buffer,
+                None,
+                BufferAccess::TransferRead,
+            )],
+            Self::CopyBuffer { src, dst, .. } => vec![
+                BufferTransitionInfo::new(src, None, BufferAccess::TransferRead),
+                BufferTransitionInfo::new(dst, None, BufferAccess::TransferWrite),
+            ],
+            Self::CopyImageToBuffer { buffer, .. } => vec![BufferTransitionInfo::new(
+                buffer,
+                None,
+                BufferAccess::TransferWrite,
+            )],
+            _ => vec![],
+        }
+    }
+
+    /// A render pass running secondary command buffers can't also have inline commands.
+    fn check_subpass_contents(&self) -> Result<(), String> {
+        let Self::RunRenderPass { commands, .. } = self else {
+            return Ok(());
+        };
+        let secondaries = commands
+            .iter()
+            .filter(|rp_command| {
+                matches!(rp_command, GpuRenderPassCommand::ExecuteSecondary { .. })
+            })
+            .count();
+        if secondaries != 0 && secondaries != commands.len() {
+            return Err("render pass mixes ExecuteSecondary with inline commands".to_string());
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Error)]
@@ -204,10 +770,9 @@ pub struct CommandBuffer {
     pub command_buffer: vk::CommandBuffer,
     command_pool: vk::CommandPool,
     queue: vk::Queue,
-}
-
-impl CommandBuffer {
-    
+    /// Last known access of every image touched since `begin_cmd_buffer`.
+    image_accesses: HashMap<vk::Image, ImageAccess>,
+    buffer_accesses: BufferAccesses,
 }
 
 #[derive(Debug, Error)]
@@ -216,6 +781,17 @@ pub enum CommandPoolError {
     CreateError(vk::Result),
     #[error("Error allocating Vulkan Command Buffers: {0}")]
     CommandBufferAllocationError(vk::Result),
+    #[error("Error resetting Vulkan command pool: {0}")]
+    ResetError(vk::Result),
+}
+
+/// Hint for how long the pool's buffers live between resets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandPoolKind {
+    /// Buffers kept and re-recorded over many frames.
+    Persistent,
+    /// Buffers recorded, submitted once and reset soon after, like upload buffers.
+    Transient,
 }
 
 pub struct CommandPool {
@@ -239,20 +815,56 @@ impl Drop for CommandPool {
 }
 
 impl Painter {
-    pub fn create_command_pool(&self) -> Result<CommandPool, CommandPoolError> {
+    pub fn create_command_pool(
+        &self,
+        kind: CommandPoolKind,
+    ) -> Result<CommandPool, CommandPoolError> {
+        self.create_command_pool_for_queue(
+            kind,
+            self.graphics_queue,
+            self.graphics_queue_family_index,
+        )
+    }
+
+    /// For buffers submitted to the present queue, e.g. the ownership transfers `Sheets` runs
+    /// when it is separate from the graphics queue.
+    pub fn create_present_command_pool(
+        &self,
+        kind: CommandPoolKind,
+    ) -> Result<CommandPool, CommandPoolError> {
+        self.create_command_pool_for_queue(
+            kind,
+            self.present_queue,
+            self.present_queue_family_index,
+        )
+    }
+
+    fn create_command_pool_for_queue(
+        &self,
+        kind: CommandPoolKind,
+        queue: vk::Queue,
+        queue_family_index: u32,
+    ) -> Result<CommandPool, CommandPoolError> {
+        let flags = match kind {
+            CommandPoolKind::Persistent => vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+            CommandPoolKind::Transient => {
+                vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER
+                    | vk::CommandPoolCreateFlags::TRANSIENT
+            }
+        };
         let command_pool = unsafe {
             self.device
                 .create_command_pool(
                     &vk::CommandPoolCreateInfo::default()
-                        .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
-                        .queue_family_index(self.graphics_queue_family_index),
+                        .flags(flags)
+                        .queue_family_index(queue_family_index),
                     None,
                 )
                 .map_err(CommandPoolError::CreateError)?
         };
         Ok(CommandPool {
             command_pool,
-            queue: self.graphics_queue,
+            queue,
             delete_sender: self.delete_signal_sender.clone(),
         })
     }
@@ -261,6 +873,30 @@ impl Painter {
         &self,
         command_pool: &CommandPool,
         count: usize,
+    ) -> Result<Vec<CommandBuffer>, CommandPoolError> {
+        self.allocate_command_buffers_of_level(command_pool, count, vk::CommandBufferLevel::PRIMARY)
+    }
+
+    /// Secondary buffers are recorded with `record_secondary_cmd_buffer` and run inside a
+    /// render pass via `GpuRenderPassCommand::ExecuteSecondary`. Command pools are not thread
+    /// safe, so each recording thread needs its own pool.
+    pub fn allocate_secondary_command_buffers(
+        &self,
+        command_pool: &CommandPool,
+        count: usize,
+    ) -> Result<Vec<CommandBuffer>, CommandPoolError> {
+        self.allocate_command_buffers_of_level(
+            command_pool,
+            count,
+            vk::CommandBufferLevel::SECONDARY,
+        )
+    }
+
+    fn allocate_command_buffers_of_level(
+        &self,
+        command_pool: &CommandPool,
+        count: usize,
+        level: vk::CommandBufferLevel,
     ) -> Result<Vec<CommandBuffer>, CommandPoolError> {
         unsafe {
             let command_buffers = self
@@ -268,7 +904,7 @@ impl Painter {
                 .allocate_command_buffers(
                     &vk::CommandBufferAllocateInfo::default()
                         .command_pool(command_pool.command_pool)
-                        .level(vk::CommandBufferLevel::PRIMARY)
+                        .level(level)
                         .command_buffer_count(count as u32),
                 )
                 .map_err(CommandPoolError::CommandBufferAllocationError)?
@@ -277,6 +913,8 @@ impl Painter {
                     command_buffer,
                     command_pool: command_pool.command_pool,
                     queue: command_pool.queue,
+                    image_accesses: HashMap::new(),
+                    buffer_accesses: BufferAccesses::default(),
                 })
                 .collect();
             Ok(command_buffers)
@@ -297,6 +935,29 @@ impl Painter {
         }
     }
 
+    /// Resets every buffer allocated from the pool at once, cheaper than resetting them one
+    /// by one. They can all be recorded again, so none of them may still be pending.
+    /// `release_resources` hands the pool's memory back to the driver instead of keeping it
+    /// for the next recordings.
+    pub fn reset_cmd_pool(
+        &self,
+        command_pool: &CommandPool,
+        release_resources: bool,
+    ) -> Result<(), CommandPoolError> {
+        let flags = if release_resources {
+            vk::CommandPoolResetFlags::RELEASE_RESOURCES
+        } else {
+            vk::CommandPoolResetFlags::empty()
+        };
+        unsafe {
+            self.device
+                .reset_command_pool(command_pool.command_pool, flags)
+                .map_err(CommandPoolError::ResetError)
+        }
+    }
+
+    /// Buffer writes still pending at the end are made visible to later submissions and the
+    /// host.
     pub fn record_cmd_buffer(
         &self,
         command_buffer: &CommandBuffer,
@@ -304,6 +965,113 @@ impl Painter {
         one_time: bool,
     ) -> Result<(), String> {
         let command_buffer = command_buffer.command_buffer;
+        let mut image_accesses = HashMap::new();
+        let mut buffer_accesses = BufferAccesses::default();
+        for command in commands {
+            command
+                .check_subpass_contents()
+                .map_err(|e| format!("at record command buffer: {e}"))?;
+        }
+        self.begin_vk_cmd_buffer(command_buffer, one_time)?;
+        for command in commands {
+            self.record_command(
+                command_buffer,
+                &mut image_accesses,
+                &mut buffer_accesses,
+                command,
+            );
+        }
+        self.record_pending_buffer_writes(command_buffer, &buffer_accesses);
+        self.end_vk_cmd_buffer(command_buffer)
+    }
+
+    /// Starts incremental recording. Commands are added with `append_cmd_buffer`, image and
+    /// buffer accesses are tracked across appends until `end_cmd_buffer`.
+    pub fn begin_cmd_buffer(
+        &self,
+        command_buffer: &mut CommandBuffer,
+        one_time: bool,
+    ) -> Result<(), String> {
+        command_buffer.image_accesses.clear();
+        command_buffer.buffer_accesses.clear();
+        self.begin_vk_cmd_buffer(command_buffer.command_buffer, one_time)
+    }
+
+    /// Nothing is recorded if any of `commands` is invalid.
+    pub fn append_cmd_buffer(
+        &self,
+        command_buffer: &mut CommandBuffer,
+        commands: &[GpuCommand],
+    ) -> Result<(), String> {
+        for command in commands {
+            command
+                .check_subpass_contents()
+                .map_err(|e| format!("at append command buffer: {e}"))?;
+        }
+        for command in commands {
+            self.record_command(
+                command_buffer.command_buffer,
+                &mut command_buffer.image_accesses,
+                &mut command_buffer.buffer_accesses,
+                command,
+            );
+        }
+        Ok(())
+    }
+
+    pub fn end_cmd_buffer(&self, command_buffer: &mut CommandBuffer) -> Result<(), String> {
+        self.record_pending_buffer_writes(
+            command_buffer.command_buffer,
+            &command_buffer.buffer_accesses,
+        );
+        command_buffer.image_accesses.clear();
+        command_buffer.buffer_accesses.clear();
+        self.end_vk_cmd_buffer(command_buffer.command_buffer)
+    }
+
+    /// Uses of buffers after the recording aren't known, so its writes are made visible to
+    /// everything after it. Barriers chain from the last access, which may be a read since.
+    fn record_pending_buffer_writes(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer_accesses: &BufferAccesses,
+    ) {
+        let mut src_stage = vk::PipelineStageFlags::empty();
+        let mut barriers = vec![];
+        for &buffer in &buffer_accesses.written {
+            let access = buffer_accesses.current[&buffer];
+            src_stage |= access.get_pipeline_stage();
+            barriers.push(
+                vk::BufferMemoryBarrier::default()
+                    .buffer(buffer)
+                    .size(vk::WHOLE_SIZE)
+                    .src_access_mask(access.to_access_flags())
+                    .dst_access_mask(vk::AccessFlags::MEMORY_READ | vk::AccessFlags::HOST_READ)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED),
+            );
+        }
+        if barriers.is_empty() {
+            return;
+        }
+        unsafe {
+            self.device.cmd_pipeline_barrier(
+                command_buffer,
+                src_stage,
+                vk::PipelineStageFlags::ALL_COMMANDS | vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[],
+                &barriers,
+                &[],
+            );
+        }
+    }
+
+    fn begin_vk_cmd_buffer(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        one_time: bool,
+    ) -> Result<(), String> {
         let begin_flags = if one_time {
             vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT
         } else {
@@ -313,152 +1081,454 @@ impl Painter {
         unsafe {
             self.device
                 .begin_command_buffer(command_buffer, &command_buffer_begin_info)
-                .map_err(|e| format!("at command buffer begin: {e}"))?;
-
-            let mut image_accesses = HashMap::new();
-
-            for (command_idx, command) in commands.iter().enumerate() {
-                for transition in command.access_transitions() {
-                    let (_, image_transitions) = image_accesses
-                        .entry(transition.image.image)
-                        .or_insert((transition.image, vec![]));
-                    if let Some(old_access) = transition.old_access {
-                        if image_transitions.len() == 0 {
-                            image_transitions.push((command_idx, old_access));
-                        }
-                    }
-                    if let Some(new_access) = transition.new_access {
-                        if let Some((_, last_access)) = image_transitions.last() {
-                            if *last_access != new_access {
-                                image_transitions.push((command_idx + 1, new_access));
-                            }
-                        } else {
-                            image_transitions.push((command_idx + 1, new_access));
-                        }
-                    }
-                }
-            }
+                .map_err(|e| format!("at command buffer begin: {e}"))
+        }
+    }
 
-            for (command_idx, command) in commands.iter().enumerate() {
-                for (_, (image, transitions_needed)) in image_accesses.iter() {
-                    let Some(access_idx) = transitions_needed
-                        .iter()
-                        .position(|(x, _)| *x == command_idx + 1)
-                    else {
-                        continue;
-                    };
-                    let access_new = transitions_needed[access_idx].1;
-                    if access_idx == 0 {
-                        continue;
-                    }
-                    let access_old = transitions_needed[access_idx - 1].1;
-                    if access_old == access_new {
-                        continue;
-                    }
-                    let is_depth_image = is_format_depth(image.format);
-                    // println!("image transition: {:?} {access_old:?} -> {access_new:?}", image.image);
+    fn end_vk_cmd_buffer(&self, command_buffer: vk::CommandBuffer) -> Result<(), String> {
+        unsafe {
+            self.device
+                .end_command_buffer(command_buffer)
+                .map_err(|e| format!("at command buffer end: {e}"))
+        }
+    }
+
+    /// Inserts the barriers `command` needs given the accesses seen so far, then records it.
+    fn record_command(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image_accesses: &mut HashMap<vk::Image, ImageAccess>,
+        buffer_accesses: &mut BufferAccesses,
+        command: &GpuCommand,
+    ) {
+        unsafe {
+            let buffer_barriers =
+                plan_buffer_barriers(buffer_accesses, &command.buffer_access_transitions());
+            if !buffer_barriers.is_empty() {
+                let mut src_stage = vk::PipelineStageFlags::empty();
+                let mut dst_stage = vk::PipelineStageFlags::empty();
+                let vk_buffer_barriers = buffer_barriers
+                    .iter()
+                    .map(|barrier| {
+                        src_stage |= barrier.old_access.get_pipeline_stage();
+                        dst_stage |= barrier.new_access.get_pipeline_stage();
+                        vk::BufferMemoryBarrier::default()
+                            .buffer(barrier.buffer)
+                            .size(vk::WHOLE_SIZE)
+                            .src_access_mask(barrier.old_access.to_access_flags())
+                            .dst_access_mask(barrier.new_access.to_access_flags())
+                            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    })
+                    .collect::<Vec<_>>();
+                self.device.cmd_pipeline_barrier(
+                    command_buffer,
+                    src_stage,
+                    dst_stage,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &vk_buffer_barriers,
+                    &[],
+                );
+            }
+            for barrier in plan_image_barriers(image_accesses, &command.access_transitions()) {
+                let is_depth_image = is_format_depth(barrier.format);
+                let (access_old, access_new) = (barrier.old_access, barrier.new_access);
+                // println!("image transition: {:?} {access_old:?} -> {access_new:?}", barrier.image);
+                // Discarding contents still has to wait for earlier work in the stage that writes
+                // next. It also chains with a semaphore wait in that stage, e.g. a swapchain
+                // image acquire waited on at COLOR_ATTACHMENT_OUTPUT before a render pass draws
+                // to it, which TOP_OF_PIPE would run ahead of
+                let src_stage = match access_old {
+                    ImageAccess::None => access_new.get_pipeline_stage(),
+                    _ => access_old.get_pipeline_stage(),
+                };
+                self.device.cmd_pipeline_barrier(
+                    command_buffer,
+                    src_stage,
+                    access_new.get_pipeline_stage(),
+                    vk::DependencyFlags::BY_REGION,
+                    &[],
+                    &[],
+                    &[vk::ImageMemoryBarrier::default()
+                        .image(barrier.image)
+                        .src_access_mask(access_old.to_access_flags(is_depth_image))
+                        .dst_access_mask(access_new.to_access_flags(is_depth_image))
+                        .old_layout(access_old.get_image_layout(is_depth_image))
+                        .new_layout(access_new.get_image_layout(is_depth_image))
+                        .subresource_range(barrier.subresource_range)],
+                );
+                // Swapchain images are handed to the present queue once ready, `Sheets`
+                // acquires them there. Images going back to graphics work are always
+                // discarded, so that direction needs no transfer
+                if access_new == ImageAccess::Present
+                    && access_old != ImageAccess::None
+                    && self.has_separate_present_queue()
+                {
                     self.device.cmd_pipeline_barrier(
                         command_buffer,
-                        access_old.get_pipeline_stage(),
-                        access_new.get_pipeline_stage(),
-                        vk::DependencyFlags::BY_REGION,
+                        vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                        vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                        vk::DependencyFlags::empty(),
                         &[],
                         &[],
                         &[vk::ImageMemoryBarrier::default()
-                            .image(image.image)
-                            .src_access_mask(access_old.to_access_flags(is_depth_image))
-                            .dst_access_mask(access_new.to_access_flags(is_depth_image))
-                            .old_layout(access_old.get_image_layout(is_depth_image))
-                            .new_layout(access_new.get_image_layout(is_depth_image))
-                            .subresource_range(image.get_subresource_range())],
+                            .image(barrier.image)
+                            .old_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+                            .new_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+                            .src_queue_family_index(self.graphics_queue_family_index)
+                            .dst_queue_family_index(self.present_queue_family_index)
+                            .subresource_range(barrier.subresource_range)],
                     );
                 }
-                match command {
-                    GpuCommand::ImageAccessInit {
-                        image: _,
-                        access: _,
-                    } => {}
-                    GpuCommand::ImageAccessHint {
-                        image: _,
-                        access: _,
-                    } => {}
-                    GpuCommand::BlitFullImage { src, dst } => {
-                        self.device.cmd_blit_image(
-                            command_buffer,
-                            src.image,
-                            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
-                            dst.image,
-                            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                            &[vk::ImageBlit::default()
-                                .src_subresource(src.get_subresource_layers())
-                                .dst_subresource(dst.get_subresource_layers())
-                                .src_offsets(src.get_full_size_offset())
-                                .dst_offsets(dst.get_full_size_offset())],
-                            vk::Filter::NEAREST,
-                        );
+            }
+            match command {
+                GpuCommand::ImageAccessInit {
+                    image: _,
+                    access: _,
+                } => {}
+                GpuCommand::ImageAccessHint {
+                    image: _,
+                    access: _,
+                } => {}
+                GpuCommand::BufferAccessHint {
+                    buffer: _,
+                    access: _,
+                } => {}
+                GpuCommand::BlitFullImage { src, dst } => {
+                    self.device.cmd_blit_image(
+                        command_buffer,
+                        src.image,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        dst.image,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        &[vk::ImageBlit::default()
+                            .src_subresource(src.get_subresource_layers())
+                            .dst_subresource(dst.get_subresource_layers())
+                            .src_offsets(src.get_full_size_offset())
+                            .dst_offsets(dst.get_full_size_offset())],
+                        vk::Filter::NEAREST,
+                    );
+                }
+                GpuCommand::ResolveImage { src, dst } => {
+                    self.device.cmd_resolve_image(
+                        command_buffer,
+                        src.image,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        dst.image,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        &[vk::ImageResolve::default()
+                            .src_subresource(src.get_subresource_layers())
+                            .dst_subresource(dst.get_subresource_layers())
+                            .extent(src.extent3d())],
+                    );
+                }
+                GpuCommand::RunRenderPass {
+                    render_pass,
+                    render_output,
+                    clear_values,
+                    pipelines,
+                    pipeline_layouts,
+                    commands: rp_commands,
+                } => {
+                    let uses_secondaries = rp_commands.iter().any(|rp_command| {
+                        matches!(rp_command, GpuRenderPassCommand::ExecuteSecondary { .. })
+                    });
+                    let subpass_contents = if uses_secondaries {
+                        vk::SubpassContents::SECONDARY_COMMAND_BUFFERS
+                    } else {
+                        vk::SubpassContents::INLINE
+                    };
+                    self.device.cmd_begin_render_pass(
+                        command_buffer,
+                        &vk::RenderPassBeginInfo::default()
+                            .render_pass(*render_pass)
+                            .framebuffer(render_output.framebuffer)
+                            .render_area(vk::Rect2D::default().extent(render_output.extent))
+                            .clear_values(clear_values),
+                        subpass_contents,
+                    );
+                    if !uses_secondaries {
+                        self.set_full_viewport(command_buffer, render_output);
                     }
-                    GpuCommand::RunRenderPass {
-                        render_pass,
-                        render_output,
-                        clear_values,
-                        pipelines,
-                        pipeline_layouts,
-                        commands: rp_commands,
-                    } => {
-                        self.device.cmd_begin_render_pass(
-                            command_buffer,
-                            &vk::RenderPassBeginInfo::default()
-                                .render_pass(*render_pass)
-                                .framebuffer(render_output.framebuffer)
-                                .render_area(vk::Rect2D::default().extent(render_output.extent))
-                                .clear_values(clear_values),
-                            vk::SubpassContents::INLINE,
-                        );
-                        self.device.cmd_set_viewport(
-                            command_buffer,
-                            0,
-                            &[vk::Viewport::default()
-                                .width(render_output.extent.width as f32)
-                                .height(render_output.extent.height as f32)],
-                        );
-                        self.device.cmd_set_scissor(
-                            command_buffer,
-                            0,
-                            &[vk::Rect2D::default().extent(render_output.extent)],
-                        );
-
-                        for rp_command in rp_commands.iter() {
-                            rp_command.apply_command(
-                                &self.device,
-                                command_buffer,
-                                pipelines,
-                                pipeline_layouts
-                            );
-                        }
 
-                        self.device.cmd_end_render_pass(command_buffer);
-                    }
-                    GpuCommand::CopyBufferToImageComplete { buffer, image } => {
-                        self.device.cmd_copy_buffer_to_image(
+                    for rp_command in rp_commands.iter() {
+                        rp_command.apply_command(
+                            &self.device,
+                            self.debug_utils_device.as_ref(),
                             command_buffer,
-                            buffer.buffer,
-                            image.image,
-                            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                            &[vk::BufferImageCopy::default()
-                                .buffer_offset(0)
-                                .buffer_row_length(0)
-                                .buffer_image_height(0)
-                                .image_subresource(image.get_subresource_layers())
-                                .image_offset(vk::Offset3D::default())
-                                .image_extent(image.extent3d())],
+                            pipelines,
+                            pipeline_layouts
                         );
                     }
+
+                    self.device.cmd_end_render_pass(command_buffer);
+                }
+                GpuCommand::CopyBufferToImageComplete {
+                    buffer,
+                    buffer_offset,
+                    image,
+                } => {
+                    self.device.cmd_copy_buffer_to_image(
+                        command_buffer,
+                        buffer.buffer,
+                        image.image,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        &[vk::BufferImageCopy::default()
+                            .buffer_offset(*buffer_offset)
+                            .buffer_row_length(0)
+                            .buffer_image_height(0)
+                            .image_subresource(image.get_subresource_layers())
+                            .image_offset(vk::Offset3D::default())
+                            .image_extent(image.extent3d())],
+                    );
+                }
+                GpuCommand::CopyBufferToImageArrayLayer {
+                    buffer,
+                    buffer_offset,
+                    image,
+                    layer,
+                } => {
+                    self.device.cmd_copy_buffer_to_image(
+                        command_buffer,
+                        buffer.buffer,
+                        image.image,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        &[vk::BufferImageCopy::default()
+                            .buffer_offset(*buffer_offset)
+                            .buffer_row_length(0)
+                            .buffer_image_height(0)
+                            .image_subresource(image.get_layer_subresource_layers(*layer))
+                            .image_offset(vk::Offset3D::default())
+                            .image_extent(image.extent3d())],
+                    );
+                }
+                GpuCommand::ClearColorImage { image, color } => {
+                    self.device.cmd_clear_color_image(
+                        command_buffer,
+                        image.image,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        color,
+                        &[image.get_subresource_range()],
+                    );
+                }
+                GpuCommand::ResetQueryPool { query_pool, count } => {
+                    self.device
+                        .cmd_reset_query_pool(command_buffer, *query_pool, 0, *count);
+                }
+                GpuCommand::WriteTimestamp {
+                    query_pool,
+                    index,
+                    stage,
+                } => {
+                    self.device
+                        .cmd_write_timestamp(command_buffer, *stage, *query_pool, *index);
+                }
+                GpuCommand::CopyBuffer {
+                    src,
+                    dst,
+                    size,
+                    src_offset,
+                    dst_offset,
+                } => {
+                    self.device.cmd_copy_buffer(
+                        command_buffer,
+                        src.buffer,
+                        dst.buffer,
+                        &[vk::BufferCopy::default()
+                            .src_offset(*src_offset)
+                            .dst_offset(*dst_offset)
+                            .size(*size)],
+                    );
+                }
+                GpuCommand::ClearDepthImage {
+                    image,
+                    depth,
+                    stencil,
+                } => {
+                    self.device.cmd_clear_depth_stencil_image(
+                        command_buffer,
+                        image.image,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        &vk::ClearDepthStencilValue {
+                            depth: *depth,
+                            stencil: *stencil,
+                        },
+                        &[image.get_subresource_range()],
+                    );
+                }
+                GpuCommand::CopyImageToBuffer {
+                    image,
+                    buffer,
+                    buffer_offset,
+                } => {
+                    self.device.cmd_copy_image_to_buffer(
+                        command_buffer,
+                        image.image,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        buffer.buffer,
+                        &[vk::BufferImageCopy::default()
+                            .buffer_offset(*buffer_offset)
+                            .buffer_row_length(0)
+                            .buffer_image_height(0)
+                            .image_subresource(image.get_subresource_layers())
+                            .image_offset(vk::Offset3D::default())
+                            .image_extent(image.extent3d())],
+                    );
+                }
+                GpuCommand::PushDebugLabel { name, color } => {
+                    record_debug_label(
+                        self.debug_utils_device.as_ref(),
+                        command_buffer,
+                        Some((name, *color)),
+                    );
+                }
+                GpuCommand::PopDebugLabel => {
+                    record_debug_label(self.debug_utils_device.as_ref(), command_buffer, None);
+                }
+                GpuCommand::DownsampleMipmaps { downsample } => {
+                    self.record_mip_downsample(command_buffer, downsample);
                 }
             }
+        }
+    }
 
+    /// Reads each level as a sampled image while the next is written as a storage image.
+    fn record_mip_downsample(&self, command_buffer: vk::CommandBuffer, downsample: &MipDownsample) {
+        let image = downsample.image;
+        if image.mip_levels < 2 {
+            return;
+        }
+        unsafe {
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                downsample.pipeline.pipeline,
+            );
+            for level in 1..image.mip_levels {
+                let written_access = if level == 1 {
+                    ImageAccess::TransferWrite
+                } else {
+                    ImageAccess::StorageWrite
+                };
+                self.device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[
+                        level_barrier(
+                            image,
+                            level - 1..level,
+                            written_access,
+                            ImageAccess::ShaderRead,
+                        ),
+                        // Nothing was written to the level yet
+                        level_barrier(
+                            image,
+                            level..level + 1,
+                            ImageAccess::None,
+                            ImageAccess::StorageWrite,
+                        ),
+                    ],
+                );
+                self.device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::COMPUTE,
+                    downsample.pipeline.pipeline_layout,
+                    0,
+                    &[downsample.descriptor_sets[level as usize - 1]],
+                    &[],
+                );
+                let extent = image.level_extent(level);
+                self.device.cmd_dispatch(
+                    command_buffer,
+                    extent.width.div_ceil(8),
+                    extent.height.div_ceil(8),
+                    1,
+                );
+            }
+            let last_level = image.mip_levels - 1;
+            self.device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[
+                    level_barrier(
+                        image,
+                        0..last_level,
+                        ImageAccess::ShaderRead,
+                        ImageAccess::TransferWrite,
+                    ),
+                    level_barrier(
+                        image,
+                        last_level..last_level + 1,
+                        ImageAccess::StorageWrite,
+                        ImageAccess::TransferWrite,
+                    ),
+                ],
+            );
+        }
+    }
+
+    fn set_full_viewport(&self, command_buffer: vk::CommandBuffer, render_output: &RenderOutput) {
+        unsafe {
+            self.device.cmd_set_viewport(
+                command_buffer,
+                0,
+                &[vk::Viewport::default()
+                    .width(render_output.extent.width as f32)
+                    .height(render_output.extent.height as f32)],
+            );
+            self.device.cmd_set_scissor(
+                command_buffer,
+                0,
+                &[vk::Rect2D::default().extent(render_output.extent)],
+            );
+        }
+    }
+
+    /// Records render pass commands into a secondary command buffer that continues the
+    /// first subpass of `render_output`'s render pass. Safe to call from worker threads as
+    /// long as each thread records into buffers from its own `CommandPool`.
+    pub fn record_secondary_cmd_buffer(
+        &self,
+        command_buffer: &CommandBuffer,
+        render_output: &RenderOutput,
+        pipelines: &[vk::Pipeline],
+        pipeline_layouts: &[vk::PipelineLayout],
+        commands: &[GpuRenderPassCommand],
+    ) -> Result<(), String> {
+        let command_buffer = command_buffer.command_buffer;
+        let inheritance_info = vk::CommandBufferInheritanceInfo::default()
+            .render_pass(render_output.render_pass)
+            .subpass(0)
+            .framebuffer(render_output.framebuffer);
+        let command_buffer_begin_info = vk::CommandBufferBeginInfo::default()
+            .flags(vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE)
+            .inheritance_info(&inheritance_info);
+        unsafe {
+            self.device
+                .begin_command_buffer(command_buffer, &command_buffer_begin_info)
+                .map_err(|e| format!("at secondary command buffer begin: {e}"))?;
+            self.set_full_viewport(command_buffer, render_output);
+            for command in commands {
+                command.apply_command(
+                    &self.device,
+                    self.debug_utils_device.as_ref(),
+                    command_buffer,
+                    pipelines,
+                    pipeline_layouts,
+                );
+            }
             self.device
                 .end_command_buffer(command_buffer)
-                .map_err(|e| format!("at command buffer end: {e}"))?;
+                .map_err(|e| format!("at secondary command buffer end: {e}"))?;
         }
         Ok(())
     }
@@ -471,6 +1541,36 @@ impl Painter {
         wait_stages: Vec<vk::PipelineStageFlags>,
         fence: Option<&CpuFuture>,
     ) -> Result<(), String> {
+        self.submit_cmd_buffers(
+            &[command_buffer],
+            signal_semaphores,
+            wait_semaphores,
+            wait_stages,
+            fence,
+        )
+    }
+
+    /// Submits the buffers in one batch, executing in order. The waits happen before the
+    /// first buffer and the signals after the last, so buffers in the batch need no
+    /// semaphores between them. All buffers must come from pools of the same queue.
+    pub fn submit_cmd_buffers(
+        &self,
+        command_buffers: &[&CommandBuffer],
+        signal_semaphores: Vec<&GpuFuture>,
+        wait_semaphores: Vec<&GpuFuture>,
+        wait_stages: Vec<vk::PipelineStageFlags>,
+        fence: Option<&CpuFuture>,
+    ) -> Result<(), String> {
+        let Some(queue) = command_buffers.first().map(|command_buffer| command_buffer.queue)
+        else {
+            return Err("at queue submit: no command buffers to submit".to_string());
+        };
+        if command_buffers
+            .iter()
+            .any(|command_buffer| command_buffer.queue != queue)
+        {
+            return Err("at queue submit: command buffers are for different queues".to_string());
+        }
         unsafe {
             let vk_fence = fence.map_or(vk::Fence::null(), |fence| fence.fence);
             let signal_semaphores = signal_semaphores
@@ -481,19 +1581,263 @@ impl Painter {
                 .iter()
                 .map(|semaphore| semaphore.semaphore)
                 .collect::<Vec<_>>();
+            let vk_command_buffers = command_buffers
+                .iter()
+                .map(|command_buffer| command_buffer.command_buffer)
+                .collect::<Vec<_>>();
             self
                 .device
                 .queue_submit(
-                    command_buffer.queue,
+                    queue,
                     &[vk::SubmitInfo::default()
                         .signal_semaphores(&signal_semaphores)
                         .wait_semaphores(&wait_semaphores)
                         .wait_dst_stage_mask(&wait_stages)
-                        .command_buffers(&[command_buffer.command_buffer])],
+                        .command_buffers(&vk_command_buffers)],
                     vk_fence,
                 )
                 .map_err(|e| format!("at queue submit: {e}"))?;
         }
         Ok(())
     }
+
+    /// Records `commands` into a fresh buffer from `command_pool`, submits it and waits for
+    /// it to finish before freeing the buffer. Meant for one-off setup and upload work, so
+    /// `command_pool` is best created as `CommandPoolKind::Transient`.
+    pub fn run_commands_blocking(
+        &self,
+        command_pool: &CommandPool,
+        commands: &[GpuCommand],
+    ) -> Result<(), String> {
+        let command_buffer = self
+            .allocate_command_buffers(command_pool, 1)
+            .map_err(|e| format!("at allocate command buffer: {e}"))?
+            .swap_remove(0);
+        let result = self.record_and_wait(&command_buffer, commands);
+        unsafe {
+            self.device
+                .free_command_buffers(command_pool.command_pool, &[command_buffer.command_buffer]);
+        }
+        result
+    }
+
+    fn record_and_wait(
+        &self,
+        command_buffer: &CommandBuffer,
+        commands: &[GpuCommand],
+    ) -> Result<(), String> {
+        self.record_cmd_buffer(command_buffer, commands, true)
+            .map_err(|e| format!("at record command buffer: {e}"))?;
+        let fence = self
+            .create_cpu_future(false)
+            .map_err(|e| format!("at create fence: {e}"))?;
+        self.submit_cmd_buffer(command_buffer, vec![], vec![], vec![], Some(&fence))
+            .map_err(|e| format!("at submit command buffer: {e}"))?;
+        self.cpu_future_wait(&fence)
+            .map_err(|e| format!("at fence wait: {e}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk::{self, Handle};
+    use hashbrown::HashMap;
+
+    use super::{
+        BufferAccesses, BufferTransitionInfo, GpuCommand, ImageTransitionInfo,
+        plan_buffer_barriers, plan_image_barriers,
+    };
+    use crate::{BufferAccess, Image2d, ImageAccess, image::test_image};
+
+    // What `RunRenderPass` reports for each of its attachments
+    fn render_pass_transition(image: &Image2d) -> ImageTransitionInfo {
+        ImageTransitionInfo::new(image, None, Some(ImageAccess::PipelineAttachment))
+    }
+
+    fn plan(
+        image_accesses: &mut HashMap<vk::Image, ImageAccess>,
+        transitions: &[ImageTransitionInfo],
+    ) -> Vec<(vk::Image, ImageAccess, ImageAccess)> {
+        plan_image_barriers(image_accesses, transitions)
+            .into_iter()
+            .map(|barrier| (barrier.image, barrier.old_access, barrier.new_access))
+            .collect()
+    }
+
+    #[test]
+    fn render_blit_present_barriers() {
+        let color = test_image(1, vk::Format::R8G8B8A8_UNORM);
+        let depth = test_image(2, vk::Format::D32_SFLOAT);
+        let sheet = test_image(3, vk::Format::B8G8R8A8_SRGB);
+        let mut image_accesses = HashMap::new();
+
+        let render = plan(
+            &mut image_accesses,
+            &[render_pass_transition(&color), render_pass_transition(&depth)],
+        );
+        assert_eq!(
+            render,
+            vec![
+                (color.image, ImageAccess::None, ImageAccess::PipelineAttachment),
+                (depth.image, ImageAccess::None, ImageAccess::PipelineAttachment),
+            ]
+        );
+
+        let blit = GpuCommand::BlitFullImage {
+            src: &color,
+            dst: &sheet,
+        };
+        assert_eq!(
+            plan(&mut image_accesses, &blit.access_transitions()),
+            vec![
+                (color.image, ImageAccess::PipelineAttachment, ImageAccess::TransferRead),
+                (sheet.image, ImageAccess::None, ImageAccess::TransferWrite),
+            ]
+        );
+
+        let present = GpuCommand::ImageAccessHint {
+            image: &sheet,
+            access: ImageAccess::Present,
+        };
+        assert_eq!(
+            plan(&mut image_accesses, &present.access_transitions()),
+            vec![(sheet.image, ImageAccess::TransferWrite, ImageAccess::Present)]
+        );
+    }
+
+    #[test]
+    fn hint_on_first_use_declares_current_access() {
+        let color = test_image(1, vk::Format::R8G8B8A8_UNORM);
+        let mut image_accesses = HashMap::new();
+
+        let hint = GpuCommand::ImageAccessHint {
+            image: &color,
+            access: ImageAccess::ShaderRead,
+        };
+        assert_eq!(plan(&mut image_accesses, &hint.access_transitions()), vec![]);
+        assert_eq!(
+            plan(&mut image_accesses, &[render_pass_transition(&color)]),
+            vec![(color.image, ImageAccess::ShaderRead, ImageAccess::PipelineAttachment)]
+        );
+    }
+
+    #[test]
+    fn repeated_access_needs_no_barrier() {
+        let color = test_image(1, vk::Format::R8G8B8A8_UNORM);
+        let mut image_accesses = HashMap::new();
+
+        plan(&mut image_accesses, &[render_pass_transition(&color)]);
+        assert_eq!(
+            plan(&mut image_accesses, &[render_pass_transition(&color)]),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn init_after_use_keeps_tracked_access() {
+        let color = test_image(1, vk::Format::R8G8B8A8_UNORM);
+        let mut image_accesses = HashMap::new();
+
+        plan(&mut image_accesses, &[render_pass_transition(&color)]);
+        let init = GpuCommand::ImageAccessInit {
+            image: &color,
+            access: ImageAccess::TransferRead,
+        };
+        assert_eq!(
+            plan(&mut image_accesses, &init.access_transitions()),
+            vec![(color.image, ImageAccess::PipelineAttachment, ImageAccess::TransferRead)]
+        );
+    }
+
+    fn buffer_transition(
+        raw: u64,
+        old_access: Option<BufferAccess>,
+        new_access: BufferAccess,
+    ) -> BufferTransitionInfo {
+        BufferTransitionInfo {
+            buffer: vk::Buffer::from_raw(raw),
+            old_access,
+            new_access,
+        }
+    }
+
+    fn plan_buffers(
+        buffer_accesses: &mut BufferAccesses,
+        transitions: &[BufferTransitionInfo],
+    ) -> Vec<(vk::Buffer, BufferAccess, BufferAccess)> {
+        plan_buffer_barriers(buffer_accesses, transitions)
+            .into_iter()
+            .map(|barrier| (barrier.buffer, barrier.old_access, barrier.new_access))
+            .collect()
+    }
+
+    #[test]
+    fn staged_upload_then_vertex_read_barriers() {
+        let staging = vk::Buffer::from_raw(1);
+        let vertices = vk::Buffer::from_raw(2);
+        let mut buffer_accesses = BufferAccesses::default();
+
+        // What `CopyBuffer` reports
+        let copy = plan_buffers(
+            &mut buffer_accesses,
+            &[
+                buffer_transition(1, None, BufferAccess::TransferRead),
+                buffer_transition(2, None, BufferAccess::TransferWrite),
+            ],
+        );
+        assert_eq!(
+            copy,
+            vec![(vertices, BufferAccess::None, BufferAccess::TransferWrite)]
+        );
+        assert_eq!(
+            plan_buffers(
+                &mut buffer_accesses,
+                &[buffer_transition(2, None, BufferAccess::VertexInput)]
+            ),
+            vec![(vertices, BufferAccess::TransferWrite, BufferAccess::VertexInput)]
+        );
+        assert!(buffer_accesses.written.contains(&vertices));
+        assert!(!buffer_accesses.written.contains(&staging));
+    }
+
+    #[test]
+    fn hint_on_first_use_declares_current_buffer_access() {
+        let object_data = vk::Buffer::from_raw(1);
+        let mut buffer_accesses = BufferAccesses::default();
+
+        let hint = buffer_transition(
+            1,
+            Some(BufferAccess::ShaderWrite),
+            BufferAccess::ShaderWrite,
+        );
+        assert_eq!(plan_buffers(&mut buffer_accesses, &[hint]), vec![]);
+        assert_eq!(
+            plan_buffers(
+                &mut buffer_accesses,
+                &[buffer_transition(1, None, BufferAccess::TransferRead)]
+            ),
+            vec![(object_data, BufferAccess::ShaderWrite, BufferAccess::TransferRead)]
+        );
+    }
+
+    #[test]
+    fn write_after_write_gets_a_barrier() {
+        let object_data = vk::Buffer::from_raw(1);
+        let mut buffer_accesses = BufferAccesses::default();
+
+        let write = buffer_transition(1, None, BufferAccess::ShaderWrite);
+        assert_eq!(
+            plan_buffers(&mut buffer_accesses, &[write]),
+            vec![(object_data, BufferAccess::None, BufferAccess::ShaderWrite)]
+        );
+        assert_eq!(
+            plan_buffers(&mut buffer_accesses, &[write]),
+            vec![(object_data, BufferAccess::ShaderWrite, BufferAccess::ShaderWrite)]
+        );
+
+        // Repeated reads don't
+        let read = buffer_transition(1, None, BufferAccess::ShaderRead);
+        assert_eq!(plan_buffers(&mut buffer_accesses, &[read]).len(), 1);
+        assert_eq!(plan_buffers(&mut buffer_accesses, &[read]), vec![]);
+    }
 }
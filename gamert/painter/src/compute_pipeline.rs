@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use ash::vk;
+
+use crate::{
+    Painter, ShaderInputAllocator, ShaderInputBindingInfo, ShaderInputLayout, ShaderModule,
+};
+
+/// A compute shader with the layouts of its shader inputs, dispatched by commands like
+/// `GpuCommand::DownsampleMipmaps`.
+pub struct ComputePipeline {
+    pub pipeline_layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+    pub shader_input_layouts: Vec<ShaderInputLayout>,
+    pub painter: Arc<Painter>,
+}
+
+impl ComputePipeline {
+    /// `input_layouts` are the bindings of each descriptor set, in set order.
+    pub fn new(
+        painter: Arc<Painter>,
+        shader_code: &[u8],
+        input_layouts: Vec<Vec<ShaderInputBindingInfo>>,
+        push_constant_ranges: Vec<vk::PushConstantRange>,
+    ) -> Result<Self, String> {
+        let shader_input_layouts = input_layouts
+            .into_iter()
+            .map(|input_layout| ShaderInputLayout::new(painter.clone(), input_layout))
+            .collect::<Result<Vec<_>, _>>()?;
+        let set_layouts = shader_input_layouts
+            .iter()
+            .map(|input_layout| input_layout.descriptor_set_layout)
+            .collect::<Vec<_>>();
+        let pipeline_layout = unsafe {
+            painter
+                .device
+                .create_pipeline_layout(
+                    &vk::PipelineLayoutCreateInfo::default()
+                        .set_layouts(&set_layouts)
+                        .push_constant_ranges(&push_constant_ranges),
+                    None,
+                )
+                .map_err(|e| format!("at pipeline layout creation: {e}"))?
+        };
+        let shader_module = ShaderModule::new(painter.clone(), shader_code)?;
+        let pipeline = unsafe {
+            painter
+                .device
+                .create_compute_pipelines(
+                    vk::PipelineCache::null(),
+                    &[vk::ComputePipelineCreateInfo::default()
+                        .stage(
+                            vk::PipelineShaderStageCreateInfo::default()
+                                .stage(vk::ShaderStageFlags::COMPUTE)
+                                .module(*shader_module.get_vk())
+                                .name(c"main"),
+                        )
+                        .layout(pipeline_layout)],
+                    None,
+                )
+                .map_err(|(_, e)| {
+                    painter
+                        .device
+                        .destroy_pipeline_layout(pipeline_layout, None);
+                    format!("at compute pipeline creation: {e}")
+                })?
+                .swap_remove(0)
+        };
+        Ok(Self {
+            pipeline_layout,
+            pipeline,
+            shader_input_layouts,
+            painter,
+        })
+    }
+
+    /// Allocates one set of every layout, in set order.
+    pub fn make_shader_inputs(
+        &self,
+        allocator: &ShaderInputAllocator,
+    ) -> Result<Vec<vk::DescriptorSet>, String> {
+        self.shader_input_layouts
+            .iter()
+            .map(|input_layout| allocator.allocate(input_layout, None))
+            .collect::<Result<Vec<_>, _>>()
+    }
+}
+
+impl Drop for ComputePipeline {
+    fn drop(&mut self) {
+        unsafe {
+            self.painter.device.destroy_pipeline(self.pipeline, None);
+            self.painter
+                .device
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use ash::vk;
+use hashbrown::HashMap;
+use thiserror::Error;
+
+use crate::{GpuCommand, Image2d, ImageAccess};
+
+#[derive(Debug, Error)]
+pub enum FrameGraphError {
+    #[error("Frame graph passes depend on each other in a cycle: {0:?}")]
+    Cycle(Vec<String>),
+}
+
+struct FramePass<'a> {
+    name: String,
+    reads: Vec<(&'a Image2d, ImageAccess)>,
+    writes: Vec<(&'a Image2d, ImageAccess)>,
+    commands: Vec<GpuCommand<'a>>,
+}
+
+/// Passes declared with the images they read and write, ordered and given image transitions
+/// by `build`. A pass reading an image runs after the last pass declared before it writing
+/// the image, or after every pass writing it when none was declared before. A pass writing an
+/// image runs after earlier declared passes writing the same image. Commands that transition their own images, like
+/// `RunRenderPass` or `BlitFullImage`, are left to do so.
+#[derive(Default)]
+pub struct FrameGraph<'a> {
+    passes: Vec<FramePass<'a>>,
+    outputs: Vec<(&'a Image2d, ImageAccess)>,
+}
+
+impl<'a> FrameGraph<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Images only written are assumed to be overwritten, their old contents are dropped the
+    /// first time the frame touches them. List an image in `reads` too to keep them.
+    pub fn add_pass(
+        &mut self,
+        name: &str,
+        reads: Vec<(&'a Image2d, ImageAccess)>,
+        writes: Vec<(&'a Image2d, ImageAccess)>,
+        commands: Vec<GpuCommand<'a>>,
+    ) {
+        self.passes.push(FramePass {
+            name: name.to_string(),
+            reads,
+            writes,
+            commands,
+        });
+    }
+
+    /// Leaves `image` in `access` once every pass has run, e.g. `Present` for a swapchain
+    /// image.
+    pub fn add_output(&mut self, image: &'a Image2d, access: ImageAccess) {
+        self.outputs.push((image, access));
+    }
+
+    fn pass_order(&self) -> Result<Vec<usize>, FrameGraphError> {
+        let mut writers: HashMap<vk::Image, Vec<usize>> = HashMap::new();
+        for (pass_idx, pass) in self.passes.iter().enumerate() {
+            for (image, _) in &pass.writes {
+                writers.entry(image.image).or_default().push(pass_idx);
+            }
+        }
+
+        let mut dependencies: Vec<Vec<usize>> = vec![vec![]; self.passes.len()];
+        for (pass_idx, pass) in self.passes.iter().enumerate() {
+            for (image, _) in &pass.reads {
+                let image_writers = writers.get(&image.image).map_or(&[][..], Vec::as_slice);
+                // The contents read are the last earlier write's. Without one, the pass reads
+                // what it finds if it writes the image itself, else whatever writes it later.
+                match image_writers.iter().rev().find(|&&w| w < pass_idx) {
+                    Some(&last_writer) => dependencies[pass_idx].push(last_writer),
+                    None if image_writers.contains(&pass_idx) => {}
+                    None => dependencies[pass_idx].extend(image_writers),
+                }
+            }
+            for (image, _) in &pass.writes {
+                let image_writers = writers.get(&image.image).into_iter().flatten();
+                dependencies[pass_idx].extend(image_writers.filter(|&&w| w < pass_idx));
+            }
+        }
+
+        // Repeatedly take the first declared pass whose dependencies all ran, so independent
+        // passes keep their declaration order
+        let mut done = vec![false; self.passes.len()];
+        let mut order = Vec::with_capacity(self.passes.len());
+        while order.len() < self.passes.len() {
+            let next = (0..self.passes.len()).find(|&pass_idx| {
+                !done[pass_idx] && dependencies[pass_idx].iter().all(|&dep| done[dep])
+            });
+            let Some(next) = next else {
+                let stuck = (0..self.passes.len())
+                    .filter(|&pass_idx| !done[pass_idx])
+                    .map(|pass_idx| self.passes[pass_idx].name.clone())
+                    .collect();
+                return Err(FrameGraphError::Cycle(stuck));
+            };
+            done[next] = true;
+            order.push(next);
+        }
+        Ok(order)
+    }
+
+    /// Flattens the graph into commands for `Painter::record_cmd_buffer`.
+    pub fn build(self) -> Result<Vec<GpuCommand<'a>>, FrameGraphError> {
+        let order = self.pass_order()?;
+        let mut passes = self.passes.into_iter().map(Some).collect::<Vec<_>>();
+        let mut commands = vec![];
+        for pass_idx in order {
+            let Some(pass) = passes[pass_idx].take() else {
+                continue;
+            };
+            for &(image, access) in &pass.reads {
+                commands.push(GpuCommand::ImageAccessHint { image, access });
+            }
+            for &(image, access) in &pass.writes {
+                let also_read = pass.reads.iter().any(|(read, _)| read.image == image.image);
+                if !also_read {
+                    commands.push(GpuCommand::ImageAccessInit { image, access });
+                }
+            }
+            commands.extend(pass.commands);
+        }
+        for (image, access) in self.outputs {
+            commands.push(GpuCommand::ImageAccessHint { image, access });
+        }
+        Ok(commands)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk;
+
+    use super::{FrameGraph, FrameGraphError};
+    use crate::{Image2d, ImageAccess, image};
+
+    fn test_image(raw: u64) -> Image2d {
+        image::test_image(raw, vk::Format::R8G8B8A8_UNORM)
+    }
+
+    #[test]
+    fn readers_run_after_writers() {
+        let hdr = test_image(1);
+        let sheet = test_image(2);
+        let shadow = test_image(3);
+        let mut frame_graph = FrameGraph::new();
+        frame_graph.add_pass(
+            "tone map",
+            vec![(&hdr, ImageAccess::ShaderRead)],
+            vec![(&sheet, ImageAccess::PipelineAttachment)],
+            vec![],
+        );
+        frame_graph.add_pass(
+            "scene",
+            vec![(&shadow, ImageAccess::ShaderRead)],
+            vec![(&hdr, ImageAccess::PipelineAttachment)],
+            vec![],
+        );
+        frame_graph.add_pass(
+            "shadow",
+            vec![],
+            vec![(&shadow, ImageAccess::PipelineAttachment)],
+            vec![],
+        );
+        frame_graph.add_pass("unrelated", vec![], vec![], vec![]);
+
+        assert_eq!(frame_graph.pass_order().unwrap(), vec![2, 1, 0, 3]);
+    }
+
+    #[test]
+    fn writers_of_the_same_image_keep_declaration_order() {
+        let color = test_image(1);
+        let mut frame_graph = FrameGraph::new();
+        frame_graph.add_pass(
+            "first",
+            vec![],
+            vec![(&color, ImageAccess::TransferWrite)],
+            vec![],
+        );
+        frame_graph.add_pass(
+            "second",
+            vec![],
+            vec![(&color, ImageAccess::TransferWrite)],
+            vec![],
+        );
+
+        assert_eq!(frame_graph.pass_order().unwrap(), vec![0, 1]);
+    }
+
+    #[test]
+    fn read_write_passes_on_the_same_image_keep_declaration_order() {
+        let color = test_image(1);
+        let mut frame_graph = FrameGraph::new();
+        for name in ["first", "second"] {
+            frame_graph.add_pass(
+                name,
+                vec![(&color, ImageAccess::ShaderRead)],
+                vec![(&color, ImageAccess::PipelineAttachment)],
+                vec![],
+            );
+        }
+        frame_graph.add_pass(
+            "reader",
+            vec![(&color, ImageAccess::ShaderRead)],
+            vec![],
+            vec![],
+        );
+
+        assert_eq!(frame_graph.pass_order().unwrap(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn cycle_is_an_error() {
+        let a = test_image(1);
+        let b = test_image(2);
+        let mut frame_graph = FrameGraph::new();
+        frame_graph.add_pass(
+            "a to b",
+            vec![(&a, ImageAccess::ShaderRead)],
+            vec![(&b, ImageAccess::PipelineAttachment)],
+            vec![],
+        );
+        frame_graph.add_pass(
+            "b to a",
+            vec![(&b, ImageAccess::ShaderRead)],
+            vec![(&a, ImageAccess::PipelineAttachment)],
+            vec![],
+        );
+
+        let Err(FrameGraphError::Cycle(stuck)) = frame_graph.pass_order() else {
+            panic!("expected a cycle");
+        };
+        assert_eq!(stuck, vec!["a to b".to_string(), "b to a".to_string()]);
+    }
+}
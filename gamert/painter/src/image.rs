@@ -47,6 +47,8 @@ pub enum ImageAccess {
     TransferWrite,
     ShaderRead,
     PipelineAttachment,
+    /// Written as a storage image by compute shaders.
+    StorageWrite,
     Present,
 }
 
@@ -65,6 +67,7 @@ impl ImageAccess {
                     vk::AccessFlags::COLOR_ATTACHMENT_WRITE
                 }
             }
+            ImageAccess::StorageWrite => vk::AccessFlags::SHADER_WRITE,
             ImageAccess::Present => vk::AccessFlags::MEMORY_READ,
         }
     }
@@ -82,6 +85,7 @@ impl ImageAccess {
                     vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::STORAGE
                 }
             }
+            ImageAccess::StorageWrite => vk::ImageUsageFlags::STORAGE,
             ImageAccess::Present => vk::ImageUsageFlags::empty(),
         }
     }
@@ -99,6 +103,7 @@ impl ImageAccess {
                     vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
                 }
             }
+            ImageAccess::StorageWrite => vk::ImageLayout::GENERAL,
             ImageAccess::Present => vk::ImageLayout::PRESENT_SRC_KHR,
         }
     }
@@ -110,6 +115,7 @@ impl ImageAccess {
             ImageAccess::TransferWrite => vk::PipelineStageFlags::TRANSFER,
             ImageAccess::ShaderRead => vk::PipelineStageFlags::FRAGMENT_SHADER,
             ImageAccess::PipelineAttachment => vk::PipelineStageFlags::ALL_GRAPHICS,
+            ImageAccess::StorageWrite => vk::PipelineStageFlags::COMPUTE_SHADER,
             ImageAccess::Present => vk::PipelineStageFlags::BOTTOM_OF_PIPE,
         }
     }
@@ -134,34 +140,76 @@ pub struct Image2d {
     pub image: vk::Image,
     pub format: vk::Format,
     pub extent: vk::Extent2D,
+    pub array_layers: u32,
+    /// 1 unless created with `Painter::create_image_2d_mipmapped`.
+    pub mip_levels: u32,
+    /// One view per mip level for mipmapped images, empty otherwise.
+    pub level_views: Vec<vk::ImageView>,
     pub(crate) bound_mem: Option<RawAllocation>,
+    /// Set along with `bound_mem`, returns it to its `GAllocator` on drop.
+    pub(crate) mem_free_sender: Option<Sender<RawAllocation>>,
     pub(crate) delete_sender: Option<Sender<PainterDelete>>,
 }
 
 impl Image2d {
-    pub(crate) fn make_subresource(format: vk::Format) -> vk::ImageSubresourceLayers {
+    pub(crate) fn make_subresource(
+        format: vk::Format,
+        array_layers: u32,
+    ) -> vk::ImageSubresourceLayers {
         vk::ImageSubresourceLayers::default()
             .aspect_mask(get_image_aspect(format))
             .mip_level(0)
             .base_array_layer(0)
-            .layer_count(1)
+            .layer_count(array_layers)
     }
 
+    /// Covers every mip level.
     pub fn get_subresource_range(&self) -> vk::ImageSubresourceRange {
-        Self::make_subresource_range(self.format)
+        Self::make_subresource_range(self.format, self.array_layers).level_count(self.mip_levels)
+    }
+
+    pub fn get_level_subresource_range(&self, level: u32) -> vk::ImageSubresourceRange {
+        Self::make_subresource_range(self.format, self.array_layers).base_mip_level(level)
+    }
+
+    /// Levels in a full chain down to 1x1.
+    pub fn mip_level_count(extent: vk::Extent2D) -> u32 {
+        u32::BITS - extent.width.max(extent.height).max(1).leading_zeros()
     }
 
-    pub(crate) fn make_subresource_range(format: vk::Format) -> vk::ImageSubresourceRange {
+    pub fn level_extent(&self, level: u32) -> vk::Extent2D {
+        vk::Extent2D {
+            width: (self.extent.width >> level).max(1),
+            height: (self.extent.height >> level).max(1),
+        }
+    }
+
+    pub(crate) fn make_subresource_range(
+        format: vk::Format,
+        array_layers: u32,
+    ) -> vk::ImageSubresourceRange {
         vk::ImageSubresourceRange::default()
             .aspect_mask(get_image_aspect(format))
             .base_mip_level(0)
             .level_count(1)
             .base_array_layer(0)
-            .layer_count(1)
+            .layer_count(array_layers)
     }
 
     pub fn get_subresource_layers(&self) -> vk::ImageSubresourceLayers {
-        Self::make_subresource(self.format)
+        Self::make_subresource(self.format, self.array_layers)
+    }
+
+    pub fn get_layer_subresource_layers(&self, layer: u32) -> vk::ImageSubresourceLayers {
+        Self::make_subresource(self.format, 1).base_array_layer(layer)
+    }
+
+    pub(crate) fn view_type(array_layers: u32) -> vk::ImageViewType {
+        if array_layers > 1 {
+            vk::ImageViewType::TYPE_2D_ARRAY
+        } else {
+            vk::ImageViewType::TYPE_2D
+        }
     }
 
     pub fn get_full_size_offset(&self) -> [vk::Offset3D; 2] {
@@ -196,7 +244,7 @@ impl Image2d {
                         .image(image)
                         .view_type(vk::ImageViewType::TYPE_2D)
                         .format(format)
-                        .subresource_range(Self::make_subresource_range(format)),
+                        .subresource_range(Self::make_subresource_range(format, 1)),
                     None,
                 )
                 .map_err(Image2dError::ViewCreateError)
@@ -206,9 +254,22 @@ impl Image2d {
 
 impl Drop for Image2d {
     fn drop(&mut self) {
+        if let (Some(allocation), Some(mem_free_sender)) =
+            (self.bound_mem.take(), self.mem_free_sender.as_ref())
+        {
+            // Only fails once the allocator is gone, which frees all of its memory anyway
+            let _ = mem_free_sender.try_send(allocation);
+        }
         let Some(delete_sender) = self.delete_sender.take() else {
             return;
         };
+        for &level_view in &self.level_views {
+            let _ = delete_sender
+                .try_send(PainterDelete::ImageView(level_view))
+                .inspect_err(|e| {
+                    eprintln!("error sending drop signal for image view {level_view:?}: {e}")
+                });
+        }
         let _ = delete_sender
             .try_send(PainterDelete::ImageView(self.image_view))
             .inspect_err(|e| {
@@ -225,6 +286,40 @@ impl Drop for Image2d {
     }
 }
 
+/// What `Painter::create_image` makes, 2D images and the cube images built from their layers.
+struct ImageDesc {
+    format: vk::Format,
+    extent: vk::Extent2D,
+    /// Every access the image is used with, which decides its usage flags.
+    accesses: Vec<ImageAccess>,
+    array_layers: u32,
+    mip_levels: u32,
+    create_flags: vk::ImageCreateFlags,
+    view_type: vk::ImageViewType,
+}
+
+/// A 4x4 image with a made up handle and nothing behind it, for tests planning transitions.
+#[cfg(test)]
+pub(crate) fn test_image(raw: u64, format: vk::Format) -> Image2d {
+    use ash::vk::Handle;
+
+    Image2d {
+        image_view: vk::ImageView::null(),
+        image: vk::Image::from_raw(raw),
+        format,
+        extent: vk::Extent2D {
+            width: 4,
+            height: 4,
+        },
+        array_layers: 1,
+        mip_levels: 1,
+        level_views: vec![],
+        bound_mem: None,
+        mem_free_sender: None,
+        delete_sender: None,
+    }
+}
+
 impl Painter {
     pub fn create_image_2d(
         &self,
@@ -233,23 +328,107 @@ impl Painter {
         image_usage_flags: Vec<ImageAccess>,
         mem_allocator: Option<&mut GAllocator>,
         mem_host_visible: Option<bool>,
+        array_layers: Option<u32>,
     ) -> Result<Image2d, Image2dError> {
+        let array_layers = array_layers.unwrap_or(1);
+        self.create_image(
+            ImageDesc {
+                format,
+                extent,
+                accesses: image_usage_flags,
+                array_layers,
+                mip_levels: 1,
+                create_flags: vk::ImageCreateFlags::empty(),
+                view_type: Image2d::view_type(array_layers),
+            },
+            mem_allocator,
+            mem_host_visible,
+        )
+    }
+
+    /// A single layer image with a full mip chain, filled from level 0 with
+    /// `GpuCommand::DownsampleMipmaps`, which needs `ShaderRead` and `StorageWrite` in
+    /// `image_usage_flags`.
+    pub fn create_image_2d_mipmapped(
+        &self,
+        format: vk::Format,
+        extent: vk::Extent2D,
+        image_usage_flags: Vec<ImageAccess>,
+        mem_allocator: Option<&mut GAllocator>,
+        mem_host_visible: Option<bool>,
+    ) -> Result<Image2d, Image2dError> {
+        self.create_image(
+            ImageDesc {
+                format,
+                extent,
+                accesses: image_usage_flags,
+                array_layers: 1,
+                mip_levels: Image2d::mip_level_count(extent),
+                create_flags: vk::ImageCreateFlags::empty(),
+                view_type: vk::ImageViewType::TYPE_2D,
+            },
+            mem_allocator,
+            mem_host_visible,
+        )
+    }
+
+    /// Creates a 6 layer cube compatible image. Faces are uploaded one layer at a time
+    /// in `+X, -X, +Y, -Y, +Z, -Z` order using `GpuCommand::CopyBufferToImageArrayLayer`.
+    pub fn create_image_cube(
+        &self,
+        format: vk::Format,
+        face_extent: vk::Extent2D,
+        image_usage_flags: Vec<ImageAccess>,
+        mem_allocator: Option<&mut GAllocator>,
+        mem_host_visible: Option<bool>,
+    ) -> Result<Image2d, Image2dError> {
+        self.create_image(
+            ImageDesc {
+                format,
+                extent: face_extent,
+                accesses: image_usage_flags,
+                array_layers: 6,
+                mip_levels: 1,
+                create_flags: vk::ImageCreateFlags::CUBE_COMPATIBLE,
+                view_type: vk::ImageViewType::CUBE,
+            },
+            mem_allocator,
+            mem_host_visible,
+        )
+    }
+
+    fn create_image(
+        &self,
+        desc: ImageDesc,
+        mut mem_allocator: Option<&mut GAllocator>,
+        mem_host_visible: Option<bool>,
+    ) -> Result<Image2d, Image2dError> {
+        let ImageDesc {
+            format,
+            extent,
+            accesses,
+            array_layers,
+            mip_levels,
+            create_flags,
+            view_type,
+        } = desc;
         let mut usage_flags = vk::ImageUsageFlags::empty();
-        for access in image_usage_flags {
+        for access in accesses {
             usage_flags |= access.to_usage_flags(is_format_depth(format));
         }
         let image = unsafe {
             self.device
                 .create_image(
                     &vk::ImageCreateInfo::default()
+                        .flags(create_flags)
                         .format(format)
                         .extent(vk::Extent3D {
                             width: extent.width,
                             height: extent.height,
                             depth: 1,
                         })
-                        .mip_levels(1)
-                        .array_layers(1)
+                        .mip_levels(mip_levels)
+                        .array_layers(array_layers)
                         .usage(usage_flags)
                         .image_type(vk::ImageType::TYPE_2D)
                         .samples(vk::SampleCountFlags::TYPE_1),
@@ -258,42 +437,99 @@ impl Painter {
                 .map_err(Image2dError::CreateError)?
         };
 
-        let image_view = unsafe {
-            self.device
-                .create_image_view(
-                    &vk::ImageViewCreateInfo::default()
-                        .image(image)
-                        .view_type(vk::ImageViewType::TYPE_2D)
-                        .format(format)
-                        .subresource_range(Image2d::make_subresource_range(format)),
-                    None,
-                )
-                .map_err(Image2dError::ViewCreateError)?
-        };
-
-        let bound_mem = match mem_allocator {
+        let mem_free_sender = mem_allocator
+            .as_ref()
+            .map(|allocator| allocator.free_sender());
+        // Views need the memory bound first
+        let bound_mem = match mem_allocator.as_deref_mut() {
             Some(mem_allocator) => {
                 let requirements = unsafe { self.device.get_image_memory_requirements(image) };
                 let gpu_local = !mem_host_visible.unwrap_or(false);
                 let allocation = mem_allocator
                     .allocate_mem(&format!("{:?}", image), requirements, gpu_local)
-                    .map_err(Image2dError::MemoryAllocationError)?;
-                unsafe {
+                    .map_err(|e| {
+                        unsafe { self.device.destroy_image(image, None) };
+                        Image2dError::MemoryAllocationError(e)
+                    })?;
+                let bound = unsafe {
                     self.device
                         .bind_image_memory(image, allocation.memory(), allocation.offset())
-                        .map_err(Image2dError::MemoryBindError)?;
+                };
+                if let Err(e) = bound {
+                    unsafe { self.device.destroy_image(image, None) };
+                    let _ = mem_allocator.allocator.free(allocation);
+                    return Err(Image2dError::MemoryBindError(e));
                 }
                 Some(allocation)
             }
             None => None,
         };
+
+        let (image_view, level_views) =
+            match self.create_image_views(image, format, array_layers, mip_levels, view_type) {
+                Ok(views) => views,
+                Err(e) => {
+                    unsafe { self.device.destroy_image(image, None) };
+                    if let (Some(mem_allocator), Some(allocation)) = (mem_allocator, bound_mem) {
+                        let _ = mem_allocator.allocator.free(allocation);
+                    }
+                    return Err(e);
+                }
+            };
         Ok(Image2d {
             image_view,
             image,
             format,
             extent,
+            array_layers,
+            mip_levels,
+            level_views,
             bound_mem,
+            mem_free_sender,
             delete_sender: Some(self.delete_signal_sender.clone()),
         })
     }
+
+    /// A view of every level, then one view per level when there are several. Views created
+    /// before a failure are destroyed.
+    fn create_image_views(
+        &self,
+        image: vk::Image,
+        format: vk::Format,
+        array_layers: u32,
+        mip_levels: u32,
+        view_type: vk::ImageViewType,
+    ) -> Result<(vk::ImageView, Vec<vk::ImageView>), Image2dError> {
+        let mut ranges =
+            vec![Image2d::make_subresource_range(format, array_layers).level_count(mip_levels)];
+        if mip_levels > 1 {
+            ranges.extend((0..mip_levels).map(|level| {
+                Image2d::make_subresource_range(format, array_layers).base_mip_level(level)
+            }));
+        }
+        let mut views = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            let view = unsafe {
+                self.device.create_image_view(
+                    &vk::ImageViewCreateInfo::default()
+                        .image(image)
+                        .view_type(view_type)
+                        .format(format)
+                        .subresource_range(range),
+                    None,
+                )
+            };
+            match view {
+                Ok(view) => views.push(view),
+                Err(e) => {
+                    for view in views {
+                        unsafe { self.device.destroy_image_view(view, None) };
+                    }
+                    return Err(Image2dError::ViewCreateError(e));
+                }
+            }
+        }
+        let image_view = views.remove(0);
+        Ok((image_view, views))
+    }
 }
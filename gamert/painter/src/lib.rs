@@ -9,24 +9,37 @@ pub use winit;
 
 mod allocator;
 mod buffer;
+mod buffer_arena;
 mod command;
+mod compute_pipeline;
+mod frame_graph;
 mod image;
 mod painter;
+mod query;
 mod render_pipeline;
 mod shader_input;
 mod sheets;
 mod sync;
 
-pub use allocator::GAllocator;
-pub use buffer::Buffer;
-pub use command::{CommandBuffer, CommandPool, GpuCommand, GpuRenderPassCommand};
-pub use image::{Image2d, ImageAccess};
-pub use painter::Painter;
-pub use render_pipeline::{RenderOutput, SingePassRenderPipeline};
+pub use allocator::{GAllocator, StagingHandle};
+pub use buffer::{Buffer, BufferAccess};
+pub use buffer_arena::{BufferArena, BufferArenaError, BufferRegion};
+pub use command::{
+    CommandBuffer, CommandPool, CommandPoolKind, GpuCommand, GpuRenderPassCommand, MipDownsample,
+};
+pub use compute_pipeline::ComputePipeline;
+pub use frame_graph::FrameGraph;
+pub use image::{Image2d, Image2dError, ImageAccess};
+pub use painter::{Painter, PainterConfig, PainterFeatures};
+pub use query::{OcclusionQueryPool, TimestampQueries};
+pub use render_pipeline::{
+    PipelineStateOverrides, RenderOutput, RenderPipelineBuilder, SingePassRenderPipeline,
+};
 pub use shader_input::{
     ShaderInputAllocator, ShaderInputBindingInfo, ShaderInputLayout, ShaderInputType,
+    ShaderInputValue,
 };
-pub use sheets::Sheets;
+pub use sheets::{AcquiredFrame, Sheets, SheetsError, SuboptimalPolicy, SurfaceFormatError};
 pub use sync::{CpuFuture, GpuFuture};
 
 pub struct ShaderModule {
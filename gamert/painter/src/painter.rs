@@ -3,7 +3,7 @@ use crossbeam::channel::{Receiver, Sender};
 use strum::{Display, EnumCount};
 use thiserror::Error;
 use winit::{
-    raw_window_handle::{HasDisplayHandle, HasWindowHandle},
+    raw_window_handle::{HasDisplayHandle, HasWindowHandle, RawDisplayHandle, RawWindowHandle},
     window::Window,
 };
 
@@ -14,17 +14,16 @@ static DEPTH_FORMAT_PREFERENCE_LIST: &[vk::Format] = &[
     vk::Format::D16_UNORM,
 ];
 
-pub fn get_instance_layers() -> Vec<*const i8> {
-    vec![
-        #[cfg(debug_assertions)]
-        c"VK_LAYER_KHRONOS_validation".as_ptr(),
-    ]
+pub fn get_instance_layers(config: &PainterConfig) -> Vec<*const i8> {
+    let mut layers = vec![];
+    if config.validation {
+        layers.push(c"VK_LAYER_KHRONOS_validation".as_ptr());
+    }
+    layers
 }
 
-pub fn get_instance_extensions() -> Vec<*const i8> {
-    vec![
-        #[cfg(debug_assertions)]
-        ext::debug_utils::NAME.as_ptr(),
+pub fn get_instance_extensions(config: &PainterConfig) -> Vec<*const i8> {
+    let mut extensions = vec![
         khr::get_physical_device_properties2::NAME.as_ptr(),
         khr::surface::NAME.as_ptr(),
         #[cfg(target_os = "windows")]
@@ -39,7 +38,14 @@ pub fn get_instance_extensions() -> Vec<*const i8> {
         ext::metal_surface::NAME.as_ptr(),
         #[cfg(target_os = "android")]
         khr::android_surface::NAME.as_ptr(),
-    ]
+    ];
+    if config.validation {
+        extensions.push(ext::debug_utils::NAME.as_ptr());
+    }
+    if config.validation && config.validation_features {
+        extensions.push(ext::validation_features::NAME.as_ptr());
+    }
+    extensions
 }
 
 pub fn get_device_extensions() -> Vec<*const i8> {
@@ -52,7 +58,10 @@ pub fn get_device_extensions() -> Vec<*const i8> {
     ]
 }
 
-pub fn create_instance(entry: &ash::Entry) -> Result<ash::Instance, PainterError> {
+pub fn create_instance(
+    entry: &ash::Entry,
+    config: &PainterConfig,
+) -> Result<ash::Instance, PainterError> {
     let app_info = vk::ApplicationInfo::default()
         .application_name(c"Residue VK App")
         .application_version(0)
@@ -60,22 +69,32 @@ pub fn create_instance(entry: &ash::Entry) -> Result<ash::Instance, PainterError
         .engine_version(0)
         .api_version(vk::API_VERSION_1_2);
 
-    let layers = get_instance_layers();
-    let extensions = get_instance_extensions();
+    let layers = get_instance_layers(config);
+    let extensions = get_instance_extensions(config);
 
     #[cfg(target_os = "macos")]
-    let vk_instance_create_info = vk::InstanceCreateInfo::default()
+    let mut vk_instance_create_info = vk::InstanceCreateInfo::default()
         .flags(vk::InstanceCreateFlags::ENUMERATE_PORTABILITY_KHR)
         .application_info(&app_info)
         .enabled_layer_names(&layers)
         .enabled_extension_names(&extensions);
 
     #[cfg(not(target_os = "macos"))]
-    let vk_instance_create_info = vk::InstanceCreateInfo::default()
+    let mut vk_instance_create_info = vk::InstanceCreateInfo::default()
         .application_info(&app_info)
         .enabled_layer_names(&layers)
         .enabled_extension_names(&extensions);
 
+    let enabled_validation_features = [
+        vk::ValidationFeatureEnableEXT::SYNCHRONIZATION_VALIDATION,
+        vk::ValidationFeatureEnableEXT::GPU_ASSISTED,
+    ];
+    let mut validation_features = vk::ValidationFeaturesEXT::default()
+        .enabled_validation_features(&enabled_validation_features);
+    if config.validation && config.validation_features {
+        vk_instance_create_info = vk_instance_create_info.push_next(&mut validation_features);
+    }
+
     unsafe {
         entry
             .create_instance(&vk_instance_create_info, None)
@@ -83,6 +102,37 @@ pub fn create_instance(entry: &ash::Entry) -> Result<ash::Instance, PainterError
     }
 }
 
+pub fn create_surface(
+    entry: &ash::Entry,
+    instance: &ash::Instance,
+    window: &Window,
+) -> Result<vk::SurfaceKHR, PainterError> {
+    create_surface_from_handles(
+        entry,
+        instance,
+        window
+            .display_handle()
+            .map_err(PainterError::GetRawDisplayHandleError)?
+            .as_raw(),
+        window
+            .window_handle()
+            .map_err(PainterError::GetRawWindowHandleError)?
+            .as_raw(),
+    )
+}
+
+pub fn create_surface_from_handles(
+    entry: &ash::Entry,
+    instance: &ash::Instance,
+    display_handle: RawDisplayHandle,
+    window_handle: RawWindowHandle,
+) -> Result<vk::SurfaceKHR, PainterError> {
+    unsafe {
+        ash_window::create_surface(entry, instance, display_handle, window_handle, None)
+            .map_err(PainterError::SurfaceCreationError)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, strum::EnumCount, Display)]
 #[repr(usize)]
 pub enum ImageFormatType {
@@ -112,87 +162,207 @@ pub enum PainterError {
     NoSuitableImageFormat(ImageFormatType),
     #[error("Error creating allocation error: {0}")]
     UnableToCreateAllocator(gpu_allocator::AllocationError),
+    #[error("Error waiting for device to be idle: {0}")]
+    WaitIdleError(vk::Result),
+}
+
+/// Optional device features worth checking before relying on them.
+/// These are what the device supports, `Painter` only enables `sampler_anisotropy` and
+/// `shader_storage_image_write_without_format` of them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PainterFeatures {
+    pub sampler_anisotropy: bool,
+    pub fill_mode_non_solid: bool,
+    pub wide_lines: bool,
+    pub multi_draw_indirect: bool,
+    pub timeline_semaphore: bool,
+    pub descriptor_binding_partially_bound: bool,
+    pub sampler_filter_minmax: bool,
+    /// Lets compute shaders write storage images declared without a format qualifier, like
+    /// the mip downsample does for any format.
+    pub shader_storage_image_write_without_format: bool,
+}
+
+/// Options picked when creating the `Painter`, see `Painter::new_with_config`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PainterConfig {
+    /// Enables the Khronos validation layer and the debug utils extension. Instance creation
+    /// fails when the layer isn't installed.
+    pub validation: bool,
+    /// Adds synchronization and GPU assisted validation on top of `validation`, for chasing
+    /// hazards in the barriers `record_cmd_buffer` inserts. Both slow rendering down a lot.
+    pub validation_features: bool,
+}
+
+impl Default for PainterConfig {
+    /// Validation in debug builds only.
+    fn default() -> Self {
+        Self {
+            validation: cfg!(debug_assertions),
+            validation_features: false,
+        }
+    }
 }
 
 pub enum PainterDelete {
     Buffer(vk::Buffer),
+    BufferView(vk::BufferView),
     Image(vk::Image),
     ImageView(vk::ImageView),
     CommandPool(vk::CommandPool),
     Semaphore(vk::Semaphore),
     Fence(vk::Fence),
+    QueryPool(vk::QueryPool),
 }
 
+/// Resources made through the painter, like `Image2d`, `Buffer`, `CommandPool` and the
+/// futures, queue their handles for destruction when dropped. The painter destroys whatever
+/// is queued when it is dropped, so every resource has to be dropped before the last
+/// `Arc<Painter>`, otherwise its handles outlive the device.
 pub struct Painter {
     pub delete_signal_sender: Sender<PainterDelete>,
     pub delete_signal_receiver: Receiver<PainterDelete>,
     pub image_formats: [vk::Format; ImageFormatType::COUNT],
     pub graphics_queue: vk::Queue,
     pub graphics_queue_family_index: u32,
+    /// The graphics queue when its family can present, which is preferred. Otherwise
+    /// swapchain images change queue family ownership on every present, see `Sheets`.
+    pub present_queue: vk::Queue,
+    pub present_queue_family_index: u32,
     pub device: ash::Device,
+    /// Set when `PainterConfig::validation` enables the debug utils extension. Used for the
+    /// debug labels that group commands in captures.
+    pub debug_utils_device: Option<ext::debug_utils::Device>,
     pub physical_device: vk::PhysicalDevice,
     pub surface: vk::SurfaceKHR,
     pub surface_instance: khr::surface::Instance,
     pub instance: ash::Instance,
     pub entry: ash::Entry,
-    pub window: Window,
+    /// `None` when created from raw handles, the caller owns the window then.
+    pub window: Option<Window>,
+    /// Size of the surface when the painter was created, or the size it was created with when
+    /// the surface leaves its size to the application. Used when there is no `window` to ask
+    /// and the surface can't tell.
+    pub initial_extent: vk::Extent2D,
 }
 
 impl Painter {
-    fn select_gpu_queue(
+    /// Graphics and present queue family indices. One family doing both is preferred, so
+    /// swapchain images need no ownership transfers.
+    fn select_gpu_queues(
         instance: &ash::Instance,
         surface_instance: &khr::surface::Instance,
         physical_device: vk::PhysicalDevice,
         surface: vk::SurfaceKHR,
-    ) -> Option<u32> {
+    ) -> Option<(u32, u32)> {
         let queue_families =
             unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
-        queue_families
+        let supports_present = |i: usize| unsafe {
+            surface_instance
+                .get_physical_device_surface_support(physical_device, i as u32, surface)
+                .unwrap_or(false)
+        };
+        let graphics_families = queue_families
             .iter()
             .enumerate()
-            .filter(|(i, queue_family)| {
-                let supports_graphics = queue_family.queue_flags.contains(vk::QueueFlags::GRAPHICS);
-                let supports_present = unsafe {
-                    surface_instance
-                        .get_physical_device_surface_support(physical_device, *i as u32, surface)
-                        .unwrap_or(false)
-                };
-                supports_graphics && supports_present
-            })
+            .filter(|(_, queue_family)| queue_family.queue_flags.contains(vk::QueueFlags::GRAPHICS))
+            .collect::<Vec<_>>();
+        let shared_family = graphics_families
+            .iter()
+            .filter(|(i, _)| supports_present(*i))
             .max_by_key(|(_, queue_family)| queue_family.queue_count)
-            .map(|(i, _)| i as u32)
+            .map(|(i, _)| *i as u32);
+        if let Some(shared_family) = shared_family {
+            return Some((shared_family, shared_family));
+        }
+        let graphics_family = graphics_families
+            .iter()
+            .max_by_key(|(_, queue_family)| queue_family.queue_count)
+            .map(|(i, _)| *i as u32)?;
+        let present_family = (0..queue_families.len()).find(|&i| supports_present(i))? as u32;
+        Some((graphics_family, present_family))
     }
 
+    /// `None` when the surface leaves its size to the application, like on Wayland.
+    fn surface_extent(
+        surface_instance: &khr::surface::Instance,
+        physical_device: vk::PhysicalDevice,
+        surface: vk::SurfaceKHR,
+    ) -> Option<vk::Extent2D> {
+        let surface_caps = unsafe {
+            surface_instance
+                .get_physical_device_surface_capabilities(physical_device, surface)
+                .ok()?
+        };
+        let extent = surface_caps.current_extent;
+        (extent.width != u32::MAX && extent.height != u32::MAX).then_some(extent)
+    }
+
+    /// Uses the default `PainterConfig`, with validation in debug builds only.
     pub fn new(window: Window) -> Result<Self, PainterError> {
+        Self::new_with_config(window, PainterConfig::default())
+    }
+
+    pub fn new_with_config(window: Window, config: PainterConfig) -> Result<Self, PainterError> {
+        let window_size = window.inner_size();
+        let mut painter = Self::new_from_handles_with_config(
+            window
+                .display_handle()
+                .map_err(PainterError::GetRawDisplayHandleError)?
+                .as_raw(),
+            window
+                .window_handle()
+                .map_err(PainterError::GetRawWindowHandleError)?
+                .as_raw(),
+            vk::Extent2D {
+                width: window_size.width,
+                height: window_size.height,
+            },
+            config,
+        )?;
+        painter.window = Some(window);
+        Ok(painter)
+    }
+
+    /// For windows owned by the caller, possibly from another windowing library. The window
+    /// must outlive the painter. `initial_extent` is only used when the surface doesn't report
+    /// its size.
+    pub fn new_from_handles(
+        display_handle: RawDisplayHandle,
+        window_handle: RawWindowHandle,
+        initial_extent: vk::Extent2D,
+    ) -> Result<Self, PainterError> {
+        Self::new_from_handles_with_config(
+            display_handle,
+            window_handle,
+            initial_extent,
+            PainterConfig::default(),
+        )
+    }
+
+    pub fn new_from_handles_with_config(
+        display_handle: RawDisplayHandle,
+        window_handle: RawWindowHandle,
+        initial_extent: vk::Extent2D,
+        config: PainterConfig,
+    ) -> Result<Self, PainterError> {
         unsafe {
             let entry = ash::Entry::load().map_err(PainterError::VkLoadError)?;
 
-            let instance = create_instance(&entry)?;
+            let instance = create_instance(&entry, &config)?;
 
             let surface_instance = khr::surface::Instance::new(&entry, &instance);
 
-            let surface = ash_window::create_surface(
-                &entry,
-                &instance,
-                window
-                    .display_handle()
-                    .map_err(PainterError::GetRawDisplayHandleError)?
-                    .as_raw(),
-                window
-                    .window_handle()
-                    .map_err(PainterError::GetRawWindowHandleError)?
-                    .as_raw(),
-                None,
-            )
-            .map_err(PainterError::SurfaceCreationError)?;
+            let surface =
+                create_surface_from_handles(&entry, &instance, display_handle, window_handle)?;
 
             let mut physical_devices = instance
                 .enumerate_physical_devices()
                 .map_err(PainterError::GetGpusError)?
                 .iter()
                 .filter_map(|&physical_device| {
-                    Self::select_gpu_queue(&instance, &surface_instance, physical_device, surface)
-                        .map(|queue_family_index| (physical_device, queue_family_index))
+                    Self::select_gpu_queues(&instance, &surface_instance, physical_device, surface)
+                        .map(|queue_family_indices| (physical_device, queue_family_indices))
                 })
                 .collect::<Vec<_>>();
 
@@ -204,18 +374,25 @@ impl Painter {
                 if is_dedicated { 2 } else { 1 }
             });
 
-            let (physical_device, graphics_queue_family_index) =
+            let (physical_device, (graphics_queue_family_index, present_queue_family_index)) =
                 physical_devices
                     .last()
                     .cloned()
                     .ok_or(PainterError::NoSupportedGpu)?;
 
             let queue_priorities = [1.0];
-            let queue_infos = vec![
+            let mut queue_infos = vec![
                 vk::DeviceQueueCreateInfo::default()
                     .queue_family_index(graphics_queue_family_index)
                     .queue_priorities(&queue_priorities),
             ];
+            if present_queue_family_index != graphics_queue_family_index {
+                queue_infos.push(
+                    vk::DeviceQueueCreateInfo::default()
+                        .queue_family_index(present_queue_family_index)
+                        .queue_priorities(&queue_priorities),
+                );
+            }
 
             let device_extensions = get_device_extensions();
 
@@ -227,7 +404,13 @@ impl Painter {
                 .descriptor_binding_variable_descriptor_count(true);
             let mut dynamic_rendering_switch =
                 vk::PhysicalDeviceDynamicRenderingFeatures::default().dynamic_rendering(true);
-            let device_features = vk::PhysicalDeviceFeatures::default();
+            // Enabled whenever supported, so `supported_features` also tells what's enabled
+            let supported_features = instance.get_physical_device_features(physical_device);
+            let device_features = vk::PhysicalDeviceFeatures::default()
+                .sampler_anisotropy(supported_features.sampler_anisotropy == vk::TRUE)
+                .shader_storage_image_write_without_format(
+                    supported_features.shader_storage_image_write_without_format == vk::TRUE,
+                );
 
             let device_create_info = vk::DeviceCreateInfo::default()
                 .queue_create_infos(&queue_infos)
@@ -241,6 +424,11 @@ impl Painter {
                 .map_err(PainterError::LogicalDeviceCreateError)?;
 
             let graphics_queue = device.get_device_queue(graphics_queue_family_index, 0);
+            let present_queue = device.get_device_queue(present_queue_family_index, 0);
+
+            let debug_utils_device = config
+                .validation
+                .then(|| ext::debug_utils::Device::new(&instance, &device));
 
             let rgba8_format = vk::Format::R8G8B8A8_UNORM;
             let depth_format = DEPTH_FORMAT_PREFERENCE_LIST
@@ -265,6 +453,9 @@ impl Painter {
             image_formats[ImageFormatType::Rgba8Unorm as usize] = rgba8_format;
             image_formats[ImageFormatType::DepthStencilOptimal as usize] = depth_format;
 
+            let initial_extent = Self::surface_extent(&surface_instance, physical_device, surface)
+                .unwrap_or(initial_extent);
+
             let (s, r) = crossbeam::channel::unbounded();
 
             Ok(Self {
@@ -272,10 +463,14 @@ impl Painter {
                 entry,
                 surface_instance,
                 surface,
-                window,
+                window: None,
+                initial_extent,
                 device,
+                debug_utils_device,
                 graphics_queue,
                 graphics_queue_family_index,
+                present_queue,
+                present_queue_family_index,
                 physical_device,
                 image_formats,
                 delete_signal_sender: s,
@@ -284,6 +479,111 @@ impl Painter {
         }
     }
 
+    /// True on integrated GPUs where some device local memory can also be mapped by the host,
+    /// so uploads can skip the staging copy. Discrete GPUs can expose such memory too, through
+    /// a small BAR heap or resizable BAR, but host access to it crosses the bus.
+    pub fn has_host_visible_device_local_memory(&self) -> bool {
+        let device_type = unsafe {
+            self.instance
+                .get_physical_device_properties(self.physical_device)
+                .device_type
+        };
+        if device_type != vk::PhysicalDeviceType::INTEGRATED_GPU {
+            return false;
+        }
+        let memory_properties = unsafe {
+            self.instance
+                .get_physical_device_memory_properties(self.physical_device)
+        };
+        memory_properties.memory_types_as_slice().iter().any(|memory_type| {
+            memory_type.property_flags.contains(
+                vk::MemoryPropertyFlags::DEVICE_LOCAL | vk::MemoryPropertyFlags::HOST_VISIBLE,
+            )
+        })
+    }
+
+    /// Whether presenting goes through a queue of another family than graphics work.
+    pub fn has_separate_present_queue(&self) -> bool {
+        self.present_queue_family_index != self.graphics_queue_family_index
+    }
+
+    /// Current size of `window`, or of the surface without one. Falls back to
+    /// `initial_extent` when neither can tell.
+    pub fn window_extent(&self) -> vk::Extent2D {
+        match &self.window {
+            Some(window) => {
+                let window_size = window.inner_size();
+                vk::Extent2D {
+                    width: window_size.width,
+                    height: window_size.height,
+                }
+            }
+            None => {
+                Self::surface_extent(&self.surface_instance, self.physical_device, self.surface)
+                    .unwrap_or(self.initial_extent)
+            }
+        }
+    }
+
+    /// Width and height of `window_extent`.
+    pub fn inner_size(&self) -> (u32, u32) {
+        let extent = self.window_extent();
+        (extent.width, extent.height)
+    }
+
+    /// Does nothing for caller owned windows, see `new_from_handles`.
+    pub fn set_title(&self, title: &str) {
+        if let Some(window) = &self.window {
+            window.set_title(title);
+        }
+    }
+
+    /// Queues a `RedrawRequested` event for the window. Does nothing for caller owned windows.
+    pub fn request_redraw(&self) {
+        if let Some(window) = &self.window {
+            window.request_redraw();
+        }
+    }
+
+    pub fn device_limits(&self) -> vk::PhysicalDeviceLimits {
+        unsafe {
+            self.instance
+                .get_physical_device_properties(self.physical_device)
+                .limits
+        }
+    }
+
+    pub fn supported_features(&self) -> PainterFeatures {
+        let mut features_12 = vk::PhysicalDeviceVulkan12Features::default();
+        let mut features = vk::PhysicalDeviceFeatures2::default().push_next(&mut features_12);
+        unsafe {
+            self.instance
+                .get_physical_device_features2(self.physical_device, &mut features);
+        }
+        let core = features.features;
+        PainterFeatures {
+            sampler_anisotropy: core.sampler_anisotropy == vk::TRUE,
+            fill_mode_non_solid: core.fill_mode_non_solid == vk::TRUE,
+            wide_lines: core.wide_lines == vk::TRUE,
+            multi_draw_indirect: core.multi_draw_indirect == vk::TRUE,
+            timeline_semaphore: features_12.timeline_semaphore == vk::TRUE,
+            descriptor_binding_partially_bound: features_12.descriptor_binding_partially_bound
+                == vk::TRUE,
+            sampler_filter_minmax: features_12.sampler_filter_minmax == vk::TRUE,
+            shader_storage_image_write_without_format: core
+                .shader_storage_image_write_without_format
+                == vk::TRUE,
+        }
+    }
+
+    pub fn wait_idle(&self) -> Result<(), PainterError> {
+        unsafe {
+            self.device
+                .device_wait_idle()
+                .map_err(PainterError::WaitIdleError)
+        }
+    }
+
     pub fn process_delete_events(&mut self) -> Result<(), PainterError> {
         loop {
             let Ok(tbd) = self.delete_signal_receiver.try_recv() else {
@@ -294,6 +594,9 @@ impl Painter {
                     PainterDelete::Buffer(buffer) => {
                         self.device.destroy_buffer(buffer, None);
                     }
+                    PainterDelete::BufferView(buffer_view) => {
+                        self.device.destroy_buffer_view(buffer_view, None);
+                    }
                     PainterDelete::Image(image) => {
                         self.device.destroy_image(image, None);
                     }
@@ -309,6 +612,9 @@ impl Painter {
                     PainterDelete::Fence(fence) => {
                         self.device.destroy_fence(fence, None);
                     }
+                    PainterDelete::QueryPool(query_pool) => {
+                        self.device.destroy_query_pool(query_pool, None);
+                    }
                 }
             }
         }
@@ -318,6 +624,14 @@ impl Painter {
 
 impl Drop for Painter {
     fn drop(&mut self) {
+        // Handles of resources dropped since the last `process_delete_events` are still
+        // queued, they have to go before the device does
+        let _ = self
+            .wait_idle()
+            .inspect_err(|e| eprintln!("at wait for device idle: {e}"));
+        let _ = self
+            .process_delete_events()
+            .inspect_err(|e| eprintln!("at process delete events: {e}"));
         unsafe {
             self.device.destroy_device(None);
             self.surface_instance.destroy_surface(self.surface, None);
@@ -0,0 +1,177 @@
+use ash::vk;
+use crossbeam::channel::Sender;
+use thiserror::Error;
+
+use crate::{painter::PainterDelete, Painter};
+
+#[derive(Debug, Error)]
+pub enum QueryPoolError {
+    #[error("Error creating Vulkan Query Pool: {0}")]
+    CreateError(vk::Result),
+    #[error("Error reading Vulkan Query Pool results: {0}")]
+    ResultError(vk::Result),
+    #[error("Graphics queue doesn't support timestamps")]
+    TimestampsUnsupported,
+}
+
+pub struct TimestampQueries {
+    pub query_pool: vk::QueryPool,
+    pub count: u32,
+    timestamp_period: f32,
+    /// Bits of the written values that are valid, the rest are undefined.
+    timestamp_mask: u64,
+    delete_sender: Sender<PainterDelete>,
+}
+
+impl Drop for TimestampQueries {
+    fn drop(&mut self) {
+        let _ = self
+            .delete_sender
+            .try_send(PainterDelete::QueryPool(self.query_pool))
+            .inspect_err(|e| {
+                eprintln!(
+                    "error sending drop signal for query pool {:?}: {e}",
+                    self.query_pool
+                )
+            });
+    }
+}
+
+/// Results lag the frame that wrote them: read them back once that frame's fence has
+/// signalled, typically to decide what to draw in the next frame. Queries have to be reset
+/// once before their first read, after that a query not written since its last reset reads
+/// as unavailable.
+pub struct OcclusionQueryPool {
+    pub query_pool: vk::QueryPool,
+    pub count: u32,
+    delete_sender: Sender<PainterDelete>,
+}
+
+impl Drop for OcclusionQueryPool {
+    fn drop(&mut self) {
+        let _ = self
+            .delete_sender
+            .try_send(PainterDelete::QueryPool(self.query_pool))
+            .inspect_err(|e| {
+                eprintln!(
+                    "error sending drop signal for query pool {:?}: {e}",
+                    self.query_pool
+                )
+            });
+    }
+}
+
+impl Painter {
+    fn create_query_pool(
+        &self,
+        query_type: vk::QueryType,
+        count: u32,
+    ) -> Result<vk::QueryPool, QueryPoolError> {
+        unsafe {
+            self.device
+                .create_query_pool(
+                    &vk::QueryPoolCreateInfo::default()
+                        .query_type(query_type)
+                        .query_count(count),
+                    None,
+                )
+                .map_err(QueryPoolError::CreateError)
+        }
+    }
+
+    /// Written with `GpuCommand::WriteTimestamp` after a `GpuCommand::ResetQueryPool` in the
+    /// same recording.
+    pub fn create_timestamp_queries(&self, count: u32) -> Result<TimestampQueries, QueryPoolError> {
+        let timestamp_valid_bits = unsafe {
+            self.instance
+                .get_physical_device_queue_family_properties(self.physical_device)
+                [self.graphics_queue_family_index as usize]
+                .timestamp_valid_bits
+        };
+        if timestamp_valid_bits == 0 {
+            return Err(QueryPoolError::TimestampsUnsupported);
+        }
+        let query_pool = self.create_query_pool(vk::QueryType::TIMESTAMP, count)?;
+        let timestamp_period = unsafe {
+            self.instance
+                .get_physical_device_properties(self.physical_device)
+                .limits
+                .timestamp_period
+        };
+        Ok(TimestampQueries {
+            query_pool,
+            count,
+            timestamp_period,
+            timestamp_mask: u64::MAX >> (64 - timestamp_valid_bits),
+            delete_sender: self.delete_signal_sender.clone(),
+        })
+    }
+
+    /// Queries are wrapped around draws with `GpuRenderPassCommand::BeginQuery`/`EndQuery`,
+    /// after a `GpuCommand::ResetQueryPool` outside the render pass.
+    pub fn create_occlusion_query_pool(
+        &self,
+        count: u32,
+    ) -> Result<OcclusionQueryPool, QueryPoolError> {
+        let query_pool = self.create_query_pool(vk::QueryType::OCCLUSION, count)?;
+        Ok(OcclusionQueryPool {
+            query_pool,
+            count,
+            delete_sender: self.delete_signal_sender.clone(),
+        })
+    }
+
+    /// Returns the passed sample count of every query, `None` for queries whose results
+    /// aren't available yet. Never blocks.
+    pub fn read_occlusion_results(
+        &self,
+        pool: &OcclusionQueryPool,
+    ) -> Result<Vec<Option<u64>>, QueryPoolError> {
+        // Each result is followed by its availability
+        let mut results = vec![[0u64; 2]; pool.count as usize];
+        let result = unsafe {
+            self.device.get_query_pool_results(
+                pool.query_pool,
+                0,
+                &mut results,
+                vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WITH_AVAILABILITY,
+            )
+        };
+        match result {
+            Ok(()) | Err(vk::Result::NOT_READY) => Ok(results
+                .into_iter()
+                .map(|[samples, available]| (available != 0).then_some(samples))
+                .collect()),
+            Err(e) => Err(QueryPoolError::ResultError(e)),
+        }
+    }
+
+    /// Returns every timestamp in nanoseconds, or `None` if the GPU hasn't written all of
+    /// them yet. Never blocks.
+    pub fn resolve_timestamps(
+        &self,
+        queries: &TimestampQueries,
+    ) -> Result<Option<Vec<f64>>, QueryPoolError> {
+        let mut ticks = vec![0u64; queries.count as usize];
+        let result = unsafe {
+            self.device.get_query_pool_results(
+                queries.query_pool,
+                0,
+                &mut ticks,
+                vk::QueryResultFlags::TYPE_64,
+            )
+        };
+        match result {
+            Ok(()) => Ok(Some(
+                ticks
+                    .into_iter()
+                    .map(|tick| {
+                        (tick & queries.timestamp_mask) as f64 * queries.timestamp_period as f64
+                    })
+                    .collect(),
+            )),
+            Err(vk::Result::NOT_READY) => Ok(None),
+            Err(e) => Err(QueryPoolError::ResultError(e)),
+        }
+    }
+}
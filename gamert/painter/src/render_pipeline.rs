@@ -3,30 +3,276 @@ use std::sync::Arc;
 use ash::vk;
 
 use crate::{
-    Image2d, Painter, ShaderInputAllocator, ShaderInputBindingInfo, ShaderInputLayout, ShaderModule,
+    GpuRenderPassCommand, Image2d, Painter, ShaderInputAllocator, ShaderInputBindingInfo, ShaderInputLayout, ShaderModule,
 };
 
 pub struct SingePassRenderPipeline {
     pub pipeline_layout: vk::PipelineLayout,
     pub pipeline: vk::Pipeline,
     pub render_pass: vk::RenderPass,
-    pub shader_input_layouts: Vec<ShaderInputLayout>,
+    /// Shared with the variants from `derive_variant`.
+    pub shader_input_layouts: Arc<Vec<ShaderInputLayout>>,
     pub push_constant_size: usize,
     pub painter: Arc<Painter>,
+    state: PipelineState,
+    shared: Arc<SharedPipelineObjects>,
+}
+
+/// What `derive_variant` changes, `None` keeps the base pipeline's state.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PipelineStateOverrides {
+    /// Modes other than `FILL` need the `fillModeNonSolid` device feature.
+    pub polygon_mode: Option<vk::PolygonMode>,
+    pub cull_mode: Option<vk::CullModeFlags>,
+    pub line_width: Option<f32>,
+    /// Compare op and depth writes, ignored without a depth attachment.
+    pub depth_test: Option<(vk::CompareOp, bool)>,
+    pub alpha_blending: Option<bool>,
+    /// Empty for variants that only write depth.
+    pub color_write_mask: Option<vk::ColorComponentFlags>,
+}
+
+/// Fixed function state a pipeline was created with, kept to create its variants.
+#[derive(Clone)]
+struct PipelineState {
+    vertex_binding_descriptions: Vec<vk::VertexInputBindingDescription>,
+    vertex_attribute_descriptions: Vec<vk::VertexInputAttributeDescription>,
+    topology: vk::PrimitiveTopology,
+    polygon_mode: vk::PolygonMode,
+    cull_mode: vk::CullModeFlags,
+    line_width: f32,
+    depth_compare_op: vk::CompareOp,
+    depth_write_enable: bool,
+    stencil_test: Option<(vk::StencilOpState, vk::StencilOpState)>,
+    alpha_blending: bool,
+    color_write_mask: vk::ColorComponentFlags,
+}
+
+impl PipelineState {
+    fn with_overrides(&self, overrides: PipelineStateOverrides) -> Self {
+        let (depth_compare_op, depth_write_enable) = overrides
+            .depth_test
+            .unwrap_or((self.depth_compare_op, self.depth_write_enable));
+        Self {
+            polygon_mode: overrides.polygon_mode.unwrap_or(self.polygon_mode),
+            cull_mode: overrides.cull_mode.unwrap_or(self.cull_mode),
+            line_width: overrides.line_width.unwrap_or(self.line_width),
+            depth_compare_op,
+            depth_write_enable,
+            alpha_blending: overrides.alpha_blending.unwrap_or(self.alpha_blending),
+            color_write_mask: overrides.color_write_mask.unwrap_or(self.color_write_mask),
+            ..self.clone()
+        }
+    }
+}
+
+/// What the render pass, pipeline layout and shader modules of a pipeline are created from.
+struct PipelineDesc<'a> {
+    color_attachments: Vec<(vk::Format, vk::AttachmentLoadOp, vk::AttachmentStoreOp)>,
+    depth_attachment: Option<(vk::Format, vk::AttachmentLoadOp, vk::AttachmentStoreOp)>,
+    input_layouts: Vec<Vec<ShaderInputBindingInfo>>,
+    push_constant_ranges: Vec<vk::PushConstantRange>,
+    vertex_shader_code: &'a [u8],
+    fragment_shader_code: Option<&'a [u8]>,
+}
+
+/// Objects a pipeline shares with its variants, destroyed along with the last of them.
+struct SharedPipelineObjects {
+    render_pass: vk::RenderPass,
+    pipeline_layout: vk::PipelineLayout,
+    vertex_shader_module: ShaderModule,
+    fragment_shader_module: Option<ShaderModule>,
+    color_attachment_count: usize,
+    has_depth_attachment: bool,
+    push_constant_ranges: Vec<vk::PushConstantRange>,
+    painter: Arc<Painter>,
+}
+
+impl Drop for SharedPipelineObjects {
+    fn drop(&mut self) {
+        unsafe {
+            self.painter
+                .device
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+            self.painter
+                .device
+                .destroy_render_pass(self.render_pass, None);
+        }
+    }
+}
+
+/// Collects the pieces of a `SingePassRenderPipeline`. Attachments, shader input sets and
+/// push constant ranges are added in the order the shaders expect them.
+#[derive(Default)]
+pub struct RenderPipelineBuilder<'a> {
+    color_attachments: Vec<(vk::Format, vk::AttachmentLoadOp, vk::AttachmentStoreOp)>,
+    depth_attachment: Option<(vk::Format, vk::AttachmentLoadOp, vk::AttachmentStoreOp)>,
+    input_layouts: Vec<Vec<ShaderInputBindingInfo>>,
+    push_constant_ranges: Vec<vk::PushConstantRange>,
+    vertex_binding_descriptions: Vec<vk::VertexInputBindingDescription>,
+    vertex_attribute_descriptions: Vec<vk::VertexInputAttributeDescription>,
+    vertex_shader_code: Option<&'a [u8]>,
+    fragment_shader_code: Option<&'a [u8]>,
+    depth_test: Option<(vk::CompareOp, bool)>,
+    stencil_test: Option<(vk::StencilOpState, vk::StencilOpState)>,
+    topology: Option<vk::PrimitiveTopology>,
+    line_width: Option<f32>,
+    alpha_blending: bool,
+}
+
+impl<'a> RenderPipelineBuilder<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn color_attachment(
+        mut self,
+        format: vk::Format,
+        load_op: vk::AttachmentLoadOp,
+        store_op: vk::AttachmentStoreOp,
+    ) -> Self {
+        self.color_attachments.push((format, load_op, store_op));
+        self
+    }
+
+    pub fn depth_attachment(
+        mut self,
+        format: vk::Format,
+        load_op: vk::AttachmentLoadOp,
+        store_op: vk::AttachmentStoreOp,
+    ) -> Self {
+        self.depth_attachment = Some((format, load_op, store_op));
+        self
+    }
+
+    /// Adds the bindings of the next descriptor set.
+    pub fn shader_inputs(mut self, bindings: Vec<ShaderInputBindingInfo>) -> Self {
+        self.input_layouts.push(bindings);
+        self
+    }
+
+    pub fn push_constants(
+        mut self,
+        stage_flags: vk::ShaderStageFlags,
+        offset: u32,
+        size: u32,
+    ) -> Self {
+        self.push_constant_ranges.push(
+            vk::PushConstantRange::default()
+                .stage_flags(stage_flags)
+                .offset(offset)
+                .size(size),
+        );
+        self
+    }
+
+    pub fn vertex_input(
+        mut self,
+        binding_descriptions: Vec<vk::VertexInputBindingDescription>,
+        attribute_descriptions: Vec<vk::VertexInputAttributeDescription>,
+    ) -> Self {
+        self.vertex_binding_descriptions = binding_descriptions;
+        self.vertex_attribute_descriptions = attribute_descriptions;
+        self
+    }
+
+    pub fn shaders(
+        mut self,
+        vertex_shader_code: &'a [u8],
+        fragment_shader_code: &'a [u8],
+    ) -> Self {
+        self.vertex_shader_code = Some(vertex_shader_code);
+        self.fragment_shader_code = Some(fragment_shader_code);
+        self
+    }
+
+    /// For depth only pipelines that have no fragment stage.
+    pub fn vertex_shader(mut self, vertex_shader_code: &'a [u8]) -> Self {
+        self.vertex_shader_code = Some(vertex_shader_code);
+        self.fragment_shader_code = None;
+        self
+    }
+
+    /// Defaults to `LESS` with depth writes on.
+    pub fn depth_test(mut self, compare_op: vk::CompareOp, write_enable: bool) -> Self {
+        self.depth_test = Some((compare_op, write_enable));
+        self
+    }
+
+    /// Enables stencil testing with separate state for front and back faces. Their
+    /// `reference` is ignored, draws set it with `GpuRenderPassCommand::SetStencilReference`.
+    pub fn stencil_test(mut self, front: vk::StencilOpState, back: vk::StencilOpState) -> Self {
+        self.stencil_test = Some((front, back));
+        self
+    }
+
+    /// Defaults to `TRIANGLE_LIST`.
+    pub fn topology(mut self, topology: vk::PrimitiveTopology) -> Self {
+        self.topology = Some(topology);
+        self
+    }
+
+    /// Defaults to 1.0. Other widths need the `wideLines` device feature.
+    pub fn line_width(mut self, line_width: f32) -> Self {
+        self.line_width = Some(line_width);
+        self
+    }
+
+    /// Blends color attachments with the fragment alpha, `src * a + dst * (1 - a)`.
+    pub fn alpha_blending(mut self) -> Self {
+        self.alpha_blending = true;
+        self
+    }
+
+    pub fn build(self, painter: Arc<Painter>) -> Result<SingePassRenderPipeline, String> {
+        let vertex_shader_code = self
+            .vertex_shader_code
+            .ok_or("at pipeline build: no vertex shader set".to_string())?;
+        let (depth_compare_op, depth_write_enable) =
+            self.depth_test.unwrap_or((vk::CompareOp::LESS, true));
+        SingePassRenderPipeline::new(
+            painter,
+            PipelineDesc {
+                color_attachments: self.color_attachments,
+                depth_attachment: self.depth_attachment,
+                input_layouts: self.input_layouts,
+                push_constant_ranges: self.push_constant_ranges,
+                vertex_shader_code,
+                fragment_shader_code: self.fragment_shader_code,
+            },
+            PipelineState {
+                vertex_binding_descriptions: self.vertex_binding_descriptions,
+                vertex_attribute_descriptions: self.vertex_attribute_descriptions,
+                topology: self
+                    .topology
+                    .unwrap_or(vk::PrimitiveTopology::TRIANGLE_LIST),
+                polygon_mode: vk::PolygonMode::FILL,
+                cull_mode: vk::CullModeFlags::BACK,
+                line_width: self.line_width.unwrap_or(1.0),
+                depth_compare_op,
+                depth_write_enable,
+                stencil_test: self.stencil_test,
+                alpha_blending: self.alpha_blending,
+                color_write_mask: vk::ColorComponentFlags::RGBA,
+            },
+        )
+    }
 }
 
 impl SingePassRenderPipeline {
-    pub fn new(
+    fn new(
         painter: Arc<Painter>,
-        color_attachments: Vec<(vk::Format, vk::AttachmentLoadOp, vk::AttachmentStoreOp)>,
-        depth_attachment: Option<(vk::Format, vk::AttachmentLoadOp, vk::AttachmentStoreOp)>,
-        input_layouts: Vec<Vec<ShaderInputBindingInfo>>,
-        push_constant_size: usize,
-        vertex_shader_code: &[u8],
-        fragment_shader_code: &[u8],
-        vertex_binding_descriptions: Vec<vk::VertexInputBindingDescription>,
-        vertex_attribute_descriptions: Vec<vk::VertexInputAttributeDescription>,
+        desc: PipelineDesc,
+        state: PipelineState,
     ) -> Result<Self, String> {
+        let PipelineDesc {
+            color_attachments,
+            depth_attachment,
+            input_layouts,
+            push_constant_ranges,
+            vertex_shader_code,
+            fragment_shader_code,
+        } = desc;
         let color_attachments = color_attachments
             .iter()
             .map(|(format, load_op, store_op)| {
@@ -45,6 +291,8 @@ impl SingePassRenderPipeline {
                 .samples(vk::SampleCountFlags::TYPE_1)
                 .load_op(load_op)
                 .store_op(store_op)
+                .stencil_load_op(load_op)
+                .stencil_store_op(store_op)
                 .initial_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
                 .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
         });
@@ -78,9 +326,31 @@ impl SingePassRenderPipeline {
         }
         let subpass = [subpass];
 
+        // Orders attachment accesses against earlier passes on the same images, e.g. a depth
+        // prepass followed by a color pass testing against its depth.
+        let attachment_stages = vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+            | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS;
+        let dependencies = [vk::SubpassDependency::default()
+            .src_subpass(vk::SUBPASS_EXTERNAL)
+            .dst_subpass(0)
+            .src_stage_mask(attachment_stages)
+            .dst_stage_mask(attachment_stages)
+            .src_access_mask(
+                vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            )
+            .dst_access_mask(
+                vk::AccessFlags::COLOR_ATTACHMENT_READ
+                    | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            )
+            .dependency_flags(vk::DependencyFlags::BY_REGION)];
         let render_pass_create_info = vk::RenderPassCreateInfo::default()
             .attachments(&all_attchments)
-            .subpasses(&subpass);
+            .subpasses(&subpass)
+            .dependencies(&dependencies);
         let render_pass = unsafe {
             painter
                 .device
@@ -96,97 +366,168 @@ impl SingePassRenderPipeline {
             .iter()
             .map(|input_layout| input_layout.descriptor_set_layout)
             .collect::<Vec<_>>();
-        let pc_ranges = if push_constant_size > 0 {
-            vec![
-                vk::PushConstantRange::default()
-                    .stage_flags(vk::ShaderStageFlags::ALL)
-                    .offset(0)
-                    .size(push_constant_size as u32),
-            ]
-        } else {
-            vec![]
-        };
+        let push_constant_size = push_constant_ranges
+            .iter()
+            .map(|range| (range.offset + range.size) as usize)
+            .max()
+            .unwrap_or(0);
         let pipeline_layout_info = vk::PipelineLayoutCreateInfo::default()
             .set_layouts(&set_layouts)
-            .push_constant_ranges(&pc_ranges);
+            .push_constant_ranges(&push_constant_ranges);
         let pipeline_layout = unsafe {
             painter
                 .device
                 .create_pipeline_layout(&pipeline_layout_info, None)
                 .map_err(|e| format!("at pipeline layout creation: {e}"))?
         };
-        let pipeline = unsafe {
-            let vertex_shader_module = ShaderModule::new(painter.clone(), &vertex_shader_code)?;
-            let fragment_shader_module = ShaderModule::new(painter.clone(), &fragment_shader_code)?;
-            let shader_stages = [
+        let vertex_shader_module = ShaderModule::new(painter.clone(), vertex_shader_code)?;
+        let fragment_shader_module = fragment_shader_code
+            .map(|code| ShaderModule::new(painter.clone(), code))
+            .transpose()?;
+        let shared = Arc::new(SharedPipelineObjects {
+            render_pass,
+            pipeline_layout,
+            vertex_shader_module,
+            fragment_shader_module,
+            color_attachment_count: color_attachments.len(),
+            has_depth_attachment: depth_attachment.is_some(),
+            push_constant_ranges,
+            painter: painter.clone(),
+        });
+        let pipeline = Self::create_pipeline(&shared, &state, None)?;
+        Ok(Self {
+            render_pass,
+            shader_input_layouts: Arc::new(shader_input_layouts),
+            push_constant_size,
+            pipeline_layout,
+            pipeline,
+            painter,
+            state,
+            shared,
+        })
+    }
+
+    /// A pipeline for the same render pass, shader inputs and shaders with some of the fixed
+    /// function state changed. Only the pipeline itself is created, as a derivative of this
+    /// one, so it is much cheaper than building another. Either can be dropped first.
+    pub fn derive_variant(&self, overrides: PipelineStateOverrides) -> Result<Self, String> {
+        let state = self.state.with_overrides(overrides);
+        let pipeline = Self::create_pipeline(&self.shared, &state, Some(self.pipeline))?;
+        Ok(Self {
+            render_pass: self.render_pass,
+            shader_input_layouts: self.shader_input_layouts.clone(),
+            push_constant_size: self.push_constant_size,
+            pipeline_layout: self.pipeline_layout,
+            pipeline,
+            painter: self.painter.clone(),
+            state,
+            shared: self.shared.clone(),
+        })
+    }
+
+    fn create_pipeline(
+        shared: &SharedPipelineObjects,
+        state: &PipelineState,
+        base_pipeline: Option<vk::Pipeline>,
+    ) -> Result<vk::Pipeline, String> {
+        unsafe {
+            let mut shader_stages = vec![
                 vk::PipelineShaderStageCreateInfo::default()
                     .stage(vk::ShaderStageFlags::VERTEX)
-                    .module(*vertex_shader_module.get_vk())
-                    .name(c"main")
-                    .into(),
-                vk::PipelineShaderStageCreateInfo::default()
-                    .stage(vk::ShaderStageFlags::FRAGMENT)
-                    .module(*fragment_shader_module.get_vk())
-                    .name(c"main")
-                    .into(),
+                    .module(*shared.vertex_shader_module.get_vk())
+                    .name(c"main"),
             ];
+            if let Some(fragment_shader_module) = shared.fragment_shader_module.as_ref() {
+                shader_stages.push(
+                    vk::PipelineShaderStageCreateInfo::default()
+                        .stage(vk::ShaderStageFlags::FRAGMENT)
+                        .module(*fragment_shader_module.get_vk())
+                        .name(c"main"),
+                );
+            }
             let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::default()
-                .vertex_binding_descriptions(&vertex_binding_descriptions)
-                .vertex_attribute_descriptions(&vertex_attribute_descriptions);
-            let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::default()
-                .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+                .vertex_binding_descriptions(&state.vertex_binding_descriptions)
+                .vertex_attribute_descriptions(&state.vertex_attribute_descriptions);
+            let input_assembly_state =
+                vk::PipelineInputAssemblyStateCreateInfo::default().topology(state.topology);
             let viewport_state = vk::PipelineViewportStateCreateInfo::default()
                 .viewport_count(1)
                 .scissor_count(1);
             let rasterization_state = vk::PipelineRasterizationStateCreateInfo::default()
-                .polygon_mode(vk::PolygonMode::FILL)
-                .cull_mode(vk::CullModeFlags::BACK)
+                .polygon_mode(state.polygon_mode)
+                .cull_mode(state.cull_mode)
                 .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
-                .line_width(1.0);
+                .line_width(state.line_width);
             let multisample_state = vk::PipelineMultisampleStateCreateInfo::default()
                 .rasterization_samples(vk::SampleCountFlags::TYPE_1);
-            let color_blend_attachments = [vk::PipelineColorBlendAttachmentState::default()
-                .color_write_mask(vk::ColorComponentFlags::RGBA)
-                .blend_enable(false)];
+            let color_blend_attachments = vec![
+                vk::PipelineColorBlendAttachmentState::default()
+                    .color_write_mask(state.color_write_mask)
+                    .blend_enable(state.alpha_blending)
+                    .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+                    .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+                    .color_blend_op(vk::BlendOp::ADD)
+                    .src_alpha_blend_factor(vk::BlendFactor::ONE)
+                    .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+                    .alpha_blend_op(vk::BlendOp::ADD);
+                shared.color_attachment_count
+            ];
             let color_blend_state = vk::PipelineColorBlendStateCreateInfo::default()
                 .attachments(&color_blend_attachments);
-            let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::default()
-                .depth_test_enable(depth_attachment.is_some())
-                .depth_write_enable(true)
-                .depth_compare_op(vk::CompareOp::LESS)
+            let mut depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::default()
+                .depth_test_enable(shared.has_depth_attachment)
+                .depth_write_enable(state.depth_write_enable)
+                .depth_compare_op(state.depth_compare_op)
                 .depth_bounds_test_enable(false)
-                .stencil_test_enable(false);
-            let dynamic_state = vk::PipelineDynamicStateCreateInfo::default()
-                .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
-            let pipeline_create_info = vk::GraphicsPipelineCreateInfo::default()
-                .render_pass(render_pass)
+                .stencil_test_enable(state.stencil_test.is_some());
+            let mut dynamic_states = vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+            if let Some((front, back)) = state.stencil_test {
+                depth_stencil_state = depth_stencil_state.front(front).back(back);
+                dynamic_states.push(vk::DynamicState::STENCIL_REFERENCE);
+            }
+            let dynamic_state =
+                vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
+            let mut pipeline_create_info = vk::GraphicsPipelineCreateInfo::default()
+                .flags(vk::PipelineCreateFlags::ALLOW_DERIVATIVES)
+                .render_pass(shared.render_pass)
                 .stages(&shader_stages)
                 .vertex_input_state(&vertex_input_state)
                 .input_assembly_state(&input_assembly_state)
-                .layout(pipeline_layout)
+                .layout(shared.pipeline_layout)
                 .viewport_state(&viewport_state)
                 .rasterization_state(&rasterization_state)
                 .multisample_state(&multisample_state)
                 .color_blend_state(&color_blend_state)
                 .depth_stencil_state(&depth_stencil_state)
                 .dynamic_state(&dynamic_state);
-            painter
+            if let Some(base_pipeline) = base_pipeline {
+                pipeline_create_info = pipeline_create_info
+                    .flags(
+                        vk::PipelineCreateFlags::ALLOW_DERIVATIVES
+                            | vk::PipelineCreateFlags::DERIVATIVE,
+                    )
+                    .base_pipeline_handle(base_pipeline)
+                    .base_pipeline_index(-1);
+            }
+            Ok(shared
+                .painter
                 .device
                 .create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_create_info], None)
                 .map_err(|(_, e)| format!("at pipeline creation: {e}"))?
-                .swap_remove(0)
-        };
-        Ok(Self {
-            render_pass,
-            shader_input_layouts,
-            push_constant_size,
-            pipeline_layout,
-            pipeline,
-            painter,
-        })
+                .swap_remove(0))
+        }
     }
 
     pub fn create_render_output(&self, attachments: Vec<&Image2d>) -> Result<RenderOutput, String> {
+        let Some(first_attachment) = attachments.first() else {
+            return Err("at framebuffer creation: no attachments".to_string());
+        };
+        let extent = first_attachment.extent;
+        if extent.width == 0 || extent.height == 0 {
+            return Err(format!(
+                "at framebuffer creation: zero sized attachments {extent:?}"
+            ));
+        }
         unsafe {
             let attachment_views = attachments
                 .iter()
@@ -195,8 +536,8 @@ impl SingePassRenderPipeline {
             let framebuffer_create_info = vk::FramebufferCreateInfo::default()
                 .render_pass(self.render_pass)
                 .attachments(&attachment_views)
-                .width(attachments[0].extent.width)
-                .height(attachments[0].extent.height)
+                .width(extent.width)
+                .height(extent.height)
                 .layers(1);
             let framebuffer = self
                 .painter
@@ -204,43 +545,111 @@ impl SingePassRenderPipeline {
                 .create_framebuffer(&framebuffer_create_info, None)
                 .map_err(|e| format!("at framebuffer creation: {e}"))?;
             Ok(RenderOutput {
-                extent: attachments[0].extent,
+                extent,
                 render_pass: self.render_pass,
                 framebuffer,
+                attachments: attachments
+                    .iter()
+                    .map(|image| (image.image, image.format, image.get_subresource_range()))
+                    .collect(),
                 painter: self.painter.clone(),
             })
         }
     }
 
+    /// Builds a `SetPushConstant` command, checking the write against the push constant ranges
+    /// this pipeline was created with, see `check_push_constant_write`.
+    pub fn make_push_constant_command<'a>(
+        &self,
+        pipeline_layout: usize,
+        stage_flags: vk::ShaderStageFlags,
+        offset: u32,
+        data: Vec<u8>,
+    ) -> Result<GpuRenderPassCommand<'a>, String> {
+        check_push_constant_write(
+            &self.shared.push_constant_ranges,
+            stage_flags,
+            offset,
+            data.len(),
+        )?;
+        Ok(GpuRenderPassCommand::SetPushConstant {
+            pipeline_layout,
+            stage_flags,
+            offset,
+            data,
+        })
+    }
+
     pub fn make_shader_inputs(
         &self,
         allocator: &ShaderInputAllocator,
     ) -> Result<Vec<vk::DescriptorSet>, String> {
         self.shader_input_layouts
             .iter()
-            .map(|input_layout| allocator.allocate(input_layout))
+            .map(|input_layout| allocator.allocate(input_layout, None))
             .collect::<Result<Vec<_>, _>>()
     }
+
+    /// Allocates only set `set`, sizing its variable sized binding to `variable_count`.
+    pub fn make_shader_input(
+        &self,
+        allocator: &ShaderInputAllocator,
+        set: usize,
+        variable_count: Option<u32>,
+    ) -> Result<vk::DescriptorSet, String> {
+        let input_layout = self
+            .shader_input_layouts
+            .get(set)
+            .ok_or(format!("at make shader input: pipeline has no set {set}"))?;
+        allocator.allocate(input_layout, variable_count)
+    }
+}
+
+/// Vulkan's rules for `vkCmdPushConstants`: every byte written has to be in a range for each
+/// of `stage_flags`, and `stage_flags` has to include all stages of each range it touches.
+fn check_push_constant_write(
+    ranges: &[vk::PushConstantRange],
+    stage_flags: vk::ShaderStageFlags,
+    offset: u32,
+    size: usize,
+) -> Result<(), String> {
+    for byte in offset..offset + size as u32 {
+        let mut covered_stages = vk::ShaderStageFlags::empty();
+        for range in ranges
+            .iter()
+            .filter(|range| (range.offset..range.offset + range.size).contains(&byte))
+        {
+            if !stage_flags.contains(range.stage_flags) {
+                return Err(format!(
+                    "push constant write to {stage_flags:?} at byte {byte} misses stages {:?} of its range",
+                    range.stage_flags & !stage_flags
+                ));
+            }
+            covered_stages |= range.stage_flags;
+        }
+        if !covered_stages.contains(stage_flags) {
+            return Err(format!(
+                "push constant write to {stage_flags:?} at byte {byte} is outside the ranges of {:?}",
+                stage_flags & !covered_stages
+            ));
+        }
+    }
+    Ok(())
 }
 
 impl Drop for SingePassRenderPipeline {
     fn drop(&mut self) {
         unsafe {
             self.painter.device.destroy_pipeline(self.pipeline, None);
-            self.painter
-                .device
-                .destroy_pipeline_layout(self.pipeline_layout, None);
-            self.painter
-                .device
-                .destroy_render_pass(self.render_pass, None);
         }
     }
 }
 
 pub struct RenderOutput {
     pub extent: vk::Extent2D,
-    render_pass: vk::RenderPass,
+    pub(crate) render_pass: vk::RenderPass,
     pub framebuffer: vk::Framebuffer,
+    pub(crate) attachments: Vec<(vk::Image, vk::Format, vk::ImageSubresourceRange)>,
     painter: Arc<Painter>,
 }
 
@@ -253,3 +662,37 @@ impl Drop for RenderOutput {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ash::vk;
+
+    use super::check_push_constant_write;
+
+    #[test]
+    fn push_constant_writes_must_match_range_stages() {
+        let ranges = [
+            vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::VERTEX,
+                offset: 0,
+                size: 64,
+            },
+            vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                offset: 64,
+                size: 16,
+            },
+        ];
+        let vertex = vk::ShaderStageFlags::VERTEX;
+        let both = vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT;
+
+        assert!(check_push_constant_write(&ranges, vertex, 0, 64).is_ok());
+        assert!(check_push_constant_write(&ranges, both, 64, 16).is_ok());
+        // Past every range
+        assert!(check_push_constant_write(&ranges, both, 64, 32).is_err());
+        // Fragment isn't in the first range
+        assert!(check_push_constant_write(&ranges, both, 0, 80).is_err());
+        // The second range is also used by the fragment stage
+        assert!(check_push_constant_write(&ranges, vertex, 60, 8).is_err());
+    }
+}
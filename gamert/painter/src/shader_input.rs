@@ -8,8 +8,18 @@ use crate::Painter;
 pub enum ShaderInputType {
     UniformBuffer,
     StorageBuffer,
+    /// Bound with an offset per draw, see `GpuRenderPassCommand::BindShaderInputDynamic`.
+    UniformBufferDynamic,
+    StorageBufferDynamic,
     SampledImage2d,
+    SampledCube,
+    /// Written with `imageStore`, in `GENERAL` layout.
+    StorageImage2d,
     Sampler,
+    CombinedImageSampler,
+    /// Buffers read as texels through a view, see `Buffer::create_texel_view`.
+    UniformTexelBuffer,
+    StorageTexelBuffer,
 }
 
 impl ShaderInputType {
@@ -17,8 +27,15 @@ impl ShaderInputType {
         match self {
             ShaderInputType::UniformBuffer => vk::DescriptorType::UNIFORM_BUFFER,
             ShaderInputType::StorageBuffer => vk::DescriptorType::STORAGE_BUFFER,
+            ShaderInputType::UniformBufferDynamic => vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
+            ShaderInputType::StorageBufferDynamic => vk::DescriptorType::STORAGE_BUFFER_DYNAMIC,
             ShaderInputType::SampledImage2d => vk::DescriptorType::SAMPLED_IMAGE,
+            ShaderInputType::SampledCube => vk::DescriptorType::SAMPLED_IMAGE,
+            ShaderInputType::StorageImage2d => vk::DescriptorType::STORAGE_IMAGE,
             ShaderInputType::Sampler => vk::DescriptorType::SAMPLER,
+            ShaderInputType::CombinedImageSampler => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            ShaderInputType::UniformTexelBuffer => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+            ShaderInputType::StorageTexelBuffer => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
         }
     }
 }
@@ -26,15 +43,65 @@ impl ShaderInputType {
 pub enum ShaderInputValue {
     UniformBuffers(Vec<vk::Buffer>),
     StorageBuffers(Vec<vk::Buffer>),
+    /// Buffers with the size of the range each dynamic offset selects, as the offset is
+    /// added to the descriptor's own.
+    UniformBuffersDynamic(Vec<(vk::Buffer, vk::DeviceSize)>),
+    StorageBuffersDynamic(Vec<(vk::Buffer, vk::DeviceSize)>),
     SampledImage2ds(Vec<vk::ImageView>),
+    SampledCubes(Vec<vk::ImageView>),
+    StorageImage2ds(Vec<vk::ImageView>),
     Samplers(Vec<vk::Sampler>),
+    CombinedImageSamplers(Vec<(vk::ImageView, vk::Sampler)>),
+    UniformTexelBuffers(Vec<vk::BufferView>),
+    StorageTexelBuffers(Vec<vk::BufferView>),
+}
+
+impl ShaderInputValue {
+    pub fn get_descriptor_type(&self) -> vk::DescriptorType {
+        match self {
+            ShaderInputValue::UniformBuffers(_) => vk::DescriptorType::UNIFORM_BUFFER,
+            ShaderInputValue::StorageBuffers(_) => vk::DescriptorType::STORAGE_BUFFER,
+            ShaderInputValue::UniformBuffersDynamic(_) => {
+                vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC
+            }
+            ShaderInputValue::StorageBuffersDynamic(_) => {
+                vk::DescriptorType::STORAGE_BUFFER_DYNAMIC
+            }
+            ShaderInputValue::SampledImage2ds(_) => vk::DescriptorType::SAMPLED_IMAGE,
+            ShaderInputValue::SampledCubes(_) => vk::DescriptorType::SAMPLED_IMAGE,
+            ShaderInputValue::StorageImage2ds(_) => vk::DescriptorType::STORAGE_IMAGE,
+            ShaderInputValue::Samplers(_) => vk::DescriptorType::SAMPLER,
+            ShaderInputValue::CombinedImageSamplers(_) => {
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER
+            }
+            ShaderInputValue::UniformTexelBuffers(_) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+            ShaderInputValue::StorageTexelBuffers(_) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct ShaderInputBindingInfo {
     pub _type: ShaderInputType,
     pub count: u32,
+    /// Lets the binding be updated after its set is bound and left partially written, for
+    /// large arrays like bindless textures. Unrelated to the `*Dynamic` input types.
     pub dynamic: bool,
+    /// Shader stages reading the binding. Narrower stages let drivers skip work for the
+    /// others, and strict validation on tiled GPUs flags bindings a stage never uses.
+    pub stages: vk::ShaderStageFlags,
+}
+
+impl ShaderInputBindingInfo {
+    /// Visible to every stage, like bindings were before `stages` existed.
+    pub fn new(_type: ShaderInputType, count: u32, dynamic: bool) -> Self {
+        Self {
+            _type,
+            count,
+            dynamic,
+            stages: vk::ShaderStageFlags::ALL,
+        }
+    }
 }
 
 pub struct ShaderInputLayout {
@@ -57,28 +124,42 @@ impl ShaderInputLayout {
                         .binding(binding as u32)
                         .descriptor_type(binding_info._type.get_descriptor_type())
                         .descriptor_count(binding_info.count)
-                        .stage_flags(vk::ShaderStageFlags::ALL)
+                        .stage_flags(binding_info.stages)
                 })
                 .collect::<Vec<_>>();
 
             let binding_flags = bindings
                 .iter()
-                .map(|binding_info| {
-                    if binding_info.dynamic {
+                .enumerate()
+                .map(|(binding, binding_info)| {
+                    if !binding_info.dynamic {
+                        vk::DescriptorBindingFlags::empty()
+                    } else if binding == bindings.len() - 1 {
+                        // Only the last binding may have its size picked at allocation
                         vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
                             | vk::DescriptorBindingFlags::PARTIALLY_BOUND
+                            | vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT
                     } else {
-                        vk::DescriptorBindingFlags::empty()
+                        vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+                            | vk::DescriptorBindingFlags::PARTIALLY_BOUND
                     }
                 })
                 .collect::<Vec<_>>();
 
+            // Layouts with dynamic offset buffers can't be update after bind, so only the ones
+            // needing it are
+            let layout_flags = if bindings.iter().any(|binding_info| binding_info.dynamic) {
+                vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL
+            } else {
+                vk::DescriptorSetLayoutCreateFlags::empty()
+            };
+
             let descriptor_set_layout = painter
                 .device
                 .create_descriptor_set_layout(
                     &vk::DescriptorSetLayoutCreateInfo::default()
                         .bindings(&vk_bindings)
-                        .flags(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL)
+                        .flags(layout_flags)
                         .push_next(
                             &mut vk::DescriptorSetLayoutBindingFlagsCreateInfo::default()
                                 .binding_flags(&binding_flags),
@@ -95,6 +176,34 @@ impl ShaderInputLayout {
     }
 }
 
+impl ShaderInputLayout {
+    /// Array size of the last binding when it is dynamic, which makes it variable sized.
+    pub fn variable_binding_count(&self) -> Option<u32> {
+        self.bindings
+            .last()
+            .filter(|binding_info| binding_info.dynamic)
+            .map(|binding_info| binding_info.count)
+    }
+
+    /// Descriptors of each type one set takes, with the variable sized binding at
+    /// `variable_count` when given.
+    pub fn descriptor_counts(&self, variable_count: Option<u32>) -> Vec<(vk::DescriptorType, u32)> {
+        let mut counts = vec![];
+        for (binding, binding_info) in self.bindings.iter().enumerate() {
+            let count = match variable_count {
+                Some(variable_count)
+                    if binding_info.dynamic && binding == self.bindings.len() - 1 =>
+                {
+                    variable_count
+                }
+                _ => binding_info.count,
+            };
+            add_descriptor_count(&mut counts, binding_info._type.get_descriptor_type(), count);
+        }
+        counts
+    }
+}
+
 impl Drop for ShaderInputLayout {
     fn drop(&mut self) {
         unsafe {
@@ -105,9 +214,115 @@ impl Drop for ShaderInputLayout {
     }
 }
 
+impl Painter {
+    /// Writes `value` into `binding` of `descriptor_set`, starting at array element
+    /// `first_element`. Images are expected to be in `SHADER_READ_ONLY_OPTIMAL` layout, storage
+    /// images in `GENERAL`.
+    pub fn write_shader_input(
+        &self,
+        descriptor_set: vk::DescriptorSet,
+        binding: u32,
+        first_element: u32,
+        value: &ShaderInputValue,
+    ) {
+        let buffer_infos = match value {
+            ShaderInputValue::UniformBuffers(buffers)
+            | ShaderInputValue::StorageBuffers(buffers) => buffers
+                .iter()
+                .map(|&buffer| {
+                    vk::DescriptorBufferInfo::default()
+                        .buffer(buffer)
+                        .range(vk::WHOLE_SIZE)
+                })
+                .collect::<Vec<_>>(),
+            ShaderInputValue::UniformBuffersDynamic(buffers)
+            | ShaderInputValue::StorageBuffersDynamic(buffers) => buffers
+                .iter()
+                .map(|&(buffer, range)| {
+                    vk::DescriptorBufferInfo::default()
+                        .buffer(buffer)
+                        .range(range)
+                })
+                .collect::<Vec<_>>(),
+            _ => vec![],
+        };
+        let image_infos = match value {
+            ShaderInputValue::SampledImage2ds(image_views)
+            | ShaderInputValue::SampledCubes(image_views) => image_views
+                .iter()
+                .map(|&image_view| {
+                    vk::DescriptorImageInfo::default()
+                        .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                        .image_view(image_view)
+                })
+                .collect::<Vec<_>>(),
+            ShaderInputValue::StorageImage2ds(image_views) => image_views
+                .iter()
+                .map(|&image_view| {
+                    vk::DescriptorImageInfo::default()
+                        .image_layout(vk::ImageLayout::GENERAL)
+                        .image_view(image_view)
+                })
+                .collect::<Vec<_>>(),
+            ShaderInputValue::Samplers(samplers) => samplers
+                .iter()
+                .map(|&sampler| vk::DescriptorImageInfo::default().sampler(sampler))
+                .collect::<Vec<_>>(),
+            ShaderInputValue::CombinedImageSamplers(pairs) => pairs
+                .iter()
+                .map(|&(image_view, sampler)| {
+                    vk::DescriptorImageInfo::default()
+                        .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                        .image_view(image_view)
+                        .sampler(sampler)
+                })
+                .collect::<Vec<_>>(),
+            _ => vec![],
+        };
+        let texel_buffer_views = match value {
+            ShaderInputValue::UniformTexelBuffers(buffer_views)
+            | ShaderInputValue::StorageTexelBuffers(buffer_views) => buffer_views.as_slice(),
+            _ => &[],
+        };
+        let mut write = vk::WriteDescriptorSet::default()
+            .dst_set(descriptor_set)
+            .dst_binding(binding)
+            .dst_array_element(first_element)
+            .descriptor_type(value.get_descriptor_type());
+        if !buffer_infos.is_empty() {
+            write = write.buffer_info(&buffer_infos);
+        } else if !image_infos.is_empty() {
+            write = write.image_info(&image_infos);
+        } else if !texel_buffer_views.is_empty() {
+            write = write.texel_buffer_view(texel_buffer_views);
+        } else {
+            return;
+        }
+        unsafe {
+            self.device.update_descriptor_sets(&[write], &[]);
+        }
+    }
+}
+
+/// Adds `count` to the entry for `ty`, so every descriptor type is counted once.
+fn add_descriptor_count(
+    counts: &mut Vec<(vk::DescriptorType, u32)>,
+    ty: vk::DescriptorType,
+    count: u32,
+) {
+    match counts.iter_mut().find(|(counted_ty, _)| *counted_ty == ty) {
+        Some((_, total)) => *total += count,
+        None => counts.push((ty, count)),
+    }
+}
+
+/// Sets can be handed back one at a time with `free`, the pool is created with
+/// `FREE_DESCRIPTOR_SET` for that.
 pub struct ShaderInputAllocator {
     painter: Arc<Painter>,
     descriptor_pool: vk::DescriptorPool,
+    /// Descriptors of each type the pool was created with.
+    pool_sizes: Vec<(vk::DescriptorType, u32)>,
 }
 
 impl ShaderInputAllocator {
@@ -116,18 +331,62 @@ impl ShaderInputAllocator {
         counts: Vec<(ShaderInputType, u32)>,
         max_sets: u32,
     ) -> Result<Self, String> {
-        let pool_sizes = counts
+        let mut pool_sizes = vec![];
+        for (ty, count) in counts {
+            add_descriptor_count(&mut pool_sizes, ty.get_descriptor_type(), count);
+        }
+        Self::with_pool_sizes(painter, pool_sizes, max_sets)
+    }
+
+    /// Sizes the pool from the layouts themselves, for `set_count` sets of each, so it can't
+    /// drift out of sync with the bindings the sets are allocated from.
+    pub fn for_layouts(
+        painter: Arc<Painter>,
+        layouts: &[(&ShaderInputLayout, u32)],
+    ) -> Result<Self, String> {
+        let mut pool_sizes = vec![];
+        for &(layout, set_count) in layouts {
+            for (ty, count) in layout.descriptor_counts(None) {
+                add_descriptor_count(&mut pool_sizes, ty, count * set_count);
+            }
+        }
+        let max_sets = layouts.iter().map(|&(_, set_count)| set_count).sum();
+        Self::with_pool_sizes(painter, pool_sizes, max_sets)
+    }
+
+    /// `for_layouts` with one set of every layout per frame in flight, the common case.
+    pub fn for_frames(
+        painter: Arc<Painter>,
+        layouts: &[&ShaderInputLayout],
+        frames: u32,
+    ) -> Result<Self, String> {
+        let layouts = layouts
+            .iter()
+            .map(|&layout| (layout, frames))
+            .collect::<Vec<_>>();
+        Self::for_layouts(painter, &layouts)
+    }
+
+    fn with_pool_sizes(
+        painter: Arc<Painter>,
+        pool_sizes: Vec<(vk::DescriptorType, u32)>,
+        max_sets: u32,
+    ) -> Result<Self, String> {
+        let vk_pool_sizes = pool_sizes
             .iter()
-            .map(|(ty, count)| {
+            .map(|&(ty, count)| {
                 vk::DescriptorPoolSize::default()
-                    .ty(ty.get_descriptor_type())
-                    .descriptor_count(*count)
+                    .ty(ty)
+                    .descriptor_count(count)
             })
             .collect::<Vec<_>>();
         let descriptor_pool_create_info = vk::DescriptorPoolCreateInfo::default()
-            .flags(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND)
+            .flags(
+                vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND
+                    | vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
+            )
             .max_sets(max_sets)
-            .pool_sizes(&pool_sizes);
+            .pool_sizes(&vk_pool_sizes);
         let descriptor_pool = unsafe {
             painter
                 .device
@@ -137,10 +396,48 @@ impl ShaderInputAllocator {
         Ok(Self {
             painter,
             descriptor_pool,
+            pool_sizes,
         })
     }
 
-    pub fn allocate(&self, layout: &ShaderInputLayout) -> Result<vk::DescriptorSet, String> {
+    /// `variable_count` sizes the layout's variable sized last binding, up to the count it
+    /// was declared with. `None` allocates the declared count.
+    pub fn allocate(
+        &self,
+        layout: &ShaderInputLayout,
+        variable_count: Option<u32>,
+    ) -> Result<vk::DescriptorSet, String> {
+        let variable_counts = match layout.variable_binding_count() {
+            Some(max_count) => {
+                let count = variable_count.unwrap_or(max_count);
+                if count > max_count {
+                    return Err(format!(
+                        "at descriptor set allocation: variable count {count} exceeds the \
+                         layout's {max_count}"
+                    ));
+                }
+                vec![count]
+            }
+            None => vec![],
+        };
+        // A pool that can't hold even one set was sized for other layouts, which would
+        // otherwise only show up as an out of pool memory error
+        for (ty, count) in layout.descriptor_counts(variable_counts.first().copied()) {
+            let pool_count = self
+                .pool_sizes
+                .iter()
+                .find(|(pool_ty, _)| *pool_ty == ty)
+                .map_or(0, |&(_, pool_count)| pool_count);
+            if count > pool_count {
+                return Err(format!(
+                    "at descriptor set allocation: set needs {count} {ty:?} descriptors, the \
+                     pool only has {pool_count}"
+                ));
+            }
+        }
+        let mut variable_count_info =
+            vk::DescriptorSetVariableDescriptorCountAllocateInfo::default()
+                .descriptor_counts(&variable_counts);
         unsafe {
             Ok(self
                 .painter
@@ -148,12 +445,24 @@ impl ShaderInputAllocator {
                 .allocate_descriptor_sets(
                     &vk::DescriptorSetAllocateInfo::default()
                         .descriptor_pool(self.descriptor_pool)
-                        .set_layouts(&[layout.descriptor_set_layout]),
+                        .set_layouts(&[layout.descriptor_set_layout])
+                        .push_next(&mut variable_count_info),
                 )
                 .map_err(|e| format!("at descriptor set allocation: {e}"))?
                 .swap_remove(0))
         }
     }
+
+    /// Returns `descriptor_set` to the pool. No submitted command buffer that uses it may
+    /// still be pending, wait on the fences of the frames that bound it first.
+    pub fn free(&self, descriptor_set: vk::DescriptorSet) -> Result<(), String> {
+        unsafe {
+            self.painter
+                .device
+                .free_descriptor_sets(self.descriptor_pool, &[descriptor_set])
+                .map_err(|e| format!("at descriptor set free: {e}"))
+        }
+    }
 }
 
 impl Drop for ShaderInputAllocator {
@@ -1,68 +1,190 @@
+use std::cell::Cell;
+
 use ash::{khr, vk};
 use crossbeam::channel::Sender;
+use thiserror::Error;
+use winit::window::Window;
+
+use crate::{
+    painter::{create_surface, PainterDelete},
+    CommandBuffer, CommandPool, CommandPoolKind, CpuFuture, GpuCommand, GpuFuture, Image2d,
+    Image2dError, ImageAccess, Painter,
+};
+
+/// What to do when acquire or present report the swapchain as suboptimal. It still works
+/// then, unlike when it is out of date, which always rebuilds it before the next acquire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuboptimalPolicy {
+    /// Keep rendering the frame and rebuild before the next acquire.
+    RebuildNextFrame,
+    /// Keep the swapchain, for compositors that report suboptimal all the time.
+    Ignore,
+}
 
-use crate::{painter::PainterDelete, CommandBuffer, CpuFuture, GpuCommand, GpuFuture, Image2d, ImageAccess, Painter};
+#[derive(Debug, Error)]
+pub enum SurfaceFormatError {
+    #[error("Error getting surface formats: {0}")]
+    GetFormatsError(vk::Result),
+    #[error(
+        "No surface format supports {required_features:?}, offered formats and their \
+         features: {available:?}"
+    )]
+    NoSuitableFormat {
+        /// Every format the surface offers, with its optimal tiling features.
+        available: Vec<(vk::SurfaceFormatKHR, vk::FormatFeatureFlags)>,
+        required_features: vk::FormatFeatureFlags,
+    },
+}
+
+#[derive(Debug, Error)]
+pub enum SheetsError {
+    #[error("Error selecting surface format: {0}")]
+    SurfaceFormatError(SurfaceFormatError),
+    #[error("Error getting surface capabilities: {0}")]
+    GetSurfaceCapabilitiesError(vk::Result),
+    #[error("Error getting surface present modes: {0}")]
+    GetPresentModesError(vk::Result),
+    #[error("Error creating swapchain: {0}")]
+    SwapchainCreationError(vk::Result),
+    #[error("Error getting swapchain images: {0}")]
+    GetSwapchainImagesError(vk::Result),
+    #[error("Error creating swapchain image view: {0}")]
+    ImageViewCreationError(Image2dError),
+    #[error("Error initializing swapchain image layouts: {0}")]
+    ImageInitError(String),
+    #[error("Error recording present queue transfers: {0}")]
+    PresentTransferError(String),
+}
 
 pub struct Sheets {
     pub swapchain_images: Vec<Image2d>,
     pub present_mode: vk::PresentModeKHR,
     pub surface_format: vk::SurfaceFormatKHR,
     pub surface_resolution: vk::Extent2D,
+    /// Rotation the images are presented with, see `quarter_turns`.
+    pub pre_transform: vk::SurfaceTransformFlagsKHR,
     pub swapchain: vk::SwapchainKHR,
     pub swapchain_device: khr::swapchain::Device,
+    pub image_usage: vk::ImageUsageFlags,
+    pub surface: vk::SurfaceKHR,
+    surface_instance: khr::surface::Instance,
+    /// Set for sheets made by `Painter::add_surface`, which own their window and surface.
+    /// Sheets of the painter's own window leave both to it.
+    window: Option<Window>,
     pub delete_sender: Sender<PainterDelete>,
+    pub suboptimal_policy: SuboptimalPolicy,
+    /// Set by present, which only borrows the sheets through `AcquiredFrame`.
+    rebuild_pending: Cell<bool>,
+    /// Set when the painter presents from another queue family than it draws with.
+    present_transfer: Option<PresentTransfer>,
+}
+
+/// The present queue's half of the ownership transfer of each swapchain image, the graphics
+/// queue releases them along their transition to `ImageAccess::Present`.
+struct PresentTransfer {
+    command_pool: CommandPool,
+    /// One per swapchain image, re-recorded whenever the swapchain is rebuilt.
+    command_buffers: Vec<CommandBuffer>,
+    /// Signalled by the transfer of the same index, waited on by its present.
+    transfer_complete_futs: Vec<GpuFuture>,
 }
 
 impl Sheets {
-    pub fn new(painter: &Painter, command_buffer: &mut CommandBuffer) -> Result<Self, String> {
+    /// `desired_image_count` is clamped to what the surface supports, `image_count` returns
+    /// the count actually used. 2 keeps latency low, 3 gives smoother presents on some drivers.
+    /// `storage_usage` lets shaders write the images directly, but rules out formats without
+    /// storage support, so only ask for it when needed.
+    pub fn new(
+        painter: &Painter,
+        command_pool: &CommandPool,
+        desired_image_count: u32,
+        storage_usage: bool,
+    ) -> Result<Self, SheetsError> {
+        Self::with_surface(
+            painter,
+            painter.surface,
+            painter.window_extent(),
+            command_pool,
+            desired_image_count,
+            storage_usage,
+        )
+    }
+
+    /// The format `new` would pick: 8 bit RGBA or BGRA in the sRGB color space, usable as a
+    /// blit destination, a color attachment and, with `storage_usage`, a storage image.
+    pub fn select_surface_format(
+        painter: &Painter,
+        surface: vk::SurfaceKHR,
+        storage_usage: bool,
+    ) -> Result<vk::SurfaceFormatKHR, SurfaceFormatError> {
+        let mut required_features =
+            vk::FormatFeatureFlags::COLOR_ATTACHMENT | vk::FormatFeatureFlags::TRANSFER_DST;
+        if storage_usage {
+            required_features |= vk::FormatFeatureFlags::STORAGE_IMAGE;
+        }
+        let available = unsafe {
+            painter
+                .surface_instance
+                .get_physical_device_surface_formats(painter.physical_device, surface)
+                .map_err(SurfaceFormatError::GetFormatsError)?
+                .into_iter()
+                .map(|format| {
+                    let features = painter
+                        .instance
+                        .get_physical_device_format_properties(
+                            painter.physical_device,
+                            format.format,
+                        )
+                        .optimal_tiling_features;
+                    (format, features)
+                })
+                .collect::<Vec<_>>()
+        };
+        available
+            .iter()
+            .find(|(format, features)| {
+                format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
+                    && features.contains(required_features)
+                    && (format.format == vk::Format::B8G8R8A8_UNORM
+                        || format.format == vk::Format::R8G8B8A8_UNORM
+                        || format.format == vk::Format::B8G8R8A8_SRGB
+                        || format.format == vk::Format::R8G8B8A8_SRGB)
+            })
+            .map(|&(format, _)| format)
+            .ok_or_else(|| SurfaceFormatError::NoSuitableFormat {
+                available: available.clone(),
+                required_features,
+            })
+    }
+
+    /// `window_extent` is used when the surface leaves the size to the application. Leaves
+    /// `window` unset, the caller stores it if the sheets own the surface.
+    fn with_surface(
+        painter: &Painter,
+        surface: vk::SurfaceKHR,
+        window_extent: vk::Extent2D,
+        command_pool: &CommandPool,
+        desired_image_count: u32,
+        storage_usage: bool,
+    ) -> Result<Self, SheetsError> {
         unsafe {
             // Swapchain creation
             let surface_instance = &painter.surface_instance;
             let physical_device = painter.physical_device;
-            let surface = painter.surface;
-            let surface_formats = surface_instance
-                .get_physical_device_surface_formats(physical_device, surface)
-                .map_err(|e| format!("at surface formats: {e}"))?;
+            let surface_format = Self::select_surface_format(painter, surface, storage_usage)
+                .map_err(SheetsError::SurfaceFormatError)?;
 
             let surface_caps = surface_instance
                 .get_physical_device_surface_capabilities(physical_device, surface)
-                .map_err(|e| format!("at surface capabilities: {e}"))?;
+                .map_err(SheetsError::GetSurfaceCapabilitiesError)?;
 
             let surface_present_modes = surface_instance
                 .get_physical_device_surface_present_modes(physical_device, surface)
-                .map_err(|e| format!("at surface present modes: {e}"))?;
-
-            let surface_format = surface_formats
-                .iter()
-                .filter(|format| format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR)
-                .filter(|format| {
-                    let supported = painter
-                        .instance
-                        .get_physical_device_format_properties(
-                            painter.physical_device,
-                            format.format,
-                        )
-                        .optimal_tiling_features
-                        .contains(
-                            vk::FormatFeatureFlags::COLOR_ATTACHMENT
-                                | vk::FormatFeatureFlags::TRANSFER_DST
-                                | vk::FormatFeatureFlags::STORAGE_IMAGE,
-                        );
-                    supported
-                        && (format.format == vk::Format::B8G8R8A8_UNORM
-                            || format.format == vk::Format::R8G8B8A8_UNORM
-                            || format.format == vk::Format::B8G8R8A8_SRGB
-                            || format.format == vk::Format::R8G8B8A8_SRGB)
-                })
-                .next()
-                .cloned()
-                .ok_or("no suitable surface format found".to_string())?;
+                .map_err(SheetsError::GetPresentModesError)?;
 
             let mut surface_resolution = surface_caps.current_extent;
             if surface_resolution.width == u32::MAX || surface_resolution.height == u32::MAX {
-                let window_res = painter.window.inner_size();
-                surface_resolution.width = window_res.width;
-                surface_resolution.height = window_res.height;
+                surface_resolution = window_extent;
             }
 
             let surface_present_mode = surface_present_modes
@@ -72,8 +194,8 @@ impl Sheets {
                 .cloned()
                 .unwrap_or(vk::PresentModeKHR::FIFO);
 
-            let swapchain_image_count = std::cmp::min(
-                surface_caps.min_image_count + 1,
+            let swapchain_image_count = desired_image_count.clamp(
+                surface_caps.min_image_count,
                 if surface_caps.max_image_count == 0 {
                     std::u32::MAX
                 } else {
@@ -81,18 +203,20 @@ impl Sheets {
                 },
             );
 
+            let mut image_usage =
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_DST;
+            if storage_usage {
+                image_usage |= vk::ImageUsageFlags::STORAGE;
+            }
+
             let swapchain_create_info = vk::SwapchainCreateInfoKHR::default()
-                .surface(painter.surface)
+                .surface(surface)
                 .min_image_count(swapchain_image_count)
                 .image_format(surface_format.format)
                 .image_color_space(surface_format.color_space)
                 .image_extent(surface_resolution)
                 .image_array_layers(1)
-                .image_usage(
-                    vk::ImageUsageFlags::COLOR_ATTACHMENT
-                        | vk::ImageUsageFlags::TRANSFER_DST
-                        | vk::ImageUsageFlags::STORAGE,
-                )
+                .image_usage(image_usage)
                 .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
                 .pre_transform(surface_caps.current_transform)
                 .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
@@ -102,25 +226,29 @@ impl Sheets {
             let swapchain_device = khr::swapchain::Device::new(&painter.instance, &painter.device);
             let swapchain = swapchain_device
                 .create_swapchain(&swapchain_create_info, None)
-                .map_err(|e| format!("at swapchain creation: {e}"))?;
+                .map_err(SheetsError::SwapchainCreationError)?;
             let swapchain_images = swapchain_device
                 .get_swapchain_images(swapchain)
-                .map_err(|e| format!("at swapchain images: {e}"))?
+                .map_err(SheetsError::GetSwapchainImagesError)?
                 .into_iter()
                 .map(|image| {
                     let image_view =
                         Image2d::create_image_view(&painter, image, surface_format.format)
-                            .map_err(|e| format!("at image view creation: {e}"))?;
+                            .map_err(SheetsError::ImageViewCreationError)?;
                     Ok(Image2d {
                         image,
                         format: surface_format.format,
                         extent: surface_resolution,
+                        array_layers: 1,
+                        mip_levels: 1,
+                        level_views: vec![],
                         bound_mem: None,
+                        mem_free_sender: None,
                         image_view,
                         delete_sender: None,
                     })
                 })
-                .collect::<Result<Vec<_>, String>>()?;
+                .collect::<Result<Vec<_>, SheetsError>>()?;
 
             let commands = swapchain_images
                 .iter()
@@ -129,36 +257,192 @@ impl Sheets {
                     access: ImageAccess::Present,
                 })
                 .collect::<Vec<_>>();
-            painter.record_cmd_buffer(&command_buffer, &commands, true)?;
-            let fence = painter.create_cpu_future(false)
-                .map_err(|e| format!("at fence creation: {e}"))?;
-            painter.submit_cmd_buffer(&command_buffer, vec![], vec![], vec![], Some(&fence))?;
-            painter.cpu_future_wait(&fence).map_err(|e| format!("at fence wait: {e}"))?;
+            painter
+                .run_commands_blocking(command_pool, &commands)
+                .map_err(SheetsError::ImageInitError)?;
 
-            Ok(Self {
+            let mut sheets = Self {
                 swapchain_images,
                 present_mode: surface_present_mode,
                 surface_format,
                 surface_resolution,
+                pre_transform: surface_caps.current_transform,
                 swapchain,
                 swapchain_device,
+                image_usage,
+                surface,
+                surface_instance: painter.surface_instance.clone(),
+                window: None,
                 delete_sender: painter.delete_signal_sender.clone(),
-            })
+                suboptimal_policy: SuboptimalPolicy::RebuildNextFrame,
+                rebuild_pending: Cell::new(false),
+                present_transfer: None,
+            };
+            sheets
+                .record_present_transfers(painter)
+                .map_err(SheetsError::PresentTransferError)?;
+            Ok(sheets)
         }
     }
 
-    pub fn refresh_resolution(&mut self, device: &ash::Device, command_buffer: &mut CommandBuffer) -> Result<(), String> {
+    /// Records the acquiring barriers for the current swapchain images, if the painter has a
+    /// separate present queue. None of the transfers may still be pending.
+    fn record_present_transfers(&mut self, painter: &Painter) -> Result<(), String> {
+        if !painter.has_separate_present_queue() {
+            return Ok(());
+        }
+        let present_transfer = match &mut self.present_transfer {
+            Some(present_transfer) => {
+                painter
+                    .reset_cmd_pool(&present_transfer.command_pool, false)
+                    .map_err(|e| format!("at reset command pool: {e}"))?;
+                present_transfer
+            }
+            None => self.present_transfer.insert(PresentTransfer {
+                command_pool: painter
+                    .create_present_command_pool(CommandPoolKind::Persistent)
+                    .map_err(|e| format!("at create command pool: {e}"))?,
+                command_buffers: vec![],
+                transfer_complete_futs: vec![],
+            }),
+        };
+        let missing_count = self
+            .swapchain_images
+            .len()
+            .saturating_sub(present_transfer.command_buffers.len());
+        if missing_count > 0 {
+            present_transfer.command_buffers.extend(
+                painter
+                    .allocate_command_buffers(&present_transfer.command_pool, missing_count)
+                    .map_err(|e| format!("at allocate command buffers: {e}"))?,
+            );
+            for _ in 0..missing_count {
+                present_transfer.transfer_complete_futs.push(
+                    painter
+                        .create_gpu_future()
+                        .map_err(|e| format!("at create gpu future: {e}"))?,
+                );
+            }
+        }
+        for (image, command_buffer) in self
+            .swapchain_images
+            .iter()
+            .zip(present_transfer.command_buffers.iter_mut())
+        {
+            painter.begin_cmd_buffer(command_buffer, false)?;
+            unsafe {
+                painter.device.cmd_pipeline_barrier(
+                    command_buffer.command_buffer,
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[vk::ImageMemoryBarrier::default()
+                        .image(image.image)
+                        .old_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+                        .new_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+                        .src_queue_family_index(painter.graphics_queue_family_index)
+                        .dst_queue_family_index(painter.present_queue_family_index)
+                        .subresource_range(image.get_subresource_range())],
+                );
+            }
+            painter.end_cmd_buffer(command_buffer)?;
+        }
+        Ok(())
+    }
+
+    /// Drivers may create more images than requested.
+    pub fn image_count(&self) -> usize {
+        self.swapchain_images.len()
+    }
+
+    /// The window presented to, `None` for the painter's own window.
+    pub fn window(&self) -> Option<&Window> {
+        self.window.as_ref()
+    }
+
+    /// Whether the swapchain images are `SRGB`, which encode linear colors on write. `UNORM`
+    /// ones store what is written, so shaders writing to them have to encode it themselves.
+    pub fn is_srgb(&self) -> bool {
+        matches!(
+            self.surface_format.format,
+            vk::Format::R8G8B8A8_SRGB
+                | vk::Format::B8G8R8A8_SRGB
+                | vk::Format::A8B8G8R8_SRGB_PACK32
+                | vk::Format::R8G8B8_SRGB
+                | vk::Format::B8G8R8_SRGB
+        )
+    }
+
+    /// How the presentation engine interprets the images' contents.
+    pub fn color_space(&self) -> vk::ColorSpaceKHR {
+        self.surface_format.color_space
+    }
+
+    /// Clockwise quarter turns the presentation engine expects the images' contents to be
+    /// rotated by, non zero on rotated displays like phones in landscape. Whatever is drawn to
+    /// the sheets has to be rotated to match, or it shows up sideways. Mirrored transforms
+    /// aren't handled and count as their rotation alone.
+    pub fn quarter_turns(&self) -> u32 {
+        let transform = self.pre_transform;
+        if transform.intersects(
+            vk::SurfaceTransformFlagsKHR::ROTATE_90
+                | vk::SurfaceTransformFlagsKHR::HORIZONTAL_MIRROR_ROTATE_90,
+        ) {
+            1
+        } else if transform.intersects(
+            vk::SurfaceTransformFlagsKHR::ROTATE_180
+                | vk::SurfaceTransformFlagsKHR::HORIZONTAL_MIRROR_ROTATE_180,
+        ) {
+            2
+        } else if transform.intersects(
+            vk::SurfaceTransformFlagsKHR::ROTATE_270
+                | vk::SurfaceTransformFlagsKHR::HORIZONTAL_MIRROR_ROTATE_270,
+        ) {
+            3
+        } else {
+            0
+        }
+    }
+
+    /// Size of the display as the user sees it, `surface_resolution` with width and height
+    /// swapped for quarter and three quarter turns. Offscreen targets meant to be rotated onto
+    /// the sheets should use this size.
+    pub fn logical_extent(&self) -> vk::Extent2D {
+        match self.quarter_turns() {
+            1 | 3 => vk::Extent2D {
+                width: self.surface_resolution.height,
+                height: self.surface_resolution.width,
+            },
+            _ => self.surface_resolution,
+        }
+    }
+
+    /// Rebuilds the swapchain for the current surface size. Waits for the device to go idle
+    /// first, so the old swapchain images can't still be in use by frames in flight, and waits
+    /// for its own submission to `command_pool` before returning. Returns false without
+    /// rebuilding while the surface is 0x0, e.g. for a minimized window, which no swapchain
+    /// can be created for.
+    pub fn refresh_resolution(
+        &mut self,
+        painter: &Painter,
+        command_pool: &CommandPool,
+    ) -> Result<bool, String> {
         unsafe {
-            let surface_caps = self
-                .painter
+            let surface_caps = painter
                 .surface_instance
-                .get_physical_device_surface_capabilities(
-                    self.painter.physical_device,
-                    self.painter.surface,
-                )
+                .get_physical_device_surface_capabilities(painter.physical_device, self.surface)
                 .map_err(|e| format!("at surface capabilities: {e}"))?;
 
             let new_resolution = surface_caps.current_extent;
+            if new_resolution.width == 0 || new_resolution.height == 0 {
+                return Ok(false);
+            }
+
+            painter
+                .wait_idle()
+                .map_err(|e| format!("at wait for device idle: {e}"))?;
 
             // Do not compare resolutions to avoid flickering in case of suboptimal swapchain
             // println!("new resolution: {:?}", new_resolution);
@@ -167,17 +451,13 @@ impl Sheets {
             // }
 
             let swapchain_create_info = vk::SwapchainCreateInfoKHR::default()
-                .surface(self.painter.surface)
+                .surface(self.surface)
                 .min_image_count(self.swapchain_images.len() as u32)
                 .image_format(self.surface_format.format)
                 .image_color_space(self.surface_format.color_space)
                 .image_extent(new_resolution)
                 .image_array_layers(1)
-                .image_usage(
-                    vk::ImageUsageFlags::COLOR_ATTACHMENT
-                        | vk::ImageUsageFlags::TRANSFER_DST
-                        | vk::ImageUsageFlags::STORAGE,
-                )
+                .image_usage(self.image_usage)
                 .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
                 .pre_transform(surface_caps.current_transform)
                 .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
@@ -198,18 +478,19 @@ impl Sheets {
                 .map_err(|e| format!("at fetching swapchain images: {e}"))?
                 .into_iter()
                 .map(|image| {
-                    let image_view = Image2d::create_image_view(
-                        &self.painter,
-                        image,
-                        self.surface_format.format,
-                    )
-                    .map_err(|e| format!("at image view creation: {e}"))?;
+                    let image_view =
+                        Image2d::create_image_view(painter, image, self.surface_format.format)
+                            .map_err(|e| format!("at image view creation: {e}"))?;
                     Ok(Image2d {
                         image_view,
                         image,
                         format: self.surface_format.format,
                         extent: new_resolution,
+                        array_layers: 1,
+                        mip_levels: 1,
+                        level_views: vec![],
                         bound_mem: None,
+                        mem_free_sender: None,
                         delete_sender: None,
                     })
                 })
@@ -222,35 +503,36 @@ impl Sheets {
                     access: ImageAccess::Present,
                 })
                 .collect::<Vec<_>>();
-            command_buffer
-                .record(&commands, true)
-                .map_err(|e| format!("at command buffer record: {e}"))?;
-            let fence = CpuFuture::new(self.painter.clone(), false)
-                .map_err(|e| format!("at fence creation: {e}"))?;
-            command_buffer
-                .submit(&[], &[], &[], Some(&fence))
-                .map_err(|e| format!("at command buffer submit: {e}"))?;
-            fence.wait().map_err(|e| format!("at fence wait: {e}"))?;
-            command_buffer
-                .reset()
-                .map_err(|e| format!("at command buffer reset: {e}"))?;
+            painter
+                .run_commands_blocking(command_pool, &commands)
+                .map_err(|e| format!("at transition swapchain images: {e}"))?;
 
             self.swapchain = new_swapchain;
             self.swapchain_images = new_swapchain_images;
+            self.record_present_transfers(painter)
+                .map_err(|e| format!("at record present queue transfers: {e}"))?;
 
             self.swapchain_device.destroy_swapchain(old_swapchain, None);
 
             self.surface_resolution = surface_caps.current_extent;
-            Ok(())
+            self.pre_transform = surface_caps.current_transform;
+            self.rebuild_pending.set(false);
+            Ok(true)
         }
     }
 
-    pub fn acquire_next_image(
-        &mut self,
+    /// Acquires the next swapchain image, rebuilding the swapchain first if it is out of date,
+    /// or was suboptimal and `suboptimal_policy` asks for a rebuild. The returned frame must be
+    /// presented through `AcquiredFrame::present`. `None` while a rebuild waits for the surface
+    /// to stop being 0x0, nothing is acquired then so `semaphore` and `fence` stay unsignalled
+    /// and the frame should be skipped.
+    pub fn acquire_next_image<'a>(
+        &'a mut self,
+        painter: &'a Painter,
         semaphore: Option<&GpuFuture>,
-        mut fence: Option<&CpuFuture>,
-        command_buffer: &mut CommandBuffer,
-    ) -> Result<u32, String> {
+        fence: Option<&CpuFuture>,
+        command_pool: &CommandPool,
+    ) -> Result<Option<AcquiredFrame<'a>>, String> {
         unsafe {
             let vk_fence = fence.map_or(vk::Fence::null(), |fence| fence.fence);
             let vk_semaphore =
@@ -259,80 +541,174 @@ impl Sheets {
                 return Err("either fence or semaphore must be provided".to_string());
             }
             loop {
-                let (img_id, refresh_needed) = match self.swapchain_device.acquire_next_image(
+                // Rebuilding only before acquiring means an acquired image is never dropped,
+                // which would leave its semaphore or fence signalled
+                if self.rebuild_pending.get()
+                    && !self
+                        .refresh_resolution(painter, command_pool)
+                        .map_err(|e| format!("at refreshing swapchain resolution: {e}"))?
+                {
+                    return Ok(None);
+                }
+                match self.swapchain_device.acquire_next_image(
                     self.swapchain,
                     std::u64::MAX,
                     vk_semaphore,
                     vk_fence,
                 ) {
-                    Ok((i_id, ref_needed)) => (Some(i_id), ref_needed),
-                    Err(e) => {
-                        if e == vk::Result::ERROR_OUT_OF_DATE_KHR {
-                            (None, true)
-                        } else {
-                            return Err(format!("at acquiring next image: {e}"))?;
+                    Ok((image_index, suboptimal)) => {
+                        if suboptimal {
+                            self.mark_suboptimal();
                         }
+                        return Ok(Some(AcquiredFrame {
+                            sheets: self,
+                            painter,
+                            image_index,
+                        }));
                     }
-                };
-                if refresh_needed {
-                    self.refresh_resolution(command_buffer)
-                        .map_err(|e| format!("at refreshing swapchain resolution: {e}"))?;
-                    if img_id.is_some() {
-                        fence
-                            .as_mut()
-                            .map(|f| {
-                                f.wait()
-                                    .map_err(|e| format!("at fence wait before refresh: {e}"))?;
-                                f.reset()
-                                    .map_err(|e| format!("at fence reset before refresh: {e}"))?;
-                                Ok::<(), String>(())
-                            })
-                            .transpose()?;
-                    }
-                    continue;
-                }
-                if let Some(i_id) = img_id {
-                    return Ok(i_id);
+                    Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => self.rebuild_pending.set(true),
+                    Err(e) => return Err(format!("at acquiring next image: {e}")),
                 }
             }
         }
     }
 
-    pub fn present_image(
+    fn mark_suboptimal(&self) {
+        if self.suboptimal_policy == SuboptimalPolicy::RebuildNextFrame {
+            self.rebuild_pending.set(true);
+        }
+    }
+
+    fn present_image(
         &self,
+        painter: &Painter,
         image_index: u32,
         wait_semaphores: &[&GpuFuture],
     ) -> Result<(), String> {
-        unsafe {
-            let wait_semaphores = wait_semaphores
+        let wait_semaphores = match &self.present_transfer {
+            Some(present_transfer) => {
+                let image_index = image_index as usize;
+                let transfer_complete_fut = &present_transfer.transfer_complete_futs[image_index];
+                painter
+                    .submit_cmd_buffer(
+                        &present_transfer.command_buffers[image_index],
+                        vec![transfer_complete_fut],
+                        wait_semaphores.to_vec(),
+                        vec![vk::PipelineStageFlags::ALL_COMMANDS; wait_semaphores.len()],
+                        None,
+                    )
+                    .map_err(|e| format!("at present queue transfer: {e}"))?;
+                vec![transfer_complete_fut.semaphore]
+            }
+            None => wait_semaphores
                 .iter()
                 .map(|semaphore| semaphore.semaphore)
-                .collect::<Vec<_>>();
+                .collect::<Vec<_>>(),
+        };
+        unsafe {
             match self.swapchain_device.queue_present(
-                self.painter.graphics_queue,
+                painter.present_queue,
                 &vk::PresentInfoKHR::default()
                     .wait_semaphores(&wait_semaphores)
                     .swapchains(&[self.swapchain])
                     .image_indices(&[image_index]),
             ) {
-                Ok(_) => Ok(()),
-                Err(e) => {
-                    if e != vk::Result::ERROR_OUT_OF_DATE_KHR {
-                        Err(format!("at presenting image: {e}"))
-                    } else {
-                        Ok(())
+                Ok(suboptimal) => {
+                    if suboptimal {
+                        self.mark_suboptimal();
                     }
+                    Ok(())
+                }
+                Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                    self.rebuild_pending.set(true);
+                    Ok(())
                 }
+                Err(e) => Err(format!("at presenting image: {e}")),
+            }
+        }
+    }
+}
+
+impl Painter {
+    /// Creates a surface for another window and sheets presenting to it. The device was picked
+    /// for the painter's own window, so this fails if its present queue can't present to the
+    /// new surface. The sheets own the window and must be dropped before the painter.
+    pub fn add_surface(
+        &self,
+        window: Window,
+        command_pool: &CommandPool,
+        desired_image_count: u32,
+        storage_usage: bool,
+    ) -> Result<Sheets, String> {
+        unsafe {
+            let surface = create_surface(&self.entry, &self.instance, &window)
+                .map_err(|e| format!("at surface creation: {e}"))?;
+            let supported = self
+                .surface_instance
+                .get_physical_device_surface_support(
+                    self.physical_device,
+                    self.present_queue_family_index,
+                    surface,
+                )
+                .unwrap_or(false);
+            if !supported {
+                self.surface_instance.destroy_surface(surface, None);
+                return Err("present queue can't present to the new surface".to_string());
             }
+            let window_size = window.inner_size();
+            let window_extent = vk::Extent2D {
+                width: window_size.width,
+                height: window_size.height,
+            };
+            let mut sheets = Sheets::with_surface(
+                self,
+                surface,
+                window_extent,
+                command_pool,
+                desired_image_count,
+                storage_usage,
+            )
+            .map_err(|e| {
+                self.surface_instance.destroy_surface(surface, None);
+                format!("at create sheets: {e}")
+            })?;
+            sheets.window = Some(window);
+            Ok(sheets)
         }
     }
 }
 
+/// A swapchain image that has been acquired but not yet presented. Consumed by `present`
+/// so an image can only be presented once, and only after it was acquired.
+pub struct AcquiredFrame<'a> {
+    sheets: &'a Sheets,
+    painter: &'a Painter,
+    image_index: u32,
+}
+
+impl<'a> AcquiredFrame<'a> {
+    pub fn image_index(&self) -> u32 {
+        self.image_index
+    }
+
+    pub fn image(&self) -> &'a Image2d {
+        &self.sheets.swapchain_images[self.image_index as usize]
+    }
+
+    pub fn present(self, wait_semaphores: &[&GpuFuture]) -> Result<(), String> {
+        self.sheets
+            .present_image(self.painter, self.image_index, wait_semaphores)
+    }
+}
+
 impl Drop for Sheets {
     fn drop(&mut self) {
         unsafe {
             self.swapchain_device
                 .destroy_swapchain(self.swapchain, None);
+            if self.window.is_some() {
+                self.surface_instance.destroy_surface(self.surface, None);
+            }
         }
     }
 }
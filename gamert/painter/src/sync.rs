@@ -11,7 +11,9 @@ pub enum CpuFutureError {
     #[error("Error waiting for Vulkan Fence")]
     WaitError(vk::Result),
     #[error("Error resetting Vulkan Fence")]
-    ResetError(vk::Result)
+    ResetError(vk::Result),
+    #[error("Error getting Vulkan Fence status")]
+    StatusError(vk::Result),
 }
 
 pub struct CpuFuture {
@@ -59,6 +61,15 @@ impl Painter {
         Ok(())
     }
 
+    /// Whether the fence has signaled, without waiting for it.
+    pub fn cpu_future_is_signaled(&self, cpu_future: &CpuFuture) -> Result<bool, CpuFutureError> {
+        unsafe {
+            self.device
+                .get_fence_status(cpu_future.fence)
+                .map_err(CpuFutureError::StatusError)
+        }
+    }
+
     pub fn cpu_future_reset(&self, cpu_future: &CpuFuture) -> Result<(), CpuFutureError> {
         unsafe {
             self
@@ -0,0 +1,278 @@
+use std::{mem::offset_of, sync::Arc};
+
+use ash::vk;
+use include_bytes_aligned::include_bytes_aligned;
+use painter::{
+    ash, Buffer, GAllocator, GpuCommand, GpuRenderPassCommand, Image2d, Painter, RenderOutput,
+    RenderPipelineBuilder, SingePassRenderPipeline,
+};
+
+static VERTEX_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/debug_lines.vert.spv");
+static FRAGMENT_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/debug_lines.frag.spv");
+
+/// Lines drawable per frame, the rest of the queued lines are dropped.
+const MAX_LINES: usize = 65536;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct LineVertex {
+    /// In world space.
+    position: glam::Vec3,
+    color: glam::Vec4,
+}
+
+impl LineVertex {
+    fn get_binding_description() -> Vec<vk::VertexInputBindingDescription> {
+        vec![vk::VertexInputBindingDescription::default()
+            .binding(0)
+            .stride(size_of::<Self>() as u32)
+            .input_rate(vk::VertexInputRate::VERTEX)]
+    }
+
+    fn get_attribute_descriptions() -> Vec<vk::VertexInputAttributeDescription> {
+        [
+            (offset_of!(Self, position), vk::Format::R32G32B32_SFLOAT),
+            (offset_of!(Self, color), vk::Format::R32G32B32A32_SFLOAT),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(location, (offset, format))| {
+            vk::VertexInputAttributeDescription::default()
+                .binding(0)
+                .location(location as u32)
+                .format(format)
+                .offset(offset as u32)
+        })
+        .collect()
+    }
+}
+
+struct PerFrameData {
+    vertex_buffer: Buffer,
+    vertex_count: u32,
+    view_proj: glam::Mat4,
+    /// Views the render output was made for, only recreated when the targets change.
+    target_views: (vk::ImageView, vk::ImageView),
+    render_output: Option<RenderOutput>,
+}
+
+/// Draws lines over the `MeshPainter` output for debugging, like bounding boxes, normals or
+/// a camera's frustum. Lines are hidden behind meshes by testing against the mesh pass depth,
+/// which needs a `MeshPainter` created with `AttachmentStoreOp::STORE`, and don't write depth
+/// themselves. Queue lines with `line`, `aabb` or `frustum`, then write them with
+/// `update_inputs` and draw them with `draw_commands`.
+pub struct DebugDraw {
+    painter: Arc<Painter>,
+    pipeline: SingePassRenderPipeline,
+    per_frame_datas: Vec<PerFrameData>,
+    queued_vertices: Vec<LineVertex>,
+    allocator: GAllocator,
+}
+
+impl DebugDraw {
+    /// `depth_compare_op` has to be the mesh pass's, see `MeshPainter::depth_compare_op`.
+    pub fn new(
+        painter: Arc<Painter>,
+        frame_count: usize,
+        color_format: vk::Format,
+        depth_format: vk::Format,
+        depth_compare_op: vk::CompareOp,
+    ) -> Result<Self, String> {
+        let pipeline = RenderPipelineBuilder::new()
+            // Lines are drawn over what the mesh pass rendered, tested against its depth
+            .color_attachment(
+                color_format,
+                vk::AttachmentLoadOp::LOAD,
+                vk::AttachmentStoreOp::STORE,
+            )
+            .depth_attachment(
+                depth_format,
+                vk::AttachmentLoadOp::LOAD,
+                vk::AttachmentStoreOp::DONT_CARE,
+            )
+            .push_constants(
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                size_of::<glam::Mat4>() as u32,
+            )
+            .vertex_input(
+                LineVertex::get_binding_description(),
+                LineVertex::get_attribute_descriptions(),
+            )
+            .shaders(VERTEX_SHADER_CODE, FRAGMENT_SHADER_CODE)
+            .depth_test(depth_compare_op, false)
+            .topology(vk::PrimitiveTopology::LINE_LIST)
+            .build(painter.clone())
+            .map_err(|e| format!("at create debug line pipeline: {e}"))?;
+
+        let mut allocator =
+            GAllocator::new(painter.clone()).map_err(|e| format!("at create allocator: {e}"))?;
+
+        let per_frame_datas = (0..frame_count)
+            .map(|_| {
+                let vertex_buffer = painter
+                    .create_buffer(
+                        (MAX_LINES * 2 * size_of::<LineVertex>()) as u64,
+                        vk::BufferUsageFlags::VERTEX_BUFFER,
+                        Some(&mut allocator),
+                        Some(true),
+                    )
+                    .map_err(|e| format!("at create debug line vertex buffer: {e}"))?;
+                Ok(PerFrameData {
+                    vertex_buffer,
+                    vertex_count: 0,
+                    view_proj: glam::Mat4::IDENTITY,
+                    target_views: (vk::ImageView::null(), vk::ImageView::null()),
+                    render_output: None,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Self {
+            painter,
+            pipeline,
+            per_frame_datas,
+            queued_vertices: vec![],
+            allocator,
+        })
+    }
+
+    /// Queues a line between two world space points for the next `update_inputs`.
+    pub fn line(&mut self, a: glam::Vec3, b: glam::Vec3, color: glam::Vec4) {
+        self.queued_vertices.extend([
+            LineVertex { position: a, color },
+            LineVertex { position: b, color },
+        ]);
+    }
+
+    /// Queues the 12 edges of an axis aligned box.
+    pub fn aabb(&mut self, min: glam::Vec3, max: glam::Vec3, color: glam::Vec4) {
+        let corner = |i: usize| {
+            glam::vec3(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            )
+        };
+        self.box_edges(std::array::from_fn(corner), color);
+    }
+
+    /// Queues the edges of the volume `view_proj` sees, e.g. another camera's. Assumes a
+    /// `[0, 1]` depth range like `Camera::frustum_planes`.
+    pub fn frustum(&mut self, view_proj: glam::Mat4, color: glam::Vec4) {
+        let inverse = view_proj.inverse();
+        let corner = |i: usize| {
+            inverse.project_point3(glam::vec3(
+                if i & 1 == 0 { -1.0 } else { 1.0 },
+                if i & 2 == 0 { -1.0 } else { 1.0 },
+                if i & 4 == 0 { 0.0 } else { 1.0 },
+            ))
+        };
+        self.box_edges(std::array::from_fn(corner), color);
+    }
+
+    /// Corners are indexed by their x, y and z sides as the bits 1, 2 and 4, edges join
+    /// corners differing in one bit.
+    fn box_edges(&mut self, corners: [glam::Vec3; 8], color: glam::Vec4) {
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(corners[i], corners[i | bit], color);
+                }
+            }
+        }
+    }
+
+    /// Writes the lines queued since the last call into this frame's vertex buffer, seen
+    /// through `view_proj`. Call once the frame's previous submission has completed.
+    pub fn update_inputs(
+        &mut self,
+        frame_number: usize,
+        view_proj: glam::Mat4,
+    ) -> Result<(), String> {
+        let frame_number = frame_number % self.per_frame_datas.len();
+        let per_frame_data = &mut self.per_frame_datas[frame_number];
+        self.queued_vertices.truncate(MAX_LINES * 2);
+        unsafe {
+            per_frame_data
+                .vertex_buffer
+                .write_to_mem(self.queued_vertices.as_slice().align_to::<u8>().1)
+                .map_err(|e| format!("at write debug line vertices: {e}"))?;
+        }
+        per_frame_data.vertex_count = self.queued_vertices.len() as u32;
+        per_frame_data.view_proj = view_proj;
+        self.queued_vertices.clear();
+        Ok(())
+    }
+
+    /// Points this frame's draw at the mesh pass's color and depth images. Call before
+    /// recording the frame, once its previous submission has completed.
+    pub fn set_target(
+        &mut self,
+        frame_number: usize,
+        color: &Image2d,
+        depth: &Image2d,
+    ) -> Result<(), String> {
+        let frame_number = frame_number % self.per_frame_datas.len();
+        let per_frame_data = &mut self.per_frame_datas[frame_number];
+        let target_views = (color.image_view, depth.image_view);
+        if per_frame_data.target_views == target_views {
+            return Ok(());
+        }
+        per_frame_data.render_output = Some(
+            self.pipeline
+                .create_render_output(vec![color, depth])
+                .map_err(|e| format!("at create debug line render output: {e}"))?,
+        );
+        per_frame_data.target_views = target_views;
+        Ok(())
+    }
+
+    /// The targets set with `set_target` have to be written earlier in the same recording.
+    pub fn draw_commands(&self, frame_number: usize) -> Vec<GpuCommand<'_>> {
+        let per_frame_data = &self.per_frame_datas[frame_number % self.per_frame_datas.len()];
+        let Some(render_output) = &per_frame_data.render_output else {
+            return vec![];
+        };
+        if per_frame_data.vertex_count == 0 {
+            return vec![];
+        }
+        vec![GpuCommand::RunRenderPass {
+            render_pass: self.pipeline.render_pass,
+            render_output,
+            clear_values: vec![],
+            pipelines: vec![self.pipeline.pipeline],
+            pipeline_layouts: vec![self.pipeline.pipeline_layout],
+            commands: vec![
+                GpuRenderPassCommand::BindPipeline { pipeline: 0 },
+                GpuRenderPassCommand::SetPushConstant {
+                    pipeline_layout: 0,
+                    stage_flags: vk::ShaderStageFlags::VERTEX,
+                    offset: 0,
+                    data: per_frame_data
+                        .view_proj
+                        .to_cols_array()
+                        .iter()
+                        .flat_map(|value| value.to_ne_bytes())
+                        .collect(),
+                },
+                GpuRenderPassCommand::BindVertexBuffers {
+                    buffers: vec![(&per_frame_data.vertex_buffer, 0)],
+                },
+                GpuRenderPassCommand::DrawVertices {
+                    count: per_frame_data.vertex_count,
+                    first_vertex: 0,
+                },
+            ],
+        }]
+    }
+}
+
+impl Drop for DebugDraw {
+    fn drop(&mut self) {
+        let _ = self
+            .painter
+            .wait_idle()
+            .inspect_err(|e| eprintln!("at wait for device idle: {e}"));
+    }
+}
@@ -1,4 +1,7 @@
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 mod renderables;
 mod renderers;
@@ -6,7 +9,7 @@ mod scene_elements;
 mod swapchain_manager;
 
 use ash::vk;
-use renderables::mesh::Vertex;
+use renderables::mesh::{Indices, Vertex};
 use winit::{application::ApplicationHandler, event::WindowEvent, event_loop, window::{Window, WindowAttributes}};
 
 use crate::swapchain_manager::SwapchainManager;
@@ -17,26 +20,30 @@ fn square_verts() -> Vec<Vertex> {
             position: glam::vec4(-0.5, -0.5, 0.0, 1.0),
             normal: glam::vec4(0.0, 0.0, 1.0, 0.0),
             tex_coords: glam::vec4(0.0, 0.0, 0.0, 0.0),
+            tangent: glam::Vec4::ZERO,
         },
         Vertex {
             position: glam::vec4(0.5, -0.5, 0.0, 1.0),
             normal: glam::vec4(0.0, 0.0, 1.0, 0.0),
             tex_coords: glam::vec4(1.0, 0.0, 0.0, 0.0),
+            tangent: glam::Vec4::ZERO,
         },
         Vertex {
             position: glam::vec4(0.5, 0.5, 0.0, 1.0),
             normal: glam::vec4(0.0, 0.0, 1.0, 0.0),
             tex_coords: glam::vec4(1.0, 1.0, 0.0, 0.0),
+            tangent: glam::Vec4::ZERO,
         },
         Vertex {
             position: glam::vec4(-0.5, 0.5, 0.0, 1.0),
             normal: glam::vec4(0.0, 0.0, 1.0, 0.0),
             tex_coords: glam::vec4(0.0, 1.0, 0.0, 0.0),
+            tangent: glam::Vec4::ZERO,
         },
     ]
 }
 
-fn square_indices() -> Vec<u32> {
+fn square_indices() -> Vec<u16> {
     vec![0, 1, 2, 2, 3, 0]
 }
 
@@ -44,37 +51,98 @@ pub struct Canvas {
     painter: Arc<Painter>,
     swapchain_manager: SwapchainManager,
     mesh_painter: MeshPainter,
+    post_process_pass: PostProcessPass,
+    text_painter: TextPainter,
+    debug_draw: DebugDraw,
+    tone_map_pass: ToneMapPass,
     drawables: Vec<DrawableMeshAndTexture>,
     command_pool: CommandPool,
     command_buffers: Vec<CommandBuffer>,
     draw_complete_semaphores: Vec<vk::Semaphore>,
     draw_complete_fences: Vec<vk::Fence>,
-    upload_command_buffer: CommandBuffer,
-    acquire_image_fence: vk::Fence,
+    /// Only used by `Sheets` while rebuilding the swapchain, which waits for its own
+    /// submission before returning.
+    upload_command_pool: CommandPool,
+    /// Semaphore the acquire of each swapchain image signalled, waited on by its draw submit.
+    acquire_image_gpu_futs: Vec<GpuFuture>,
+    /// Passed to the next acquire, then swapped with the acquired image's slot. The one
+    /// swapped out was last waited on by that image's previous draw, which has completed by
+    /// the time the image is acquired again and its draw fence is waited on.
+    spare_acquire_image_gpu_fut: GpuFuture,
+    /// Start and end of the mesh pass, one pair per frame in flight.
+    mesh_pass_timestamps: Vec<TimestampQueries>,
+    /// Unwritten queries must not be read, so only frames that were submitted get resolved.
+    mesh_pass_timestamps_written: Vec<bool>,
+    mesh_pass_gpu_ms: Option<f64>,
 }
 
 impl Canvas {
     pub fn new(window: Window) -> Result<Self, String> {
         let painter = Arc::new(Painter::new(window).map_err(|e| e.to_string())?);
 
-        let command_pool = CommandPool::new(painter.clone())
+        let command_pool = painter
+            .create_command_pool(CommandPoolKind::Persistent)
             .map_err(|e| format!("at create command pool: {e}"))?;
 
-        let mut upload_command_buffer = command_pool
-            .allocate_command_buffers(1)
-            .map_err(|e| format!("at allocate upload command buffer: {e}"))?
-            .swap_remove(0);
+        let upload_command_pool = painter
+            .create_command_pool(CommandPoolKind::Transient)
+            .map_err(|e| format!("at create upload command pool: {e}"))?;
 
-        let sheets = Sheets::new(painter.clone(), &mut upload_command_buffer)?;
+        let sheets = Sheets::new(painter.clone(), &upload_command_pool, 3, false)
+            .map_err(|e| format!("at create sheets: {e}"))?;
 
+        // Offscreen targets are upright, the tone map pass rotates them onto the sheets
         let mut mesh_painter = MeshPainter::new(
             painter.clone(),
-            sheets.surface_resolution,
+            sheets.logical_extent(),
             sheets.swapchain_images.len(),
+            vk::Format::R8G8B8A8_UNORM,
+            1.0,
+            false,
+            vk::AttachmentLoadOp::CLEAR,
+            // Debug lines are depth tested against the mesh pass
+            Some(vk::AttachmentStoreOp::STORE),
         )?;
 
-        let command_buffers = command_pool
-            .allocate_command_buffers(sheets.swapchain_images.len())
+        let post_process_pass = PostProcessPass::new(
+            painter.clone(),
+            sheets.logical_extent(),
+            sheets.swapchain_images.len(),
+            vk::Format::R8G8B8A8_UNORM,
+            PASSTHROUGH_FRAGMENT_SHADER_CODE,
+        )
+        .map_err(|e| format!("at create post process pass: {e}"))?;
+
+        let text_painter = TextPainter::new(
+            painter.clone(),
+            sheets.swapchain_images.len(),
+            vk::Format::R8G8B8A8_UNORM,
+        )
+        .map_err(|e| format!("at create text painter: {e}"))?;
+
+        let mesh_depth_format = mesh_painter
+            .get_depth_image(0)
+            .ok_or("at create debug draw: mesh painter has no depth image".to_string())?
+            .format;
+        let debug_draw = DebugDraw::new(
+            painter.clone(),
+            sheets.swapchain_images.len(),
+            vk::Format::R8G8B8A8_UNORM,
+            mesh_depth_format,
+            mesh_painter.depth_compare_op(),
+        )
+        .map_err(|e| format!("at create debug draw: {e}"))?;
+
+        let tone_map_pass = ToneMapPass::new(
+            painter.clone(),
+            sheets.swapchain_images.len(),
+            sheets.surface_format.format,
+            sheets.is_srgb(),
+        )
+        .map_err(|e| format!("at create tone map pass: {e}"))?;
+
+        let command_buffers = painter
+            .allocate_command_buffers(&command_pool, sheets.swapchain_images.len())
             .map_err(|e| format!("at allocate command buffers: {e}"))?;
 
         let draw_complete_semaphores = (0..sheets.swapchain_images.len())
@@ -91,39 +159,97 @@ impl Canvas {
             })
             .collect::<Result<Vec<_>, String>>()?;
 
-        let acquire_image_future = CpuFuture::new(painter.clone(), false)
-            .map_err(|e| format!("at create acquire image future: {e}"))?;
+        let acquire_image_semaphores = (0..sheets.swapchain_images.len())
+            .map(|_| {
+                GpuFuture::new(painter.clone())
+                    .map_err(|e| format!("at create acquire image semaphore: {e}"))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
 
-        let square_mesh = mesh_painter.add_mesh(square_verts(), square_indices());
-        let default_texture = mesh_painter
-            .add_texture("textures/default.png")
-            .map_err(|e| format!("at add default texture: {e}"))?;
+        let spare_acquire_image_semaphore = GpuFuture::new(painter.clone())
+            .map_err(|e| format!("at create acquire image semaphore: {e}"))?;
+
+        let mesh_pass_timestamps = (0..sheets.swapchain_images.len())
+            .map(|_| {
+                painter
+                    .create_timestamp_queries(2)
+                    .map_err(|e| format!("at create mesh pass timestamp queries: {e}"))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let square_mesh = mesh_painter.add_mesh(square_verts(), Indices::U16(square_indices()));
+        let default_material =
+            mesh_painter.add_material(Material::new(mesh_painter.fallback_texture()));
         Ok(Self {
             painter,
             sheets,
             mesh_painter,
-            drawables: vec![DrawableMeshAndTexture {
-                mesh_name: square_mesh,
-                texture_name: default_texture,
-            }],
+            post_process_pass,
+            text_painter,
+            debug_draw,
+            tone_map_pass,
+            drawables: vec![DrawableMeshAndTexture::new(square_mesh, default_material)],
             command_pool,
             command_buffers,
             draw_complete_gpu_futs: draw_complete_semaphores,
             draw_complete_cpu_futs: draw_complete_fences,
-            upload_command_buffer,
-            acquire_image_cpu_fut: acquire_image_future,
+            upload_command_pool,
+            acquire_image_gpu_futs: acquire_image_semaphores,
+            spare_acquire_image_gpu_fut: spare_acquire_image_semaphore,
+            mesh_pass_timestamps_written: vec![false; mesh_pass_timestamps.len()],
+            mesh_pass_timestamps,
+            mesh_pass_gpu_ms: None,
         })
     }
 
+    /// Lines queued here are drawn over the next painted frame.
+    pub fn debug_draw(&mut self) -> &mut DebugDraw {
+        &mut self.debug_draw
+    }
+
+    /// Applied when copying each frame to its sheet. Starts as `ToneMapMode::None` with an
+    /// exposure of 1.0, which only clamps.
+    pub fn set_tone_map(&mut self, mode: ToneMapMode, exposure: f32) {
+        self.tone_map_pass.set_mode(mode);
+        self.tone_map_pass.set_exposure(exposure);
+    }
+
+    /// Does nothing while the window is minimized.
     pub fn paint(&mut self) -> Result<(), String> {
-        // Wait till next image is available
-        let frame_num = self
+        self.paint_with_overlay(|_, _, _| {})
+    }
+
+    /// Like `paint`, but calls `overlay` after the scene is on the sheet and before it is
+    /// presented, recording into the frame's command buffer. `overlay` gets the buffer, the
+    /// frame index and the sheet. Commands appended with `Painter::append_cmd_buffer` get
+    /// their image transitions tracked. Raw Vulkan commands recorded into
+    /// `CommandBuffer::command_buffer` have to leave the sheet in `ImageAccess::TransferWrite`,
+    /// the access the scene leaves it in.
+    pub fn paint_with_overlay(
+        &mut self,
+        overlay: impl FnOnce(&mut CommandBuffer, usize, &Image2d),
+    ) -> Result<(), String> {
+        // The image may still be in use by the presentation engine, the draw submit waits on
+        // the acquire semaphore instead of stalling here
+        let Some(frame) = self
             .sheets
-            .acquire_next_image(None, Some(&self.acquire_image_cpu_fut), &mut self.upload_command_buffer)
-            .map_err(|e| format!("at acquire next image: {e}"))?;
-        self.acquire_image_cpu_fut
-            .wait_and_reset()
-            .map_err(|e| format!("at wait for acquire image future: {e}"))?;
+            .acquire_next_image(
+                &self.painter,
+                Some(&self.spare_acquire_image_gpu_fut),
+                None,
+                &self.upload_command_pool,
+            )
+            .map_err(|e| format!("at acquire next image: {e}"))?
+        else {
+            // Minimized, nothing to paint until the window is restored
+            return Ok(());
+        };
+        let frame_num = frame.image_index();
+        std::mem::swap(
+            &mut self.spare_acquire_image_gpu_fut,
+            &mut self.acquire_image_gpu_futs[frame_num as usize],
+        );
+        let acquire_image_gpu_fut = &self.acquire_image_gpu_futs[frame_num as usize];
 
         let draw_complete_gpu_fut = &self.draw_complete_gpu_futs[frame_num as usize];
         let draw_complete_cpu_fut = &self.draw_complete_cpu_futs[frame_num as usize];
@@ -132,86 +258,276 @@ impl Canvas {
             .wait_and_reset()
             .map_err(|e| format!("at wait for draw complete cpu future: {e}"))?;
 
+        let mesh_pass_timestamps = &self.mesh_pass_timestamps[frame_num as usize];
+        if self.mesh_pass_timestamps_written[frame_num as usize] {
+            if let Some(timestamps) = self
+                .painter
+                .resolve_timestamps(mesh_pass_timestamps)
+                .map_err(|e| format!("at resolve mesh pass timestamps: {e}"))?
+            {
+                self.mesh_pass_gpu_ms = Some((timestamps[1] - timestamps[0]) / 1_000_000.0);
+            }
+        }
+
         let cam_data = CamData::new(
             glam::vec4(0.0, 0.0, 1.0, 1.0),
             glam::vec4(0.0, 0.0, 0.0, 0.0),
+            0.1,
+            100.0,
         );
 
         // self.command_buffers[frame_num as usize]
         //     .reset()
         //     .map_err(|e| format!("at reset command buffer: {e}"))?;
 
+        let view_proj = cam_data.view_proj;
         self.mesh_painter
             .update_inputs(frame_num as usize, &self.drawables, cam_data)
             .map_err(|e| format!("at update vb and ib: {e}"))?;
 
         let mesh_render_image = self.mesh_painter.get_rendered_image(frame_num as usize);
-        let sheet = &self.sheets.swapchain_images[frame_num as usize];
-
-        let commands = vec![
-            GpuCommand::ImageAccessHint {
-                image: mesh_render_image,
-                access: ImageAccess::TransferRead,
+        let mesh_depth_image = self
+            .mesh_painter
+            .get_depth_image(frame_num as usize)
+            .ok_or("at debug draw: mesh painter has no depth image".to_string())?;
+        self.debug_draw
+            .update_inputs(frame_num as usize, view_proj)
+            .map_err(|e| format!("at update debug line inputs: {e}"))?;
+        self.debug_draw
+            .set_target(frame_num as usize, mesh_render_image, mesh_depth_image)
+            .map_err(|e| format!("at set debug line target: {e}"))?;
+        self.post_process_pass
+            .set_input(frame_num as usize, mesh_render_image);
+        let sheet = frame.image();
+
+        let post_process_output = self.post_process_pass.get_output_image(frame_num as usize);
+        // The display may have rotated since the last frame's swapchain rebuild
+        self.tone_map_pass
+            .set_quarter_turns(self.sheets.quarter_turns());
+        self.tone_map_pass
+            .set_images(frame_num as usize, post_process_output, sheet)
+            .map_err(|e| format!("at set tone map images: {e}"))?;
+
+        if let Some(mesh_pass_gpu_ms) = self.mesh_pass_gpu_ms {
+            self.text_painter.draw_text(
+                &format!("mesh pass: {mesh_pass_gpu_ms:.2} ms"),
+                8.0,
+                8.0,
+                2.0,
+                glam::Vec4::ONE,
+            );
+        }
+        self.text_painter
+            .update_inputs(frame_num as usize)
+            .map_err(|e| format!("at update text inputs: {e}"))?;
+        self.text_painter
+            .set_target(frame_num as usize, post_process_output)
+            .map_err(|e| format!("at set text target: {e}"))?;
+
+        let mut mesh_pass_commands = vec![
+            GpuCommand::ResetQueryPool {
+                query_pool: mesh_pass_timestamps.query_pool,
+                count: mesh_pass_timestamps.count,
             },
-            GpuCommand::ImageAccessHint {
-                image: mesh_render_image,
-                access: ImageAccess::PipelineAttachment,
+            GpuCommand::WriteTimestamp {
+                query_pool: mesh_pass_timestamps.query_pool,
+                index: 0,
+                stage: vk::PipelineStageFlags::TOP_OF_PIPE,
             },
+        ];
+        mesh_pass_commands.extend(
             self.mesh_painter
-                .draw_meshes_command(frame_num as usize)
+                .draw_meshes_commands(frame_num as usize)
                 .map_err(|e| format!("at draw meshes: {e}"))?,
-            GpuCommand::ImageAccessHint {
-                image: sheet,
-                access: ImageAccess::Present,
-            },
-            GpuCommand::BlitFullImage {
-                src: mesh_render_image,
-                dst: sheet,
-            },
-            GpuCommand::ImageAccessHint {
-                image: sheet,
-                access: ImageAccess::Present,
-            },
-        ];
-        self.command_buffers[frame_num as usize]
-            .record(&commands, false)
-            .map_err(|e| format!("at command buffer record: {e}"))?;
+        );
+        mesh_pass_commands.push(GpuCommand::WriteTimestamp {
+            query_pool: mesh_pass_timestamps.query_pool,
+            index: 1,
+            stage: vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+        });
+
+        let mut frame_graph = FrameGraph::new();
+        frame_graph.add_pass(
+            "mesh",
+            vec![],
+            vec![(mesh_render_image, ImageAccess::PipelineAttachment)],
+            mesh_pass_commands,
+        );
+        frame_graph.add_pass(
+            "debug lines",
+            vec![
+                (mesh_render_image, ImageAccess::PipelineAttachment),
+                (mesh_depth_image, ImageAccess::PipelineAttachment),
+            ],
+            vec![(mesh_render_image, ImageAccess::PipelineAttachment)],
+            self.debug_draw.draw_commands(frame_num as usize),
+        );
+        frame_graph.add_pass(
+            "post process",
+            vec![(mesh_render_image, ImageAccess::ShaderRead)],
+            vec![(post_process_output, ImageAccess::PipelineAttachment)],
+            self.post_process_pass
+                .draw_commands(frame_num as usize, mesh_render_image),
+        );
+        frame_graph.add_pass(
+            "text",
+            vec![(post_process_output, ImageAccess::PipelineAttachment)],
+            vec![(post_process_output, ImageAccess::PipelineAttachment)],
+            self.text_painter.draw_commands(frame_num as usize),
+        );
+        frame_graph.add_pass(
+            "tone map to sheet",
+            vec![(post_process_output, ImageAccess::ShaderRead)],
+            vec![(sheet, ImageAccess::PipelineAttachment)],
+            self.tone_map_pass
+                .draw_commands(frame_num as usize, post_process_output)
+                .map_err(|e| format!("at draw tone map: {e}"))?,
+        );
+        // Where overlays expect the sheet
+        frame_graph.add_output(sheet, ImageAccess::TransferWrite);
+        let commands = frame_graph
+            .build()
+            .map_err(|e| format!("at build frame graph: {e}"))?;
+
+        let command_buffer = &mut self.command_buffers[frame_num as usize];
+        self.painter
+            .begin_cmd_buffer(command_buffer, false)
+            .map_err(|e| format!("at command buffer begin: {e}"))?;
+        self.painter
+            .append_cmd_buffer(command_buffer, &commands)
+            .map_err(|e| format!("at command buffer append: {e}"))?;
+        overlay(command_buffer, frame_num as usize, sheet);
+        self.painter
+            .append_cmd_buffer(
+                command_buffer,
+                &[GpuCommand::ImageAccessHint {
+                    image: sheet,
+                    access: ImageAccess::Present,
+                }],
+            )
+            .map_err(|e| format!("at command buffer append: {e}"))?;
+        self.painter
+            .end_cmd_buffer(command_buffer)
+            .map_err(|e| format!("at command buffer end: {e}"))?;
 
         self.command_buffers[frame_num as usize]
             .submit(
                 &[draw_complete_gpu_fut],
-                &[],
-                &[],
+                &[acquire_image_gpu_fut],
+                // The sheet is first written as the tone map's color attachment, earlier passes
+                // don't touch it. Its first barrier waits on ALL_GRAPHICS, which chains with this
+                &[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT],
                 Some(&draw_complete_cpu_fut),
             )
             .map_err(|e| format!("at command buffer submit: {e}"))?;
+        self.mesh_pass_timestamps_written[frame_num as usize] = true;
 
-        self.sheets
-            .present_image(frame_num, &[draw_complete_gpu_fut])
+        frame
+            .present(&[draw_complete_gpu_fut])
             .map_err(|e| format!("at present image: {e}"))?;
         Ok(())
     }
+
+    /// GPU time of the mesh pass from the last frame whose results are available.
+    pub fn mesh_pass_gpu_ms(&self) -> Option<f64> {
+        self.mesh_pass_gpu_ms
+    }
+
+    /// Queues a `RedrawRequested` event for the window, frames are only painted there.
+    pub fn request_redraw(&self) {
+        self.painter.request_redraw();
+    }
+
+    /// Size of the sheets overlays draw on, changes when the swapchain is rebuilt.
+    pub fn sheet_extent(&self) -> vk::Extent2D {
+        self.sheets.surface_resolution
+    }
 }
 
 impl Drop for Canvas {
     fn drop(&mut self) {
-        unsafe {
-            self.painter
-                .device
-                .device_wait_idle()
-                .map_err(|e| eprintln!("at wait for device idle: {e}"))
-                .ok();
-        }
+        let _ = self
+            .painter
+            .wait_idle()
+            .inspect_err(|e| eprintln!("at wait for device idle: {e}"));
     }
 }
 
+/// When `Game` paints frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedrawMode {
+    /// Paints every event loop iteration, or as often as the frame cap allows.
+    Continuous,
+    /// Sleeps until there are events and paints only on `Game::request_redraw`, input and
+    /// resizes. For apps that don't animate, like editors or turn based games.
+    OnDemand,
+}
+
 pub struct Game {
     canvas: Option<Canvas>,
+    redraw_mode: RedrawMode,
+    /// `None` paints as often as the event loop spins.
+    frame_interval: Option<Duration>,
+    last_frame_start: Option<Instant>,
+    last_frame_time: Option<Duration>,
 }
 
 impl Game {
     pub fn new() -> Self {
-        Self { canvas: None }
+        Self {
+            canvas: None,
+            redraw_mode: RedrawMode::Continuous,
+            frame_interval: None,
+            last_frame_start: None,
+            last_frame_time: None,
+        }
+    }
+
+    /// Paints at most `fps` frames a second, sleeping until the next frame is due instead of
+    /// spinning the event loop. 0 leaves the frame rate uncapped.
+    pub fn with_frame_cap(fps: u32) -> Self {
+        Self {
+            frame_interval: (fps > 0).then(|| Duration::from_secs_f64(1.0 / fps as f64)),
+            ..Self::new()
+        }
+    }
+
+    /// Time between the starts of the last two frames.
+    pub fn frame_time(&self) -> Option<Duration> {
+        self.last_frame_time
+    }
+
+    /// Defaults to `RedrawMode::Continuous`. The frame cap only applies to it.
+    pub fn set_redraw_mode(&mut self, redraw_mode: RedrawMode) {
+        self.redraw_mode = redraw_mode;
+        // Wakes a loop waiting for events, so a continuous loop starts spinning again
+        self.request_redraw();
+    }
+
+    /// Paints a frame on the next event loop iteration, needed for anything to show up in
+    /// `RedrawMode::OnDemand`.
+    pub fn request_redraw(&self) {
+        if let Some(canvas) = self.canvas.as_ref() {
+            canvas.request_redraw();
+        }
+    }
+
+    fn on_input(&self) {
+        if self.redraw_mode == RedrawMode::OnDemand {
+            self.request_redraw();
+        }
+    }
+
+    fn paint(&mut self) {
+        let Some(canvas) = self.canvas.as_mut() else {
+            return;
+        };
+        let frame_start = Instant::now();
+        if let Some(last_frame_start) = self.last_frame_start {
+            self.last_frame_time = Some(frame_start - last_frame_start);
+        }
+        self.last_frame_start = Some(frame_start);
+        let _ = canvas.paint().inspect_err(|e| eprintln!("at paint: {e}"));
     }
 }
 
@@ -242,9 +558,7 @@ impl ApplicationHandler for Game {
         match event {
             WindowEvent::ActivationTokenDone { serial: _, token: _ } => {}
             WindowEvent::Resized(_physical_size) => {
-                self.canvas
-                    .as_mut()
-                    .map(|c| c.paint().inspect_err(|e| eprintln!("at paint: {e}")));
+                self.canvas.as_ref().map(|c| c.request_redraw());
             }
             WindowEvent::Moved(_physical_position) => {}
             WindowEvent::CloseRequested => {
@@ -259,7 +573,7 @@ impl ApplicationHandler for Game {
                 device_id: _,
                 event: _,
                 is_synthetic: _,
-            } => {}
+            } => self.on_input(),
             WindowEvent::ModifiersChanged(_modifiers) => {}
             WindowEvent::Ime(_ime) => {}
             WindowEvent::CursorMoved {
@@ -272,12 +586,12 @@ impl ApplicationHandler for Game {
                 device_id: _,
                 delta: _,
                 phase: _,
-            } => {}
+            } => self.on_input(),
             WindowEvent::MouseInput {
                 device_id: _,
                 state: _,
                 button: _,
-            } => {}
+            } => self.on_input(),
             WindowEvent::PinchGesture {
                 device_id: _,
                 delta: _,
@@ -304,25 +618,43 @@ impl ApplicationHandler for Game {
                 axis: _,
                 value: _,
             } => {}
-            WindowEvent::Touch(_touch) => {}
+            WindowEvent::Touch(_touch) => self.on_input(),
             WindowEvent::ScaleFactorChanged {
                 scale_factor: _,
                 inner_size_writer: _,
             } => {}
             WindowEvent::ThemeChanged(_theme) => {}
             WindowEvent::Occluded(_) => {}
-            WindowEvent::RedrawRequested => {
-                self.canvas
-                    .as_mut()
-                    .map(|c| c.paint().inspect_err(|e| eprintln!("at paint: {e}")));
-            }
+            WindowEvent::RedrawRequested => self.paint(),
         }
     }
 
-    fn about_to_wait(&mut self, _event_loop: &event_loop::ActiveEventLoop) {
-        self.canvas
-            .as_mut()
-            .map(|c| c.paint().inspect_err(|e| eprintln!("at paint: {e}")));
+    /// Only asks for the next frame, painting happens in `RedrawRequested` so each loop
+    /// iteration paints at most once.
+    fn about_to_wait(&mut self, event_loop: &event_loop::ActiveEventLoop) {
+        let Some(canvas) = self.canvas.as_ref() else {
+            return;
+        };
+        if self.redraw_mode == RedrawMode::OnDemand {
+            event_loop.set_control_flow(event_loop::ControlFlow::Wait);
+            return;
+        }
+        let Some(frame_interval) = self.frame_interval else {
+            event_loop.set_control_flow(event_loop::ControlFlow::Poll);
+            canvas.request_redraw();
+            return;
+        };
+        // Other events can wake the loop before the next frame is due
+        let frame_due = self
+            .last_frame_start
+            .is_none_or(|last_frame_start| last_frame_start.elapsed() >= frame_interval);
+        if frame_due {
+            canvas.request_redraw();
+        }
+        if let Some(last_frame_start) = self.last_frame_start {
+            let next_frame_start = last_frame_start + frame_interval;
+            event_loop.set_control_flow(event_loop::ControlFlow::WaitUntil(next_frame_start));
+        }
     }
 }
 
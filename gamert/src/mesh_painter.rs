@@ -1,135 +1,441 @@
-use std::{collections::HashMap, mem::offset_of, sync::Arc};
+use std::{
+    collections::HashSet,
+    mem::offset_of,
+    sync::{Arc, Mutex, mpsc},
+};
 
 use ash::vk;
 use glam::Vec4Swizzles;
 use include_bytes_aligned::include_bytes_aligned;
 use painter::{
-    ash, slotmap::{new_key_type, SlotMap}, GAllocator, Buffer, CommandBuffer, CommandPool, CpuFuture, GpuCommand, GpuRenderPassCommand, Image2d, ImageAccess, Painter, RenderOutput, ShaderInputAllocator, ShaderInputBindingInfo, ShaderInputType, SingePassRenderPipeline
+    ash, slotmap::{new_key_type, SlotMap}, GAllocator, Buffer, BufferAccess, CommandBuffer, CommandPool, CommandPoolKind, CpuFuture, GpuCommand, GpuRenderPassCommand, Image2d, ImageAccess, Painter, PipelineStateOverrides, RenderOutput, RenderPipelineBuilder, ShaderInputAllocator, ShaderInputBindingInfo, ShaderInputType, SingePassRenderPipeline
+};
+
+use crate::{
+    mip_generator::MipGenerator,
+    renderables::mesh::{Indices, Mesh, Vertex},
 };
 
 static VERTEX_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/mesh_painter.vert.spv");
 static FRAGMENT_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/mesh_painter.frag.spv");
 
+/// Upper bound on the texture array, lowered to what the device can bind.
 static MAX_TEXTURES: usize = 100;
+/// Smallest texture array worth running with, and the size texture sets start at. Vulkan
+/// guarantees at least 16 sampled images per stage, so only broken drivers report less.
+static MIN_TEXTURES: usize = 16;
+/// Descriptor pools are sized for the main target plus this many render targets.
+static MAX_RENDER_TARGETS: usize = 4;
+/// Size of the shader's sampler array, must match `MAX_SAMPLERS` in mesh_painter_common.glsl.
+pub const MAX_SAMPLERS: usize = 16;
+/// Drawn objects per frame the object buffer has room for.
+static MAX_OBJECTS: usize = 16384;
+/// Threads decoding images for `add_texture_async`, fewer on machines with fewer cores.
+static MAX_DECODE_THREADS: usize = 4;
+/// Point lights the scene buffer has room for, must match `MAX_POINT_LIGHTS` in
+/// mesh_painter_common.glsl.
+pub const MAX_POINT_LIGHTS: usize = 64;
+
+/// A light shining in every direction from `position`, fading out to nothing at `range`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointLight {
+    pub position: glam::Vec3,
+    /// Linear color, scaled by the light's intensity.
+    pub color: glam::Vec3,
+    pub range: f32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct GpuPointLight {
+    pos: glam::Vec4,
+    color: glam::Vec4,
+    /// `x` is the range.
+    props: glam::Vec4,
+}
 
 #[repr(C)]
 #[derive(Debug, Clone)]
 pub struct SceneDescriptorData {
     cam_data: CamData,
+    light_count: u32,
+    _pad: [u32; 3],
+    lights: [GpuPointLight; MAX_POINT_LIGHTS],
 }
 
+// Must match the std430 scene buffer block in mesh_painter_common.glsl
+const _: () = assert!(offset_of!(SceneDescriptorData, light_count) == 96);
+const _: () = assert!(offset_of!(SceneDescriptorData, lights) == 112);
+
 pub struct PerFrameData {
     descriptor_sets: Vec<vk::DescriptorSet>,
+    /// Slots in the texture set, grown when textures outnumber it.
+    texture_capacity: u32,
     vertex_buffer: Buffer,
     index_buffer: Buffer,
+    index_buffer_u16: Buffer,
     index_buffer_size: u32,
+    next_draw_params: Vec<ObjDrawParams>,
     scene_buffer: Buffer,
+    /// `GpuObjectFragmentInfo` of every drawn object, indexed by its `obj_id`.
+    object_buffer: Buffer,
+    /// Host visible mirror of the vertex, index, scene and object buffers, laid out back to
+    /// back. `None` when those buffers are host visible themselves.
+    staging_buffer: Option<Buffer>,
+    /// Bytes written to each staged buffer this frame, in `input_buffers` order.
+    staged_sizes: [vk::DeviceSize; 5],
     color_image: Image2d,
-    depth_image: Image2d,
+    /// `None` along with `depth_render_output` for painters made without depth.
+    depth_image: Option<Image2d>,
     render_output: RenderOutput,
+    depth_render_output: Option<RenderOutput>,
+    /// Textures added since this frame's texture descriptors were last written.
+    pending_texture_writes: Vec<TextureID>,
+    /// Set when samplers were added since this frame's sampler array was last written.
+    sampler_writes_pending: bool,
 }
 
 impl PerFrameData {
     pub fn new(
         pipeline: &SingePassRenderPipeline,
+        depth_pipeline: Option<&SingePassRenderPipeline>,
         allocator: &mut GAllocator,
         color_format: vk::Format,
-        depth_format: vk::Format,
+        depth_format: Option<vk::Format>,
         extent: vk::Extent2D,
         shader_input_allocator: &ShaderInputAllocator,
-        command_buffer: &mut CommandBuffer,
+        command_pool: &CommandPool,
+        direct_upload: bool,
+        samplers: &[vk::Sampler],
     ) -> Result<Self, String> {
-        let descriptor_sets = pipeline
-            .make_shader_inputs(shader_input_allocator)
-            .map_err(|e| format!("at make shader inputs: {e}"))?;
+        let descriptor_sets = vec![
+            pipeline
+                .make_shader_input(shader_input_allocator, 0, None)
+                .map_err(|e| format!("at make scene shader input: {e}"))?,
+            pipeline
+                .make_shader_input(shader_input_allocator, 1, Some(MIN_TEXTURES as u32))
+                .map_err(|e| format!("at make texture shader input: {e}"))?,
+        ];
         let painter = pipeline.painter.clone();
-        let vertex_buffer = Buffer::new_with_mem(
-            painter.clone(),
-            32 * 1024 * 1024,
-            vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
-            allocator,
-            false
-        )
+        let vertex_buffer = painter
+            .create_buffer(
+                32 * 1024 * 1024,
+                vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+                Some(allocator),
+                Some(direct_upload),
+            )
             .map_err(|e| format!("at create vertex buffer: {e}"))?;
 
-        let index_buffer = Buffer::new_with_mem(
-            painter.clone(),
-            4 * 1024 * 1024,
-            vk::BufferUsageFlags::INDEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
-            allocator,
-            false
-        )
-            .map_err(|e| format!("at create vertex buffer: {e}"))?;
+        let index_buffer = painter
+            .create_buffer(
+                4 * 1024 * 1024,
+                vk::BufferUsageFlags::INDEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+                Some(allocator),
+                Some(direct_upload),
+            )
+            .map_err(|e| format!("at create index buffer: {e}"))?;
 
-        let scene_buffer = Buffer::new_with_mem(
-            painter.clone(),
-            size_of::<SceneDescriptorData>() as _,
-            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
-            allocator,
-            false
-        )
-            .map_err(|e| format!("at create vertex buffer: {e}"))?;
+        let index_buffer_u16 = painter
+            .create_buffer(
+                2 * 1024 * 1024,
+                vk::BufferUsageFlags::INDEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+                Some(allocator),
+                Some(direct_upload),
+            )
+            .map_err(|e| format!("at create u16 index buffer: {e}"))?;
+
+        let scene_buffer = painter
+            .create_buffer(
+                size_of::<SceneDescriptorData>() as _,
+                vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+                Some(allocator),
+                Some(direct_upload),
+            )
+            .map_err(|e| format!("at create scene buffer: {e}"))?;
+
+        let object_buffer = painter
+            .create_buffer(
+                (MAX_OBJECTS * size_of::<GpuObjectFragmentInfo>()) as _,
+                vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+                Some(allocator),
+                Some(direct_upload),
+            )
+            .map_err(|e| format!("at create object buffer: {e}"))?;
+
+        let staging_buffer = if direct_upload {
+            None
+        } else {
+            let staging_size = vertex_buffer.size
+                + index_buffer.size
+                + index_buffer_u16.size
+                + scene_buffer.size
+                + object_buffer.size;
+            let staging_buffer = painter
+                .create_buffer(
+                    staging_size,
+                    vk::BufferUsageFlags::TRANSFER_SRC,
+                    Some(allocator),
+                    Some(true),
+                )
+                .map_err(|e| format!("at create staging buffer: {e}"))?;
+            Some(staging_buffer)
+        };
 
         let color_image = Image2d::new_with_mem(
             painter.clone(),
             color_format,
             extent,
-            vec![ImageAccess::PipelineAttachment, ImageAccess::TransferRead],
+            vec![
+                ImageAccess::PipelineAttachment,
+                ImageAccess::TransferRead,
+                ImageAccess::ShaderRead,
+            ],
             allocator,
             true
         )
             .map_err(|e| format!("at create color image: {e}"))?;
 
-        let depth_image = Image2d::new_with_mem(
-            painter.clone(),
-            depth_format,
-            extent,
-            vec![ImageAccess::PipelineAttachment],
-            allocator,
-            true
-        )
-            .map_err(|e| format!("at create depth image: {e}"))?;
+        let depth_image = depth_format
+            .map(|depth_format| {
+                Image2d::new_with_mem(
+                    painter.clone(),
+                    depth_format,
+                    extent,
+                    vec![ImageAccess::PipelineAttachment],
+                    allocator,
+                    true,
+                )
+                .map_err(|e| format!("at create depth image: {e}"))
+            })
+            .transpose()?;
 
-        let commands = vec![
+        // Color images start out cleared and in the access a `LOAD` pass expects them in, see
+        // `MeshPainter::new`
+        let mut commands = vec![
             GpuCommand::ImageAccessInit {
                 image: &color_image,
-                access: ImageAccess::TransferRead,
+                access: ImageAccess::TransferWrite,
             },
-            GpuCommand::ImageAccessInit {
-                image: &depth_image,
-                access: ImageAccess::PipelineAttachment,
+            GpuCommand::ClearColorImage {
+                image: &color_image,
+                color: vk::ClearColorValue::default(),
+            },
+            GpuCommand::ImageAccessHint {
+                image: &color_image,
+                access: ImageAccess::ShaderRead,
             },
         ];
+        if let Some(depth_image) = &depth_image {
+            commands.push(GpuCommand::ImageAccessInit {
+                image: depth_image,
+                access: ImageAccess::PipelineAttachment,
+            });
+        }
 
-        command_buffer
-            .record(&commands, true)
-            .map_err(|e| format!("at record command buffer: {e}"))?;
-
-        let fence = CpuFuture::new(pipeline.painter.clone(), false)
-            .map_err(|e| format!("at create fence: {e}"))?;
-        command_buffer
-            .submit(&[], &[], &[], Some(&fence))
-            .map_err(|e| format!("at submit command buffer: {e}"))?;
-        fence.wait().map_err(|e| format!("at fence wait: {e}"))?;
-        command_buffer
-            .reset()
-            .map_err(|e| format!("at reset command buffer: {e}"))?;
+        painter
+            .run_commands_blocking(command_pool, &commands)
+            .map_err(|e| format!("at init attachment layouts: {e}"))?;
 
         let render_output = pipeline
-            .create_render_output(vec![&color_image, &depth_image])
+            .create_render_output(
+                std::iter::once(&color_image)
+                    .chain(depth_image.as_ref())
+                    .collect(),
+            )
             .map_err(|e| format!("at create render output: {e}"))?;
+        let depth_render_output = depth_pipeline
+            .zip(depth_image.as_ref())
+            .map(|(depth_pipeline, depth_image)| {
+                depth_pipeline
+                    .create_render_output(vec![depth_image])
+                    .map_err(|e| format!("at create depth render output: {e}"))
+            })
+            .transpose()?;
+
+        // The scene and object buffers never change, textures and samplers are written per
+        // frame once added
+        unsafe {
+            painter.device.update_descriptor_sets(
+                &[
+                    vk::WriteDescriptorSet::default()
+                        .dst_set(descriptor_sets[0])
+                        .dst_binding(0)
+                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                        .descriptor_count(1)
+                        .buffer_info(&[vk::DescriptorBufferInfo::default()
+                            .buffer(scene_buffer.buffer)
+                            .range(vk::WHOLE_SIZE)]),
+                    vk::WriteDescriptorSet::default()
+                        .dst_set(descriptor_sets[0])
+                        .dst_binding(1)
+                        .descriptor_type(vk::DescriptorType::SAMPLER)
+                        .image_info(&sampler_image_infos(samplers)),
+                    vk::WriteDescriptorSet::default()
+                        .dst_set(descriptor_sets[0])
+                        .dst_binding(2)
+                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                        .descriptor_count(1)
+                        .buffer_info(&[vk::DescriptorBufferInfo::default()
+                            .buffer(object_buffer.buffer)
+                            .range(vk::WHOLE_SIZE)]),
+                ],
+                &[],
+            );
+        }
 
         Ok(Self {
             descriptor_sets,
+            texture_capacity: MIN_TEXTURES as u32,
             vertex_buffer,
             index_buffer,
+            index_buffer_u16,
             scene_buffer,
+            object_buffer,
+            staging_buffer,
+            staged_sizes: [0; 5],
             index_buffer_size: 0,
+            next_draw_params: vec![],
             color_image,
             depth_image,
             render_output,
+            depth_render_output,
+            pending_texture_writes: vec![],
+            sampler_writes_pending: false,
         })
     }
+
+    fn input_buffers(&self) -> [&Buffer; 5] {
+        [
+            &self.vertex_buffer,
+            &self.index_buffer,
+            &self.index_buffer_u16,
+            &self.scene_buffer,
+            &self.object_buffer,
+        ]
+    }
+
+    /// Writes each entry of `datas` to the matching buffer from `input_buffers`, either
+    /// directly or into the staging buffer for `upload_commands` to copy.
+    fn write_inputs(&mut self, datas: [&[u8]; 5]) -> Result<(), String> {
+        let Self {
+            vertex_buffer,
+            index_buffer,
+            index_buffer_u16,
+            scene_buffer,
+            object_buffer,
+            staging_buffer,
+            staged_sizes,
+            ..
+        } = self;
+        let buffers = [
+            vertex_buffer,
+            index_buffer,
+            index_buffer_u16,
+            scene_buffer,
+            object_buffer,
+        ];
+        let mut staging_offset = 0;
+        for (i, (buffer, data)) in buffers.into_iter().zip(datas).enumerate() {
+            if data.len() as u64 > buffer.size {
+                return Err(format!(
+                    "{} bytes don't fit in input buffer {i} of {} bytes",
+                    data.len(),
+                    buffer.size
+                ));
+            }
+            match staging_buffer.as_mut() {
+                Some(staging_buffer) => {
+                    staging_buffer
+                        .write_to_mem_at(staging_offset as usize, data)
+                        .map_err(|e| format!("at write to staging buffer mem: {e}"))?;
+                    staged_sizes[i] = data.len() as u64;
+                }
+                None => buffer
+                    .write_to_mem(data)
+                    .map_err(|e| format!("at write to input buffer mem: {e}"))?,
+            }
+            staging_offset += buffer.size;
+        }
+        Ok(())
+    }
+
+    fn upload_commands(&self) -> Vec<GpuCommand> {
+        let Some(staging_buffer) = &self.staging_buffer else {
+            return vec![];
+        };
+        let mut commands = vec![];
+        let mut staging_offset = 0;
+        for (buffer, &size) in self.input_buffers().into_iter().zip(&self.staged_sizes) {
+            if size > 0 {
+                commands.push(GpuCommand::CopyBuffer {
+                    src: staging_buffer,
+                    dst: buffer,
+                    size,
+                    src_offset: staging_offset,
+                    dst_offset: 0,
+                });
+            }
+            staging_offset += buffer.size;
+        }
+        // Read through shader inputs, which the recording can't see. Vertex and index
+        // buffers are tracked through the render pass binding them
+        for buffer in [&self.scene_buffer, &self.object_buffer] {
+            commands.push(GpuCommand::BufferAccessHint {
+                buffer,
+                access: BufferAccess::ShaderRead,
+            });
+        }
+        commands
+    }
+}
+
+/// Descriptors for the whole sampler array, `samplers` in slot order.
+fn sampler_image_infos(samplers: &[vk::Sampler]) -> Vec<vk::DescriptorImageInfo> {
+    samplers
+        .iter()
+        .map(|&sampler| vk::DescriptorImageInfo::default().sampler(sampler))
+        .collect()
+}
+
+/// How a sampler added with `MeshPainter::add_sampler` handles UVs outside 0 to 1. Filtering
+/// is the same for every sampler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SamplerInfo {
+    pub address_mode_u: vk::SamplerAddressMode,
+    pub address_mode_v: vk::SamplerAddressMode,
+    /// Only used by `CLAMP_TO_BORDER`.
+    pub border_color: vk::BorderColor,
+}
+
+impl Default for SamplerInfo {
+    /// Repeats the texture, what the default sampler does.
+    fn default() -> Self {
+        Self {
+            address_mode_u: vk::SamplerAddressMode::REPEAT,
+            address_mode_v: vk::SamplerAddressMode::REPEAT,
+            border_color: vk::BorderColor::FLOAT_TRANSPARENT_BLACK,
+        }
+    }
+}
+
+impl SamplerInfo {
+    /// For UI sprites and skybox faces, where repeating bleeds the opposite edge in.
+    pub fn clamp_to_edge() -> Self {
+        Self {
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            ..Self::default()
+        }
+    }
+
+    /// For decals, showing `border_color` outside the texture.
+    pub fn clamp_to_border(border_color: vk::BorderColor) -> Self {
+        Self {
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_BORDER,
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_BORDER,
+            border_color,
+        }
+    }
 }
 
 #[repr(C)]
@@ -139,25 +445,126 @@ pub enum SamplingMode {
     X4 = 1,
 }
 
+/// Whether `update_inputs` orders draws by distance to the camera.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransparencyMode {
+    /// Draws in the order given, transparent drawables may blend in the wrong order.
+    Unsorted,
+    /// Opaque drawables front to back, then transparent ones back to front.
+    Sorted,
+}
+
+/// Scalar inputs of a material, combined with its textures when shading.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MaterialParams {
+    /// Multiplies the albedo texture.
+    pub base_color: glam::Vec4,
+    pub metallic: f32,
+    pub roughness: f32,
+}
+
+impl Default for MaterialParams {
+    fn default() -> Self {
+        Self {
+            base_color: glam::Vec4::ONE,
+            metallic: 0.0,
+            roughness: 1.0,
+        }
+    }
+}
+
+/// Textures and parameters shared by every drawable using it, see `MeshPainter::add_material`.
+#[derive(Debug, Clone, Copy)]
+pub struct Material {
+    pub albedo: TextureID,
+    /// Tangent space normal map, added as `TextureKind::Data`.
+    pub normal: Option<TextureID>,
+    pub params: MaterialParams,
+}
+
+impl Material {
+    pub fn new(albedo: TextureID) -> Self {
+        Self {
+            albedo,
+            normal: None,
+            params: MaterialParams::default(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct DrawableMeshAndTexture {
     pub mesh_name: MeshID,
-    pub texture_name: TextureID,
+    pub material: MaterialID,
+    /// Multiplies the material's base color, for per object variations.
+    pub tint: glam::Vec4,
+    /// Mesh to world space.
+    pub transform: glam::Mat4,
+    /// Alpha blended without writing depth. Drawn after opaque drawables, farthest first,
+    /// with `TransparencyMode::Sorted`.
+    pub transparent: bool,
+    /// Overrides the sampler of the material's textures, see `MeshPainter::set_texture_sampler`.
+    pub sampler: Option<SamplerID>,
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct GpuObjectInfo {
+impl DrawableMeshAndTexture {
+    pub fn new(mesh_name: MeshID, material: MaterialID) -> Self {
+        Self {
+            mesh_name,
+            material,
+            tint: glam::Vec4::ONE,
+            transform: glam::Mat4::IDENTITY,
+            transparent: false,
+            sampler: None,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GpuObjectVertexInfo {
+    pub model: glam::Mat4,
     pub obj_id: u32,
     pub mesh_id: u32,
+}
+
+/// An object's material, stored in the object buffer at its `obj_id`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GpuObjectFragmentInfo {
+    /// The drawable's tint times the material's base color.
+    pub tint: glam::Vec4,
     pub texture_id: u32,
+    /// `NO_TEXTURE` when the object has no normal map.
+    pub normal_texture_id: u32,
+    pub metallic: f32,
+    pub roughness: f32,
+    /// Slot of the sampler every texture of the object is sampled with.
+    pub sampler_id: u32,
+    pub _pad: [u32; 3],
 }
 
+pub const NO_TEXTURE: u32 = u32::MAX;
+
+// Must match the push constant block in mesh_painter.vert and the std430 `ObjectMaterial`
+// in mesh_painter_common.glsl
+const _: () = assert!(offset_of!(GpuObjectVertexInfo, obj_id) == 64);
+const _: () = assert!(offset_of!(GpuObjectFragmentInfo, texture_id) == 16);
+const _: () = assert!(offset_of!(GpuObjectFragmentInfo, normal_texture_id) == 20);
+const _: () = assert!(offset_of!(GpuObjectFragmentInfo, roughness) == 28);
+const _: () = assert!(offset_of!(GpuObjectFragmentInfo, sampler_id) == 32);
+const _: () = assert!(size_of::<GpuObjectFragmentInfo>() == 48);
+
 #[derive(Debug, Clone)]
 pub struct ObjDrawParams {
     pub vert_offset: i32,
     pub idx_offset: u32,
     pub idx_count: u32,
-    pub obj_info: GpuObjectInfo,
+    pub idx_type: vk::IndexType,
+    pub topology: vk::PrimitiveTopology,
+    /// Blended over what was drawn before it, and left out of the depth prepass.
+    pub transparent: bool,
+    pub obj_info: GpuObjectVertexInfo,
 }
 
 new_key_type! {
@@ -168,20 +575,236 @@ new_key_type! {
     pub struct TextureID;
 }
 
+new_key_type! {
+    pub struct MaterialID;
+}
+
+new_key_type! {
+    pub struct RenderTargetID;
+}
+
+new_key_type! {
+    pub struct SamplerID;
+}
+
+/// How a texture's texels should be interpreted when sampled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureKind {
+    /// sRGB encoded color, decoded to linear by the sampler.
+    Color,
+    /// Linear data such as normal or roughness maps, sampled as is.
+    Data,
+}
+
+impl TextureKind {
+    pub fn format(&self) -> vk::Format {
+        match self {
+            TextureKind::Color => vk::Format::R8G8B8A8_SRGB,
+            TextureKind::Data => vk::Format::R8G8B8A8_UNORM,
+        }
+    }
+}
+
+/// 2x2 magenta and black checker, loud enough to spot missing textures.
+const FALLBACK_TEXTURE_TEXELS: [u8; 16] = [
+    255, 0, 255, 255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 0, 255, 255,
+];
+
+/// Bytes per texel of the formats textures can be uploaded in.
+fn texel_size(format: vk::Format) -> Option<usize> {
+    match format {
+        vk::Format::R8G8B8A8_SRGB | vk::Format::R8G8B8A8_UNORM => Some(4),
+        vk::Format::R16G16B16A16_SFLOAT => Some(8),
+        vk::Format::R32G32B32A32_SFLOAT => Some(16),
+        _ => None,
+    }
+}
+
+/// Bytes per 4x4 block of the block compressed formats textures can be uploaded in.
+fn block_size(format: vk::Format) -> Option<usize> {
+    match format {
+        vk::Format::BC1_RGB_UNORM_BLOCK
+        | vk::Format::BC1_RGB_SRGB_BLOCK
+        | vk::Format::BC1_RGBA_UNORM_BLOCK
+        | vk::Format::BC1_RGBA_SRGB_BLOCK
+        | vk::Format::BC4_UNORM_BLOCK
+        | vk::Format::BC4_SNORM_BLOCK => Some(8),
+        vk::Format::BC2_UNORM_BLOCK
+        | vk::Format::BC2_SRGB_BLOCK
+        | vk::Format::BC3_UNORM_BLOCK
+        | vk::Format::BC3_SRGB_BLOCK
+        | vk::Format::BC5_UNORM_BLOCK
+        | vk::Format::BC5_SNORM_BLOCK
+        | vk::Format::BC6H_UFLOAT_BLOCK
+        | vk::Format::BC6H_SFLOAT_BLOCK
+        | vk::Format::BC7_UNORM_BLOCK
+        | vk::Format::BC7_SRGB_BLOCK => Some(16),
+        _ => None,
+    }
+}
+
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+
+/// Reads the format, size and top mip level of a 2D KTX2 file. KTX2 stores the `vk::Format`
+/// itself, so only supercompressed and Basis Universal files, which need transcoding, are
+/// rejected here.
+fn parse_ktx2(bytes: &[u8]) -> Result<(vk::Format, u32, u32, &[u8]), String> {
+    let read_u64 = |offset: usize| {
+        bytes
+            .get(offset..offset + 8)
+            .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+            .ok_or("unexpected end of file".to_string())
+    };
+    let read_u32 = |offset: usize| {
+        bytes
+            .get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+            .ok_or("unexpected end of file".to_string())
+    };
+
+    if bytes.get(..12) != Some(&KTX2_IDENTIFIER[..]) {
+        return Err("not a KTX2 file".to_string());
+    }
+    let format = vk::Format::from_raw(read_u32(12)? as i32);
+    let width = read_u32(20)?;
+    let height = read_u32(24)?;
+    let depth = read_u32(28)?;
+    let layer_count = read_u32(32)?;
+    let face_count = read_u32(36)?;
+    let supercompression = read_u32(44)?;
+    if format == vk::Format::UNDEFINED {
+        return Err("Basis Universal textures aren't supported".to_string());
+    }
+    if supercompression != 0 {
+        return Err(format!(
+            "supercompression scheme {supercompression} isn't supported"
+        ));
+    }
+    if width == 0 || height == 0 || depth != 0 || layer_count > 1 || face_count != 1 {
+        return Err("only single layer 2D textures are supported".to_string());
+    }
+
+    // The level index follows the 48 byte header and the 32 byte section index, largest
+    // level first
+    let level_offset = read_u64(80)? as usize;
+    let level_size = read_u64(88)? as usize;
+    let level_end = level_offset
+        .checked_add(level_size)
+        .ok_or("mip level 0 is out of bounds".to_string())?;
+    let level_data = bytes
+        .get(level_offset..level_end)
+        .ok_or("mip level 0 is out of bounds".to_string())?;
+    Ok((format, width, height, level_data))
+}
+
+struct Texture {
+    /// `None` when the image is owned elsewhere, like a render target's.
+    image: Option<Image2d>,
+    /// View sampled in each frame, a single view is shared by all of them.
+    views: Vec<vk::ImageView>,
+    /// Index into the shader's texture array, fixed for the texture's lifetime.
+    slot: u32,
+    /// `None` samples with the default sampler.
+    sampler: Option<SamplerID>,
+}
+
+struct TextureSampler {
+    sampler: vk::Sampler,
+    /// Index into the shader's sampler array.
+    slot: u32,
+}
+
+impl Texture {
+    fn view(&self, frame_number: usize) -> vk::ImageView {
+        self.views[frame_number % self.views.len()]
+    }
+}
+
+/// An offscreen target drawn with its own camera and inputs, sampled by other draws through
+/// its texture.
+struct RenderTarget {
+    per_frame_datas: Vec<PerFrameData>,
+    texture_id: TextureID,
+}
+
+/// An image for the decode threads to open, queued by `add_texture_async`.
+struct DecodeJob {
+    texture_id: TextureID,
+    path: String,
+    format: vk::Format,
+}
+
+/// An image `add_texture_async` had decoded, waiting to be uploaded.
+struct DecodedTexture {
+    texture_id: TextureID,
+    format: vk::Format,
+    image: Result<image::RgbaImage, String>,
+}
+
+/// A decoded image being uploaded, swapped in for the fallback texture once `fence` signals.
+struct TextureUpload {
+    texture_id: TextureID,
+    image: Image2d,
+    /// Read by the upload, kept alive until it completes.
+    staging_buffer: Buffer,
+    /// Used by the upload's mip generation, freed once it completes.
+    mip_shader_inputs: Vec<vk::DescriptorSet>,
+    command_buffer: CommandBuffer,
+    fence: CpuFuture,
+}
+
 pub struct MeshPainter {
     painter: Arc<Painter>,
     pipeline: SingePassRenderPipeline,
+    line_pipeline: SingePassRenderPipeline,
+    /// Variant of `pipeline` for transparent drawables.
+    transparent_pipeline: SingePassRenderPipeline,
+    /// Both `None`, like `depth_attachment_format`, for painters made without depth.
+    depth_pipeline: Option<SingePassRenderPipeline>,
+    depth_equal_pipeline: Option<SingePassRenderPipeline>,
     color_attachment_format: vk::Format,
-    depth_attachment_format: vk::Format,
-    sampler: vk::Sampler,
+    /// See `MeshPainter::new`.
+    color_load_op: vk::AttachmentLoadOp,
+    depth_attachment_format: Option<vk::Format>,
+    depth_compare_op: vk::CompareOp,
+    /// Farthest depth for `depth_compare_op`, what the depth attachment is cleared to.
+    depth_clear_value: f32,
+    /// Clamped to the device limit, shared by every sampler.
+    max_anisotropy: f32,
+    /// Never removed, so slots are `0..len`.
+    samplers: SlotMap<SamplerID, TextureSampler>,
+    default_sampler: SamplerID,
     allocator: GAllocator,
     meshes: SlotMap<MeshID, Mesh>,
-    textures: SlotMap<TextureID, Image2d>,
+    textures: SlotMap<TextureID, Texture>,
+    materials: SlotMap<MaterialID, Material>,
+    /// Slots left behind by removed textures, reused before growing the array.
+    free_texture_slots: Vec<u32>,
+    /// Size of the shader's texture array, `MAX_TEXTURES` clamped to the device limits.
+    max_textures: usize,
     textures_to_delete: Vec<Image2d>,
+    /// Magenta checker drawn in place of missing textures, so it can't be missing itself.
+    fallback_texture: TextureID,
+    /// Missing textures already warned about, so each is only reported once.
+    warned_missing_textures: HashSet<TextureID>,
+    /// Feeds the decode threads, which are started by the first `add_texture_async` and
+    /// exit once it is dropped.
+    decode_job_sender: Option<mpsc::Sender<DecodeJob>>,
+    decoded_texture_sender: mpsc::Sender<DecodedTexture>,
+    decoded_texture_receiver: mpsc::Receiver<DecodedTexture>,
+    texture_uploads: Vec<TextureUpload>,
+    mip_generator: MipGenerator,
     shader_input_allocator: ShaderInputAllocator,
     command_pool: CommandPool,
-    command_buffer: CommandBuffer,
     per_frame_datas: Vec<PerFrameData>,
+    render_targets: SlotMap<RenderTargetID, RenderTarget>,
+    culling_enabled: bool,
+    lights: Vec<PointLight>,
+    transparency_mode: TransparencyMode,
+    depth_prepass_enabled: bool,
+    clear_color: [f32; 4],
 }
 
 impl MeshPainter {
@@ -207,179 +830,1097 @@ impl MeshPainter {
         return Err("No suitable depth format found".to_string());
     }
 
+    fn select_max_textures(painter: &Painter) -> Result<usize, String> {
+        let limits = painter.device_limits();
+        let device_max = limits
+            .max_per_stage_descriptor_sampled_images
+            .min(limits.max_descriptor_set_sampled_images) as usize;
+        if device_max < MIN_TEXTURES {
+            return Err(format!(
+                "device can only bind {device_max} sampled images per stage, need at least \
+                 {MIN_TEXTURES}"
+            ));
+        }
+        Ok(device_max.min(MAX_TEXTURES))
+    }
+
+    /// Shader inputs, push constants and vertex input shared by every pipeline variant, so
+    /// descriptor sets and push constants stay compatible between them.
+    fn pipeline_builder<'a>(max_textures: usize) -> RenderPipelineBuilder<'a> {
+        RenderPipelineBuilder::new()
+            .shader_inputs(vec![
+                ShaderInputBindingInfo {
+                    _type: ShaderInputType::StorageBuffer,
+                    count: 1,
+                    dynamic: false,
+                    stages: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                },
+                ShaderInputBindingInfo {
+                    _type: ShaderInputType::Sampler,
+                    count: MAX_SAMPLERS as _,
+                    dynamic: false,
+                    stages: vk::ShaderStageFlags::FRAGMENT,
+                },
+                ShaderInputBindingInfo {
+                    _type: ShaderInputType::StorageBuffer,
+                    count: 1,
+                    dynamic: false,
+                    stages: vk::ShaderStageFlags::FRAGMENT,
+                },
+            ])
+            .shader_inputs(vec![ShaderInputBindingInfo {
+                _type: ShaderInputType::SampledImage2d,
+                count: max_textures as _,
+                dynamic: true,
+                stages: vk::ShaderStageFlags::FRAGMENT,
+            }])
+            .push_constants(
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                size_of::<GpuObjectVertexInfo>() as u32,
+            )
+            .vertex_input(
+                Vertex::get_binding_description(),
+                Vertex::get_attribute_descriptions(),
+            )
+    }
+
+    fn build_color_pipeline(
+        painter: &Arc<Painter>,
+        max_textures: usize,
+        color_attachment_format: vk::Format,
+        color_load_op: vk::AttachmentLoadOp,
+        depth: Option<(vk::Format, vk::AttachmentStoreOp)>,
+        depth_compare_op: vk::CompareOp,
+        topology: vk::PrimitiveTopology,
+    ) -> Result<SingePassRenderPipeline, String> {
+        let mut builder = Self::pipeline_builder(max_textures)
+            .color_attachment(
+                color_attachment_format,
+                color_load_op,
+                vk::AttachmentStoreOp::STORE,
+            )
+            .shaders(VERTEX_SHADER_CODE, FRAGMENT_SHADER_CODE)
+            .topology(topology);
+        if let Some((depth_attachment_format, depth_store_op)) = depth {
+            builder = builder
+                .depth_attachment(
+                    depth_attachment_format,
+                    vk::AttachmentLoadOp::CLEAR,
+                    depth_store_op,
+                )
+                .depth_test(depth_compare_op, true);
+        }
+        builder.build(painter.clone())
+    }
+
+    /// `color_load_op` is `CLEAR` to start every frame from the clear color, or `LOAD` to draw
+    /// over what the target's image held, e.g. to accumulate. Each frame has its own image, so
+    /// `LOAD` gets what was drawn `frame_count` frames ago. Those images are expected in
+    /// `ImageAccess::ShaderRead` when the pass starts, where they are created in and where
+    /// the draw commands leave them. Passes after those in the same recording leaving them
+    /// in another access have to hint them back.
+    /// `depth_store_op` is `DONT_CARE` unless later passes reuse the depth image, like a
+    /// transparent pass testing against it or SSAO sampling it. `None` leaves the depth image
+    /// out altogether, for 2D or UI drawing in submission order, which also rules out the
+    /// depth prepass.
     pub fn new(
         painter: Arc<Painter>,
         resolution: vk::Extent2D,
         frame_count: usize,
+        color_attachment_format: vk::Format,
+        max_anisotropy: f32,
+        reverse_z: bool,
+        color_load_op: vk::AttachmentLoadOp,
+        depth_store_op: Option<vk::AttachmentStoreOp>,
     ) -> Result<Self, String> {
         unsafe {
             let device = &painter.device;
 
-            let color_attachment_format = vk::Format::R8G8B8A8_UNORM;
+            let depth = depth_store_op
+                .map(|depth_store_op| {
+                    Self::select_depth_format(&painter.instance, painter.physical_device)
+                        .map(|depth_attachment_format| (depth_attachment_format, depth_store_op))
+                        .map_err(|e| format!("at select depth format: {e}"))
+                })
+                .transpose()?;
             let depth_attachment_format =
-                Self::select_depth_format(&painter.instance, painter.physical_device)
-                    .map_err(|e| format!("at select depth format: {e}"))?;
+                depth.map(|(depth_attachment_format, _)| depth_attachment_format);
 
-            let sampler = device
-                .create_sampler(&vk::SamplerCreateInfo::default(), None)
-                .map_err(|e| format!("at create sampler: {e}"))?;
+            // Reverse Z needs a matching projection, see `Camera::new_reverse_z`
+            let (depth_compare_op, depth_clear_value) = if reverse_z {
+                (vk::CompareOp::GREATER, 0.0)
+            } else {
+                (vk::CompareOp::LESS, 1.0)
+            };
 
-            let pipeline = SingePassRenderPipeline::new(
-                painter.clone(),
-                vec![(
-                    color_attachment_format,
-                    vk::AttachmentLoadOp::CLEAR,
-                    vk::AttachmentStoreOp::STORE,
-                )],
-                Some((
-                    depth_attachment_format,
-                    vk::AttachmentLoadOp::CLEAR,
-                    vk::AttachmentStoreOp::DONT_CARE,
-                )),
-                vec![
-                    vec![
-                        ShaderInputBindingInfo {
-                            _type: ShaderInputType::StorageBuffer,
-                            count: 1,
-                            dynamic: false,
-                        },
-                        ShaderInputBindingInfo {
-                            _type: ShaderInputType::Sampler,
-                            count: 1,
-                            dynamic: false,
-                        },],
-                    vec![
-                        
-                        ShaderInputBindingInfo {
-                            _type: ShaderInputType::SampledImage2d,
-                            count: MAX_TEXTURES as _,
-                            dynamic: true,
-                        },
-                    ],
-                ],
-                size_of::<GpuObjectInfo>(),
-                VERTEX_SHADER_CODE,
-                FRAGMENT_SHADER_CODE,
-                Vertex::get_binding_description(),
-                Vertex::get_attribute_descriptions(),
+            let max_textures = Self::select_max_textures(&painter)
+                .map_err(|e| format!("at select max textures: {e}"))?;
+
+            // 1.0 or less turns anisotropic filtering off, as do devices without it
+            let max_anisotropy = if painter.supported_features().sampler_anisotropy {
+                max_anisotropy.min(painter.device_limits().max_sampler_anisotropy)
+            } else {
+                1.0
+            };
+            let sampler = Self::create_sampler(&painter, max_anisotropy, SamplerInfo::default())
+                .map_err(|e| format!("at create default sampler: {e}"))?;
+            let mut samplers = SlotMap::with_key();
+            let default_sampler = samplers.insert(TextureSampler { sampler, slot: 0 });
+
+            let pipeline = Self::build_color_pipeline(
+                &painter,
+                max_textures,
+                color_attachment_format,
+                color_load_op,
+                depth,
+                depth_compare_op,
+                vk::PrimitiveTopology::TRIANGLE_LIST,
             )
             .map_err(|e| format!("at create render pipeline: {e}"))?;
+            let line_pipeline = Self::build_color_pipeline(
+                &painter,
+                max_textures,
+                color_attachment_format,
+                color_load_op,
+                depth,
+                depth_compare_op,
+                vk::PrimitiveTopology::LINE_LIST,
+            )
+            .map_err(|e| format!("at create line render pipeline: {e}"))?;
+            // Tests against opaque depth, including the prepass's, without hiding what is
+            // behind it from later transparent draws
+            let transparent_pipeline = pipeline
+                .derive_variant(PipelineStateOverrides {
+                    alpha_blending: Some(true),
+                    depth_test: Some((depth_compare_op, false)),
+                    ..Default::default()
+                })
+                .map_err(|e| format!("at create transparent render pipeline: {e}"))?;
+            let depth_pipeline = depth
+                .map(|(depth_attachment_format, _)| {
+                    Self::pipeline_builder(max_textures)
+                        .depth_attachment(
+                            depth_attachment_format,
+                            vk::AttachmentLoadOp::CLEAR,
+                            vk::AttachmentStoreOp::STORE,
+                        )
+                        .vertex_shader(VERTEX_SHADER_CODE)
+                        .depth_test(depth_compare_op, true)
+                        .build(painter.clone())
+                        .map_err(|e| format!("at create depth prepass pipeline: {e}"))
+                })
+                .transpose()?;
+            // Color pass after a prepass: keeps the prepass depth and only shades the
+            // fragments that won it.
+            let depth_equal_pipeline = depth
+                .map(|(depth_attachment_format, depth_store_op)| {
+                    Self::pipeline_builder(max_textures)
+                        .color_attachment(
+                            color_attachment_format,
+                            color_load_op,
+                            vk::AttachmentStoreOp::STORE,
+                        )
+                        .depth_attachment(
+                            depth_attachment_format,
+                            vk::AttachmentLoadOp::LOAD,
+                            depth_store_op,
+                        )
+                        .shaders(VERTEX_SHADER_CODE, FRAGMENT_SHADER_CODE)
+                        .depth_test(vk::CompareOp::EQUAL, false)
+                        .build(painter.clone())
+                        .map_err(|e| format!("at create depth equal pipeline: {e}"))
+                })
+                .transpose()?;
 
-            let shader_input_allocator = ShaderInputAllocator::new(
+            let target_frame_count = (1 + MAX_RENDER_TARGETS) * frame_count;
+            // Every pipeline shares the same set layouts
+            let shader_input_allocator = ShaderInputAllocator::for_layouts(
                 painter.clone(),
-                vec![
-                    (ShaderInputType::StorageBuffer, frame_count as u32),
-                    (ShaderInputType::Sampler, 2),
-                    (
-                        ShaderInputType::SampledImage2d,
-                        (MAX_TEXTURES * frame_count) as u32,
-                    ),
+                &[
+                    (&pipeline.shader_input_layouts[0], target_frame_count as u32),
+                    // Outgrown texture sets are freed after their replacement is allocated,
+                    // so growing briefly needs room for both
+                    (&pipeline.shader_input_layouts[1], 2 * target_frame_count as u32),
                 ],
-                4 * frame_count as u32,
             )
             .map_err(|e| format!("at create shader input allocator: {e}"))?;
 
             let mut allocator =
                 GAllocator::new(painter.clone()).map_err(|e| format!("at create allocator: {e}"))?;
 
-            let command_pool = CommandPool::new(painter.clone())
+            let command_pool = painter
+                .create_command_pool(CommandPoolKind::Transient)
                 .map_err(|e| format!("at create command pool: {e}"))?;
+            let mip_generator = MipGenerator::new(painter.clone())?;
 
-            let mut command_buffer = command_pool
-                .allocate_command_buffers(1)
-                .map_err(|e| format!("at allocate command buffer: {e}"))?
-                .swap_remove(0);
-
+            let direct_upload = painter.has_host_visible_device_local_memory();
             let per_frame_datas = (0..frame_count)
                 .map(|_| {
                     PerFrameData::new(
                         &pipeline,
+                        depth_pipeline.as_ref(),
                         &mut allocator,
                         color_attachment_format,
                         depth_attachment_format,
                         resolution,
                         &shader_input_allocator,
-                        &mut command_buffer,
+                        &command_pool,
+                        direct_upload,
+                        &[sampler; MAX_SAMPLERS],
                     )
                 })
                 .collect::<Result<Vec<_>, String>>()?;
 
-            Ok(Self {
+            let (decoded_texture_sender, decoded_texture_receiver) = mpsc::channel();
+
+            let mut mesh_painter = Self {
                 painter,
                 pipeline,
+                line_pipeline,
+                transparent_pipeline,
+                depth_pipeline,
+                depth_equal_pipeline,
                 color_attachment_format,
+                color_load_op,
                 depth_attachment_format,
+                depth_compare_op,
+                depth_clear_value,
                 meshes: SlotMap::with_key(),
                 textures: SlotMap::with_key(),
+                materials: SlotMap::with_key(),
+                free_texture_slots: Vec::new(),
+                max_textures,
                 textures_to_delete: Vec::new(),
+                fallback_texture: TextureID::default(),
+                warned_missing_textures: HashSet::new(),
+                decode_job_sender: None,
+                decoded_texture_sender,
+                decoded_texture_receiver,
+                texture_uploads: vec![],
+                mip_generator,
                 shader_input_allocator,
                 command_pool,
-                command_buffer,
                 per_frame_datas,
-                sampler,
+                render_targets: SlotMap::with_key(),
+                max_anisotropy,
+                samplers,
+                default_sampler,
                 allocator,
-            })
+                culling_enabled: true,
+                lights: vec![],
+                transparency_mode: TransparencyMode::Unsorted,
+                depth_prepass_enabled: false,
+                clear_color: [0.0, 0.0, 0.0, 1.0],
+            };
+            mesh_painter.fallback_texture = mesh_painter
+                .add_texture_from_rgba(&FALLBACK_TEXTURE_TEXELS, 2, 2, vk::Format::R8G8B8A8_UNORM)
+                .map_err(|e| format!("at create fallback texture: {e}"))?;
+            Ok(mesh_painter)
         }
     }
 
+    /// Drawn in place of textures that were removed or never added. Always present, e.g. to
+    /// build materials with before their own textures are loaded.
+    pub fn fallback_texture(&self) -> TextureID {
+        self.fallback_texture
+    }
+
     pub fn get_rendered_image(&self, frame_number: usize) -> &Image2d {
         &self.per_frame_datas[frame_number % self.per_frame_datas.len()].color_image
     }
 
-    pub fn add_mesh(&mut self, vertices: Vec<Vertex>, indices: Vec<u32>) -> MeshID {
-        let mesh_id = self.meshes.insert(Mesh { vertices, indices });
-        mesh_id
+    /// Reads the frame's color image back, e.g. for thumbnails. Blocks until the copy is
+    /// done, and the frame's own submission has to be complete. `last_access` is the access
+    /// the frame's recording left the image in, `ImageAccess::ShaderRead` once a later pass
+    /// sampled it, and the image is put back in it. Only 8 bit RGBA and BGRA color formats
+    /// can be captured.
+    pub fn capture_frame(
+        &mut self,
+        frame_number: usize,
+        last_access: ImageAccess,
+    ) -> Result<image::RgbaImage, String> {
+        let bgra = match self.color_attachment_format {
+            vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB => false,
+            vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB => true,
+            format => return Err(format!("at capture frame: can't capture {format:?} images")),
+        };
+        let color_image =
+            &self.per_frame_datas[frame_number % self.per_frame_datas.len()].color_image;
+        let extent = color_image.extent;
+        let buffer = self
+            .painter
+            .create_buffer(
+                extent.width as u64 * extent.height as u64 * 4,
+                vk::BufferUsageFlags::TRANSFER_DST,
+                Some(&mut self.allocator),
+                Some(true),
+            )
+            .map_err(|e| format!("at create capture buffer: {e}"))?;
+        self.painter
+            .run_commands_blocking(
+                &self.command_pool,
+                &[
+                    GpuCommand::ImageAccessHint {
+                        image: color_image,
+                        access: last_access,
+                    },
+                    GpuCommand::CopyImageToBuffer {
+                        image: color_image,
+                        buffer: &buffer,
+                        buffer_offset: 0,
+                    },
+                    GpuCommand::ImageAccessHint {
+                        image: color_image,
+                        access: last_access,
+                    },
+                ],
+            )
+            .map_err(|e| format!("at copy frame to buffer: {e}"))?;
+        let mut texels = buffer
+            .read_from_mem()
+            .map_err(|e| format!("at read capture buffer: {e}"))?;
+        drop(buffer);
+        self.allocator
+            .process_free_events()
+            .map_err(|e| format!("at free capture buffer: {e}"))?;
+        if bgra {
+            for texel in texels.chunks_exact_mut(4) {
+                texel.swap(0, 2);
+            }
+        }
+        image::RgbaImage::from_raw(extent.width, extent.height, texels)
+            .ok_or("at capture frame: buffer smaller than the image".to_string())
     }
 
-    pub fn add_texture(&mut self, path: &str) -> Result<TextureID, String> {
-        let image = image::open(path).map_err(|e| format!("at open image: {e}"))?;
-        let image_data = image.to_rgba8();
-        let vk_image = self.painter.create_image_2d(
-            vk::Format::R8G8B8A8_UNORM,
-            vk::Extent2D {
-                width: image.width(),
-                height: image.height(),
-            },
-            vec![ImageAccess::TransferWrite, ImageAccess::ShaderRead],
-            Some(&mut self.allocator),
-            Some(false)
-        )
-            .map_err(|e| format!("at vk create image: {e}"))?;
+    /// Only keeps its contents after the pass when created with `AttachmentStoreOp::STORE`.
+    /// `None` for painters made without depth.
+    pub fn get_depth_image(&self, frame_number: usize) -> Option<&Image2d> {
+        self.per_frame_datas[frame_number % self.per_frame_datas.len()]
+            .depth_image
+            .as_ref()
+    }
 
-        let mut stage_buffer = self.painter.create_buffer(image_data.len() as u64, vk::BufferUsageFlags::TRANSFER_SRC, Some(&mut self.allocator), Some(true)).map_err(|e| format!("at create stage buffer: {e}"))?;
+    /// Vertices given without tangents, all left zero, get them computed from the UVs.
+    pub fn add_mesh(&mut self, vertices: Vec<Vertex>, indices: Indices) -> MeshID {
+        let mut mesh = Mesh::new(vertices, indices);
+        if mesh.vertices.iter().all(|vertex| vertex.tangent == glam::Vec4::ZERO) {
+            mesh.compute_tangents();
+        }
+        self.meshes.insert(mesh)
+    }
 
-        stage_buffer.write_to_mem(&image_data)
-            .map_err(|e| format!("at write to staging buffer mem: {e}"))?;
+    /// Adds a mesh drawn as `LINE_LIST`, for debug geometry like normals, bounds or grids.
+    pub fn add_line_mesh(&mut self, vertices: Vec<Vertex>, indices: Indices) -> MeshID {
+        self.meshes.insert(Mesh::with_topology(
+            vertices,
+            indices,
+            vk::PrimitiveTopology::LINE_LIST,
+        ))
+    }
 
-        let commands = vec![
-            GpuCommand::ImageAccessInit {
-                image: &vk_image,
+    /// Computes missing tangents like `add_mesh`.
+    pub fn update_mesh(
+        &mut self,
+        mesh_id: MeshID,
+        vertices: Vec<Vertex>,
+        indices: Indices,
+    ) -> Result<(), String> {
+        let mesh = self
+            .meshes
+            .get_mut(mesh_id)
+            .ok_or(format!("mesh {mesh_id:?} not found"))?;
+        *mesh = Mesh::with_topology(vertices, indices, mesh.topology);
+        if mesh.vertices.iter().all(|vertex| vertex.tangent == glam::Vec4::ZERO) {
+            mesh.compute_tangents();
+        }
+        Ok(())
+    }
+
+    pub fn set_culling_enabled(&mut self, enabled: bool) {
+        self.culling_enabled = enabled;
+    }
+
+    /// Lights every target from the next `update_inputs` on. Only the first
+    /// `MAX_POINT_LIGHTS` are kept.
+    pub fn set_lights(&mut self, mut lights: Vec<PointLight>) {
+        lights.truncate(MAX_POINT_LIGHTS);
+        self.lights = lights;
+    }
+
+    pub fn set_transparency_mode(&mut self, transparency_mode: TransparencyMode) {
+        self.transparency_mode = transparency_mode;
+    }
+
+    /// Draws triangle meshes to depth first, then shades them with an `EQUAL` depth test so
+    /// each pixel is shaded once. Line meshes are only drawn in the color pass. Ignored by
+    /// painters made without depth.
+    pub fn set_depth_prepass(&mut self, enabled: bool) {
+        self.depth_prepass_enabled = enabled;
+    }
+
+    pub fn depth_compare_op(&self) -> vk::CompareOp {
+        self.depth_compare_op
+    }
+
+    pub fn depth_clear_value(&self) -> f32 {
+        self.depth_clear_value
+    }
+
+    pub fn set_clear_color(&mut self, color: [f32; 4]) {
+        self.clear_color = color;
+    }
+
+    pub fn add_texture(&mut self, path: &str, kind: TextureKind) -> Result<TextureID, String> {
+        self.add_textures(&[path], kind)?
+            .pop()
+            .ok_or("no texture uploaded".to_string())
+    }
+
+    pub fn add_textures(
+        &mut self,
+        paths: &[&str],
+        kind: TextureKind,
+    ) -> Result<Vec<TextureID>, String> {
+        let images = paths
+            .iter()
+            .map(|path| {
+                image::open(path)
+                    .map(|image| image.to_rgba8())
+                    .map_err(|e| format!("at open image {path}: {e}"))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let pixels = images
+            .iter()
+            .map(|image| (image.as_raw().as_slice(), image.width(), image.height()))
+            .collect::<Vec<_>>();
+        self.upload_textures(&pixels, kind.format())
+    }
+
+    /// Decodes an encoded image, e.g. one embedded with `include_bytes!`.
+    pub fn add_texture_from_memory(
+        &mut self,
+        bytes: &[u8],
+        format_hint: image::ImageFormat,
+        kind: TextureKind,
+    ) -> Result<TextureID, String> {
+        let image = image::load_from_memory_with_format(bytes, format_hint)
+            .map_err(|e| format!("at decode image: {e}"))?
+            .to_rgba8();
+        self.add_texture_from_rgba(
+            image.as_raw(),
+            image.width(),
+            image.height(),
+            kind.format(),
+        )
+    }
+
+    /// Uploads the top mip level of a block compressed KTX2 file, like BC7 for color or BC5 for
+    /// normal maps, without decompressing it. Fails if the device can't sample the format.
+    pub fn add_texture_ktx2(&mut self, path: &str) -> Result<TextureID, String> {
+        let bytes = std::fs::read(path).map_err(|e| format!("at read ktx2 {path}: {e}"))?;
+        let (format, width, height, blocks) =
+            parse_ktx2(&bytes).map_err(|e| format!("at parse ktx2 {path}: {e}"))?;
+        let block_size =
+            block_size(format).ok_or(format!("at add texture ktx2: {format:?} isn't supported"))?;
+        let expected_size = width.div_ceil(4) as usize * height.div_ceil(4) as usize * block_size;
+        if blocks.len() != expected_size {
+            return Err(format!(
+                "at add texture ktx2: expected {expected_size} bytes for {width}x{height} \
+                 {format:?}, got {}",
+                blocks.len()
+            ));
+        }
+        self.upload_textures(&[(blocks, width, height)], format)?
+            .pop()
+            .ok_or("no texture uploaded".to_string())
+    }
+
+    /// Loads a float image such as an `.hdr` or `.exr` environment map.
+    /// `format` has to be `R16G16B16A16_SFLOAT` or `R32G32B32A32_SFLOAT`.
+    pub fn add_hdr_texture(&mut self, path: &str, format: vk::Format) -> Result<TextureID, String> {
+        let image = image::open(path)
+            .map_err(|e| format!("at open image {path}: {e}"))?
+            .to_rgba32f();
+        let pixels = match format {
+            vk::Format::R32G32B32A32_SFLOAT => image
+                .as_raw()
+                .iter()
+                .flat_map(|texel| texel.to_ne_bytes())
+                .collect::<Vec<_>>(),
+            vk::Format::R16G16B16A16_SFLOAT => image
+                .as_raw()
+                .iter()
+                .flat_map(|&texel| half::f16::from_f32(texel).to_ne_bytes())
+                .collect::<Vec<_>>(),
+            _ => return Err(format!("at add hdr texture: {format:?} is not a float format")),
+        };
+        self.add_texture_from_rgba(&pixels, image.width(), image.height(), format)
+    }
+
+    /// Uploads tightly packed RGBA texels as is, in any format `texel_size` knows.
+    pub fn add_texture_from_rgba(
+        &mut self,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+        format: vk::Format,
+    ) -> Result<TextureID, String> {
+        let texel_size = texel_size(format)
+            .ok_or(format!("at add texture from rgba: unsupported format {format:?}"))?;
+        let expected_size = width as usize * height as usize * texel_size;
+        if pixels.len() != expected_size {
+            return Err(format!(
+                "at add texture from rgba: expected {expected_size} bytes for {width}x{height} \
+                 {format:?}, got {}",
+                pixels.len()
+            ));
+        }
+        self.upload_textures(&[(pixels, width, height)], format)?
+            .pop()
+            .ok_or("no texture uploaded".to_string())
+    }
+
+    fn upload_textures(
+        &mut self,
+        images: &[(&[u8], u32, u32)],
+        format: vk::Format,
+    ) -> Result<Vec<TextureID>, String> {
+        let properties = unsafe {
+            self.painter
+                .instance
+                .get_physical_device_format_properties(self.painter.physical_device, format)
+        };
+        if !properties
+            .optimal_tiling_features
+            .contains(vk::FormatFeatureFlags::SAMPLED_IMAGE)
+        {
+            return Err(format!("at upload textures: {format:?} can't be sampled on this device"));
+        }
+        if self.textures.len() + images.len() > self.max_textures {
+            return Err(format!(
+                "at add textures: {} textures would exceed the limit of {}",
+                self.textures.len() + images.len(),
+                self.max_textures
+            ));
+        }
+        let mut uploads = vec![];
+        for &(image_data, width, height) in images {
+            let vk_image = self.create_texture_image(format, vk::Extent2D { width, height })?;
+            let staged = self
+                .allocator
+                .stage_upload(image_data)
+                .map_err(|e| format!("at stage texture data: {e}"))?;
+            uploads.push((vk_image, staged));
+        }
+
+        let mut commands = vec![];
+        let mut mip_shader_inputs = vec![];
+        for (vk_image, staged) in uploads.iter() {
+            commands.push(GpuCommand::ImageAccessInit {
+                image: vk_image,
                 access: ImageAccess::TransferWrite,
+            });
+            commands.push(GpuCommand::CopyBufferToImageComplete {
+                buffer: self.allocator.staging_buffer(staged),
+                buffer_offset: staged.offset,
+                image: vk_image,
+            });
+            if vk_image.mip_levels > 1 {
+                let (command, shader_inputs) = self.mip_generator.commands(vk_image)?;
+                commands.push(command);
+                mip_shader_inputs.extend(shader_inputs);
+            }
+            commands.push(GpuCommand::ImageAccessHint {
+                image: vk_image,
+                access: ImageAccess::ShaderRead,
+            });
+        }
+        let result = self
+            .painter
+            .run_commands_blocking(&self.command_pool, &commands)
+            .map_err(|e| format!("at upload textures: {e}"));
+        drop(commands);
+        self.mip_generator.free_shader_inputs(mip_shader_inputs)?;
+        result?;
+        self.allocator.reset_staging();
+
+        let texture_ids = uploads
+            .into_iter()
+            .map(|(image, _)| {
+                let views = vec![image.image_view];
+                self.insert_texture(Some(image), views)
+            })
+            .collect();
+        Ok(texture_ids)
+    }
+
+    /// With a full mip chain when the format's levels are downsampled, single level otherwise.
+    fn create_texture_image(
+        &mut self,
+        format: vk::Format,
+        extent: vk::Extent2D,
+    ) -> Result<Image2d, String> {
+        let mut accesses = vec![ImageAccess::TransferWrite, ImageAccess::ShaderRead];
+        let image = if self.mip_generator.downsamples(format) {
+            accesses.extend(MipGenerator::IMAGE_ACCESSES);
+            self.painter.create_image_2d_mipmapped(
+                format,
+                extent,
+                accesses,
+                Some(&mut self.allocator),
+                Some(false),
+            )
+        } else {
+            self.painter.create_image_2d(
+                format,
+                extent,
+                accesses,
+                Some(&mut self.allocator),
+                Some(false),
+                None,
+            )
+        };
+        image.map_err(|e| format!("at vk create image: {e}"))
+    }
+
+    /// Gives the texture a slot and queues its descriptor write in every frame.
+    fn insert_texture(&mut self, image: Option<Image2d>, views: Vec<vk::ImageView>) -> TextureID {
+        // With no free slots, the live textures fill slots 0..len
+        let slot = self
+            .free_texture_slots
+            .pop()
+            .unwrap_or(self.textures.len() as u32);
+        let texture_id = self.textures.insert(Texture {
+            image,
+            views,
+            slot,
+            sampler: None,
+        });
+        self.queue_texture_write(texture_id);
+        texture_id
+    }
+
+    /// Rewrites the texture's descriptor in every frame, at their next `update_inputs`.
+    fn queue_texture_write(&mut self, texture_id: TextureID) {
+        let render_target_frames = self
+            .render_targets
+            .values_mut()
+            .flat_map(|render_target| render_target.per_frame_datas.iter_mut());
+        for per_frame_data in self.per_frame_datas.iter_mut().chain(render_target_frames) {
+            per_frame_data.pending_texture_writes.push(texture_id);
+        }
+    }
+
+    /// Returns right away with a texture showing the fallback texture, while the image is
+    /// decoded on one of a few shared threads. `update_inputs` uploads it once decoded and
+    /// swaps it in once the upload completes, see `promote_loaded_textures`. Images failing
+    /// to decode keep showing the fallback texture.
+    pub fn add_texture_async(
+        &mut self,
+        path: &str,
+        kind: TextureKind,
+    ) -> Result<TextureID, String> {
+        if self.textures.len() >= self.max_textures {
+            return Err(format!(
+                "at add texture async: texture limit of {} reached",
+                self.max_textures
+            ));
+        }
+        if self.decode_job_sender.is_none() {
+            let decode_job_sender = Self::start_decode_threads(self.decoded_texture_sender.clone())
+                .map_err(|e| format!("at start decode threads: {e}"))?;
+            self.decode_job_sender = Some(decode_job_sender);
+        }
+        let fallback_view = self.textures[self.fallback_texture].views[0];
+        let texture_id = self.insert_texture(None, vec![fallback_view]);
+        let job = DecodeJob {
+            texture_id,
+            path: path.to_string(),
+            format: kind.format(),
+        };
+        let sent = self
+            .decode_job_sender
+            .as_ref()
+            .is_some_and(|decode_job_sender| decode_job_sender.send(job).is_ok());
+        if !sent {
+            // Nothing was drawn with it yet, so the slot can be reused right away
+            if let Some(texture) = self.textures.remove(texture_id) {
+                self.free_texture_slots.push(texture.slot);
+            }
+            return Err("at add texture async: decode threads exited".to_string());
+        }
+        Ok(texture_id)
+    }
+
+    /// Threads taking jobs off the returned sender until it is dropped, sending what they
+    /// decoded to `decoded_texture_sender`.
+    fn start_decode_threads(
+        decoded_texture_sender: mpsc::Sender<DecodedTexture>,
+    ) -> Result<mpsc::Sender<DecodeJob>, String> {
+        let thread_count = std::thread::available_parallelism()
+            .map_or(1, |thread_count| thread_count.get())
+            .min(MAX_DECODE_THREADS);
+        let (job_sender, job_receiver) = mpsc::channel::<DecodeJob>();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        for _ in 0..thread_count {
+            let job_receiver = job_receiver.clone();
+            let decoded_texture_sender = decoded_texture_sender.clone();
+            std::thread::Builder::new()
+                .name("texture decode".to_string())
+                .spawn(move || {
+                    loop {
+                        // Only waiting for a job holds the lock, decoding runs unlocked
+                        let job = match job_receiver.lock() {
+                            Ok(job_receiver) => job_receiver.recv(),
+                            Err(_) => return,
+                        };
+                        let Ok(job) = job else {
+                            return;
+                        };
+                        let image = image::open(&job.path)
+                            .map(|image| image.to_rgba8())
+                            .map_err(|e| format!("at open image {}: {e}", job.path));
+                        // Fails only if the painter was dropped meanwhile
+                        let _ = decoded_texture_sender.send(DecodedTexture {
+                            texture_id: job.texture_id,
+                            format: job.format,
+                            image,
+                        });
+                    }
+                })
+                .map_err(|e| format!("at spawn decode thread: {e}"))?;
+        }
+        Ok(job_sender)
+    }
+
+    /// Points textures from `add_texture_async` at their images once their uploads completed,
+    /// then starts uploads for the images decoded since the last call. Never waits on the
+    /// GPU, `update_inputs` calls it every frame.
+    pub fn promote_loaded_textures(&mut self) -> Result<(), String> {
+        let mut index = 0;
+        while index < self.texture_uploads.len() {
+            let uploaded = self
+                .painter
+                .cpu_future_is_signaled(&self.texture_uploads[index].fence)
+                .map_err(|e| format!("at check texture upload: {e}"))?;
+            if !uploaded {
+                index += 1;
+                continue;
+            }
+            let upload = self.texture_uploads.swap_remove(index);
+            unsafe {
+                self.painter.device.free_command_buffers(
+                    self.command_pool.command_pool,
+                    &[upload.command_buffer.command_buffer],
+                );
+            }
+            drop(upload.staging_buffer);
+            self.mip_generator
+                .free_shader_inputs(upload.mip_shader_inputs)?;
+            // Removed while loading, the image is dropped unused
+            let Some(texture) = self.textures.get_mut(upload.texture_id) else {
+                continue;
+            };
+            texture.views = vec![upload.image.image_view];
+            texture.image = Some(upload.image);
+            self.queue_texture_write(upload.texture_id);
+        }
+        self.allocator
+            .process_free_events()
+            .map_err(|e| format!("at free texture upload memory: {e}"))?;
+
+        while let Ok(decoded) = self.decoded_texture_receiver.try_recv() {
+            if !self.textures.contains_key(decoded.texture_id) {
+                continue;
+            }
+            let image = match decoded.image {
+                Ok(image) => image,
+                Err(e) => {
+                    eprintln!("at load texture async: {e}");
+                    continue;
+                }
+            };
+            let upload = self
+                .start_texture_upload(decoded.texture_id, &image, decoded.format)
+                .map_err(|e| format!("at start texture upload: {e}"))?;
+            self.texture_uploads.push(upload);
+        }
+        Ok(())
+    }
+
+    /// Submits the copy of `image` into a new texture image, without waiting for it.
+    fn start_texture_upload(
+        &mut self,
+        texture_id: TextureID,
+        image: &image::RgbaImage,
+        format: vk::Format,
+    ) -> Result<TextureUpload, String> {
+        let vk_image = self.create_texture_image(
+            format,
+            vk::Extent2D {
+                width: image.width(),
+                height: image.height(),
             },
-            GpuCommand::CopyBufferToImageComplete {
-                buffer: &stage_buffer,
+        )?;
+        let mut staging_buffer = self
+            .painter
+            .create_buffer(
+                image.as_raw().len() as u64,
+                vk::BufferUsageFlags::TRANSFER_SRC,
+                Some(&mut self.allocator),
+                Some(true),
+            )
+            .map_err(|e| format!("at create staging buffer: {e}"))?;
+        staging_buffer
+            .write_to_mem(image.as_raw())
+            .map_err(|e| format!("at write staging buffer: {e}"))?;
+        let command_buffer = self
+            .painter
+            .allocate_command_buffers(&self.command_pool, 1)
+            .map_err(|e| format!("at allocate command buffer: {e}"))?
+            .swap_remove(0);
+        let mut commands = vec![
+            GpuCommand::ImageAccessInit {
                 image: &vk_image,
+                access: ImageAccess::TransferWrite,
             },
-            GpuCommand::ImageAccessHint {
+            GpuCommand::CopyBufferToImageComplete {
+                buffer: &staging_buffer,
+                buffer_offset: 0,
                 image: &vk_image,
-                access: ImageAccess::ShaderRead,
             },
         ];
-        self.command_buffer
-            .record(&commands, true)
-            .map_err(|e| format!("at record command buffer: {e}"))?;
-
-        let fence = CpuFuture::new(self.painter.clone(), false)
-            .map_err(|e| format!("at create upload texture fence: {e}"))?;
-        self.command_buffer
-            .submit(&[], &[], &[], Some(&fence))
+        let mut mip_shader_inputs = vec![];
+        if vk_image.mip_levels > 1 {
+            let (command, shader_inputs) = self.mip_generator.commands(&vk_image)?;
+            commands.push(command);
+            mip_shader_inputs = shader_inputs;
+        }
+        commands.push(GpuCommand::ImageAccessHint {
+            image: &vk_image,
+            access: ImageAccess::ShaderRead,
+        });
+        let recorded = self
+            .painter
+            .record_cmd_buffer(&command_buffer, &commands, true)
+            .map_err(|e| format!("at record command buffer: {e}"));
+        drop(commands);
+        if let Err(e) = recorded {
+            self.mip_generator.free_shader_inputs(mip_shader_inputs)?;
+            return Err(e);
+        }
+        let fence = self
+            .painter
+            .create_cpu_future(false)
+            .map_err(|e| format!("at create fence: {e}"))?;
+        self.painter
+            .submit_cmd_buffer(&command_buffer, vec![], vec![], vec![], Some(&fence))
             .map_err(|e| format!("at submit command buffer: {e}"))?;
-        fence
-            .wait()
-            .map_err(|e| format!("at texture upload fence wait: {e}"))?;
+        Ok(TextureUpload {
+            texture_id,
+            image: vk_image,
+            staging_buffer,
+            mip_shader_inputs,
+            command_buffer,
+            fence,
+        })
+    }
 
-        let texture_id = self.textures.insert(vk_image);
-        Ok(texture_id)
+    /// Adds an offscreen target drawn through `update_render_target_inputs` and
+    /// `draw_render_target_commands`. Its image can be sampled by other draws, including the
+    /// main pass, through the returned texture once its commands ran earlier in the frame.
+    /// A target must not draw its own texture.
+    pub fn add_render_target(
+        &mut self,
+        resolution: vk::Extent2D,
+    ) -> Result<(RenderTargetID, TextureID), String> {
+        if self.render_targets.len() >= MAX_RENDER_TARGETS {
+            return Err(format!(
+                "at add render target: only {MAX_RENDER_TARGETS} render targets are supported"
+            ));
+        }
+        if self.textures.len() >= self.max_textures {
+            return Err(format!(
+                "at add render target: texture limit of {} reached",
+                self.max_textures
+            ));
+        }
+        let direct_upload = self.painter.has_host_visible_device_local_memory();
+        let per_frame_datas = (0..self.per_frame_datas.len())
+            .map(|_| {
+                let mut per_frame_data = PerFrameData::new(
+                    &self.pipeline,
+                    self.depth_pipeline.as_ref(),
+                    &mut self.allocator,
+                    self.color_attachment_format,
+                    self.depth_attachment_format,
+                    resolution,
+                    &self.shader_input_allocator,
+                    &self.command_pool,
+                    direct_upload,
+                    &Self::sampler_array(&self.samplers),
+                )?;
+                // Fresh texture sets start out empty
+                per_frame_data.pending_texture_writes = self.textures.keys().collect();
+                Ok(per_frame_data)
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let views = per_frame_datas
+            .iter()
+            .map(|per_frame_data| per_frame_data.color_image.image_view)
+            .collect();
+        let render_target_id = self.render_targets.insert(RenderTarget {
+            per_frame_datas,
+            texture_id: TextureID::default(),
+        });
+        let texture_id = self.insert_texture(None, views);
+        self.render_targets[render_target_id].texture_id = texture_id;
+        Ok((render_target_id, texture_id))
+    }
+
+    /// Samples `image` as a texture without copying it, e.g. a post process output. The
+    /// image needs `ImageAccess::ShaderRead` usage and has to be transitioned to it after each
+    /// write, before draws sampling it, like a `FrameGraph` pass reading it does. Writes must
+    /// not overlap frames still sampling it, and the texture has to be removed before the
+    /// image is dropped.
+    pub fn register_render_target_as_texture(
+        &mut self,
+        image: &Image2d,
+    ) -> Result<TextureID, String> {
+        if self.textures.len() >= self.max_textures {
+            return Err(format!(
+                "at register render target as texture: texture limit of {} reached",
+                self.max_textures
+            ));
+        }
+        Ok(self.insert_texture(None, vec![image.image_view]))
+    }
+
+    pub fn render_target_texture(&self, render_target: RenderTargetID) -> Option<TextureID> {
+        self.render_targets
+            .get(render_target)
+            .map(|render_target| render_target.texture_id)
+    }
+
+    /// Descriptor array index the shaders see for `texture_id`.
+    /// Stays the same until the texture is removed.
+    pub fn texture_slot(&self, texture_id: TextureID) -> Option<u32> {
+        self.textures.get(texture_id).map(|texture| texture.slot)
+    }
+
+    fn create_sampler(
+        painter: &Painter,
+        max_anisotropy: f32,
+        info: SamplerInfo,
+    ) -> Result<vk::Sampler, String> {
+        unsafe {
+            painter
+                .device
+                .create_sampler(
+                    &vk::SamplerCreateInfo::default()
+                        .address_mode_u(info.address_mode_u)
+                        .address_mode_v(info.address_mode_v)
+                        .border_color(info.border_color)
+                        .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+                        .max_lod(vk::LOD_CLAMP_NONE)
+                        .anisotropy_enable(max_anisotropy > 1.0)
+                        .max_anisotropy(max_anisotropy.max(1.0)),
+                    None,
+                )
+                .map_err(|e| format!("at create sampler: {e}"))
+        }
+    }
+
+    /// Contents of the shader's sampler array, unused slots repeat the default sampler.
+    fn sampler_array(samplers: &SlotMap<SamplerID, TextureSampler>) -> [vk::Sampler; MAX_SAMPLERS] {
+        let mut sampler_array = [vk::Sampler::null(); MAX_SAMPLERS];
+        for sampler in samplers.values() {
+            sampler_array[sampler.slot as usize] = sampler.sampler;
+        }
+        let default_sampler = sampler_array[0];
+        for sampler in sampler_array[samplers.len()..].iter_mut() {
+            *sampler = default_sampler;
+        }
+        sampler_array
+    }
+
+    /// Adds a sampler textures and drawables can be set to use. Samplers live as long as the
+    /// painter, at most `MAX_SAMPLERS` of them including the default one.
+    pub fn add_sampler(&mut self, info: SamplerInfo) -> Result<SamplerID, String> {
+        if self.samplers.len() >= MAX_SAMPLERS {
+            return Err(format!("at add sampler: sampler limit of {MAX_SAMPLERS} reached"));
+        }
+        let sampler = Self::create_sampler(&self.painter, self.max_anisotropy, info)?;
+        let slot = self.samplers.len() as u32;
+        let sampler_id = self.samplers.insert(TextureSampler { sampler, slot });
+        let render_target_frames = self
+            .render_targets
+            .values_mut()
+            .flat_map(|render_target| render_target.per_frame_datas.iter_mut());
+        for per_frame_data in self.per_frame_datas.iter_mut().chain(render_target_frames) {
+            per_frame_data.sampler_writes_pending = true;
+        }
+        Ok(sampler_id)
+    }
+
+    /// Repeats textures, used by those without a sampler of their own.
+    pub fn default_sampler(&self) -> SamplerID {
+        self.default_sampler
+    }
+
+    /// Samples the texture with `sampler` from the next `update_inputs`, `None` goes back to
+    /// the default sampler. Drawables whose material uses it as albedo can override it with
+    /// `DrawableMeshAndTexture::sampler`.
+    pub fn set_texture_sampler(
+        &mut self,
+        texture_id: TextureID,
+        sampler: Option<SamplerID>,
+    ) -> Result<(), String> {
+        if sampler.is_some_and(|sampler| !self.samplers.contains_key(sampler)) {
+            return Err("at set texture sampler: unknown sampler".to_string());
+        }
+        let texture = self
+            .textures
+            .get_mut(texture_id)
+            .ok_or("at set texture sampler: unknown texture".to_string())?;
+        texture.sampler = sampler;
+        Ok(())
+    }
+
+    /// Materials only keep the IDs of their textures, so one texture can back many of them.
+    pub fn add_material(&mut self, material: Material) -> MaterialID {
+        self.materials.insert(material)
+    }
+
+    /// Changes are picked up by the next `update_inputs`.
+    pub fn material_mut(&mut self, material_id: MaterialID) -> Option<&mut Material> {
+        self.materials.get_mut(material_id)
+    }
+
+    /// Drawables using the material are skipped from then on. Its textures are kept.
+    pub fn remove_material(&mut self, material_id: MaterialID) {
+        self.materials.remove(material_id);
+    }
+
+    /// Waits for the device to go idle before destroying the texture, as in flight frames
+    /// may still sample it. Its slot is handed to the next added texture.
+    pub fn remove_texture(&mut self, texture_id: TextureID) -> Result<(), String> {
+        if texture_id == self.fallback_texture {
+            return Err("at remove texture: the fallback texture can't be removed".to_string());
+        }
+        let Some(texture) = self.textures.remove(texture_id) else {
+            return Ok(());
+        };
+        self.painter
+            .wait_idle()
+            .map_err(|e| format!("at wait for device idle: {e}"))?;
+        self.free_texture_slots.push(texture.slot);
+        drop(texture);
+        self.allocator
+            .process_free_events()
+            .map_err(|e| format!("at free texture memory: {e}"))?;
+        Ok(())
     }
 
     pub fn update_inputs(
@@ -387,172 +1928,505 @@ impl MeshPainter {
         frame_number: usize,
         drawables: &[DrawableMeshAndTexture],
         camera: CamData,
+    ) -> Result<(), String> {
+        self.promote_loaded_textures()?;
+        self.update_target_inputs(None, frame_number, drawables, camera)
+    }
+
+    /// Same as `update_inputs`, for a render target's own camera and drawables.
+    pub fn update_render_target_inputs(
+        &mut self,
+        render_target: RenderTargetID,
+        frame_number: usize,
+        drawables: &[DrawableMeshAndTexture],
+        camera: CamData,
+    ) -> Result<(), String> {
+        self.update_target_inputs(Some(render_target), frame_number, drawables, camera)
+    }
+
+    fn update_target_inputs(
+        &mut self,
+        render_target: Option<RenderTargetID>,
+        frame_number: usize,
+        drawables: &[DrawableMeshAndTexture],
+        camera: CamData,
     ) -> Result<(), String> {
         let mut vb_data = vec![];
         let mut ib_data = vec![];
+        let mut ib_data_u16 = vec![];
 
         let mut vb_offset = 0i32;
-        let mut ib_offset = 0;
         let mut mesh_id = 0;
 
-        let textures_array = self.textures.iter().collect::<Vec<_>>();
-
-        let texture_idx_map = textures_array
-            .iter()
-            .enumerate()
-            .map(|(tid, tex)| (tex.0, tid))
-            .collect::<HashMap<_, _>>();
-
         let mut objects = vec![];
+        let mut object_infos = vec![];
+        // `(transparent, squared distance to the camera)` of each object when sorting, with
+        // transparent distances negated so both sort ascending
+        let mut sort_keys = vec![];
+
+        let frustum_planes = camera.frustum_planes();
 
         for drawable in drawables {
             let Some(mesh) = self.meshes.get(drawable.mesh_name) else {
                 continue;
             };
-            let Some(&texture_idx) = texture_idx_map.get(&drawable.texture_name) else {
+            if self.culling_enabled
+                && !mesh
+                    .aabb
+                    .transformed(&drawable.transform)
+                    .intersects_frustum(&frustum_planes)
+            {
+                continue;
+            }
+            let Some(material) = self.materials.get(drawable.material) else {
                 continue;
             };
+            let (texture_idx, texture_sampler) = match self.textures.get(material.albedo) {
+                Some(texture) => (texture.slot, texture.sampler),
+                None => {
+                    if self.warned_missing_textures.insert(material.albedo) {
+                        eprintln!(
+                            "texture {:?} not found, drawing the fallback texture",
+                            material.albedo
+                        );
+                    }
+                    (self.textures[self.fallback_texture].slot, None)
+                }
+            };
+            let sampler_idx = drawable
+                .sampler
+                .or(texture_sampler)
+                .and_then(|sampler_id| self.samplers.get(sampler_id))
+                .map_or(0, |sampler| sampler.slot);
+            let normal_texture_idx = material
+                .normal
+                .and_then(|normal_map| self.texture_slot(normal_map))
+                .unwrap_or(NO_TEXTURE);
             vb_data.extend_from_slice(&mesh.vertices);
-            ib_data.extend_from_slice(
-                &mesh
-                    .indices
-                    .iter()
-                    .map(|i| i + vb_offset as u32)
-                    .collect::<Vec<_>>(),
-            );
+            // Indices stay local to the mesh, the draw's vertex offset rebases them.
+            // This keeps u16 indices from overflowing once several meshes are packed.
+            let idx_offset = match &mesh.indices {
+                Indices::U16(indices) => {
+                    let offset = ib_data_u16.len() as u32;
+                    ib_data_u16.extend_from_slice(indices);
+                    offset
+                }
+                Indices::U32(indices) => {
+                    let offset = ib_data.len() as u32;
+                    ib_data.extend_from_slice(indices);
+                    offset
+                }
+            };
 
-            let object = GpuObjectInfo {
-                obj_id: objects.len() as u32,
+            // Objects are indexed in the order they are added here, sorting only reorders draws
+            let object = GpuObjectVertexInfo {
+                model: drawable.transform,
+                obj_id: object_infos.len() as u32,
                 mesh_id,
-                texture_id: texture_idx as u32,
             };
+            object_infos.push(GpuObjectFragmentInfo {
+                tint: drawable.tint * material.params.base_color,
+                texture_id: texture_idx,
+                normal_texture_id: normal_texture_idx,
+                metallic: material.params.metallic,
+                roughness: material.params.roughness,
+                sampler_id: sampler_idx,
+                _pad: [0; 3],
+            });
             mesh_id += 1;
+            if self.transparency_mode == TransparencyMode::Sorted {
+                let center = drawable
+                    .transform
+                    .transform_point3((mesh.aabb.min + mesh.aabb.max) * 0.5);
+                let distance = center.distance_squared(camera.pos.truncate());
+                let distance = if drawable.transparent {
+                    -distance
+                } else {
+                    distance
+                };
+                sort_keys.push((drawable.transparent, distance));
+            }
             objects.push(ObjDrawParams {
                 vert_offset: vb_offset,
-                idx_offset: ib_offset,
+                idx_offset,
                 idx_count: mesh.indices.len() as u32,
+                idx_type: mesh.indices.index_type(),
+                topology: mesh.topology,
+                transparent: drawable.transparent,
                 obj_info: object,
             });
             vb_offset += mesh.vertices.len() as i32;
-            ib_offset += mesh.indices.len() as u32;
         }
 
-        let norm_frame_number = frame_number % self.per_frame_datas.len();
-        self.per_frame_datas[norm_frame_number].index_buffer_size = ib_data.len() as u32;
-        self.per_frame_datas[norm_frame_number].next_draw_params = objects;
+        if self.transparency_mode == TransparencyMode::Sorted {
+            // Front to back lets the depth test reject hidden opaque fragments early, back to
+            // front makes transparent ones blend over what is behind them
+            let mut sorted = sort_keys.into_iter().zip(objects).collect::<Vec<_>>();
+            sorted.sort_by(|(a, _), (b, _)| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)));
+            objects = sorted.into_iter().map(|(_, object)| object).collect();
+        }
 
-        let vb = &self.per_frame_datas[norm_frame_number].vertex_buffer;
-        let ib = &self.per_frame_datas[norm_frame_number].index_buffer;
-        let sb = &self.per_frame_datas[norm_frame_number].scene_buffer;
+        let per_frame_datas = match render_target {
+            None => &mut self.per_frame_datas,
+            Some(render_target) => {
+                &mut self
+                    .render_targets
+                    .get_mut(render_target)
+                    .ok_or("at update inputs: unknown render target".to_string())?
+                    .per_frame_datas
+            }
+        };
+        let norm_frame_number = frame_number % per_frame_datas.len();
+        let per_frame_data = &mut per_frame_datas[norm_frame_number];
+        per_frame_data.index_buffer_size = (ib_data.len() + ib_data_u16.len()) as u32;
+        per_frame_data.next_draw_params = objects;
 
-        unsafe {
-            let scene_data = SceneDescriptorData { cam_data: camera };
-            sb.write_to_mem(&[scene_data].align_to::<u8>().1).map_err(|e| format!("at write to scene buffer mem: {e}"))?;
-            vb.write_to_mem(vb_data.as_slice().align_to::<u8>().1).map_err(|e| format!("at write to scene buffer mem: {e}"))?;
-            ib.write_to_mem(ib_data.as_slice().align_to::<u8>().1).map_err(|e| format!("at write to scene buffer mem: {e}"))?;
+        let mut gpu_lights = [GpuPointLight::default(); MAX_POINT_LIGHTS];
+        for (gpu_light, light) in gpu_lights.iter_mut().zip(&self.lights) {
+            *gpu_light = GpuPointLight {
+                pos: light.position.extend(1.0),
+                color: light.color.extend(1.0),
+                props: glam::vec4(light.range, 0.0, 0.0, 0.0),
+            };
+        }
 
-            let scene_dset = self.per_frame_datas[norm_frame_number].descriptor_sets[0];
-            let texture_dset = self.per_frame_datas[norm_frame_number].descriptor_sets[1];
+        unsafe {
+            let scene_data = SceneDescriptorData {
+                cam_data: camera,
+                light_count: self.lights.len() as u32,
+                _pad: [0; 3],
+                lights: gpu_lights,
+            };
+            per_frame_data.write_inputs([
+                vb_data.as_slice().align_to::<u8>().1,
+                ib_data.as_slice().align_to::<u8>().1,
+                ib_data_u16.as_slice().align_to::<u8>().1,
+                [scene_data].align_to::<u8>().1,
+                object_infos.as_slice().align_to::<u8>().1,
+            ])?;
+        }
 
-            self.painter.device.update_descriptor_sets(
-                &[
+        // Only textures added since this frame's sets were last used need writing, so steady
+        // state frames skip `update_descriptor_sets` instead of rewriting every texture.
+        let used_slots = self
+            .textures
+            .values()
+            .map(|texture| texture.slot + 1)
+            .max()
+            .unwrap_or(0);
+        if used_slots > per_frame_data.texture_capacity {
+            // This frame's last submission has completed, so its old set can be freed
+            let texture_capacity = used_slots.next_power_of_two().min(self.max_textures as u32);
+            let texture_set = self
+                .pipeline
+                .make_shader_input(&self.shader_input_allocator, 1, Some(texture_capacity))
+                .map_err(|e| format!("at grow texture shader input: {e}"))?;
+            let old_texture_set =
+                std::mem::replace(&mut per_frame_data.descriptor_sets[1], texture_set);
+            self.shader_input_allocator
+                .free(old_texture_set)
+                .map_err(|e| format!("at free outgrown texture shader input: {e}"))?;
+            per_frame_data.texture_capacity = texture_capacity;
+            per_frame_data.pending_texture_writes = self.textures.keys().collect();
+        }
+        if !per_frame_data.pending_texture_writes.is_empty() {
+            // Textures removed before this frame came around are skipped
+            let image_infos = per_frame_data
+                .pending_texture_writes
+                .iter()
+                .filter_map(|&texture_id| self.textures.get(texture_id))
+                .map(|texture| {
+                    (
+                        texture.slot,
+                        [vk::DescriptorImageInfo::default()
+                            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                            .image_view(texture.view(norm_frame_number))],
+                    )
+                })
+                .collect::<Vec<_>>();
+            let writes = image_infos
+                .iter()
+                .map(|(slot, image_info)| {
                     vk::WriteDescriptorSet::default()
-                        .dst_set(scene_dset)
+                        .dst_set(per_frame_data.descriptor_sets[1])
                         .dst_binding(0)
-                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                        .dst_array_element(*slot)
+                        .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
                         .descriptor_count(1)
-                        .buffer_info(&[vk::DescriptorBufferInfo::default()
-                            .buffer(sb.buffer)
-                            .range(vk::WHOLE_SIZE)]),
-                    vk::WriteDescriptorSet::default()
-                        .dst_set(scene_dset)
+                        .image_info(image_info)
+                })
+                .collect::<Vec<_>>();
+            unsafe {
+                self.painter.device.update_descriptor_sets(&writes, &[]);
+            }
+            per_frame_data.pending_texture_writes.clear();
+        }
+        if per_frame_data.sampler_writes_pending {
+            let image_infos = sampler_image_infos(&Self::sampler_array(&self.samplers));
+            unsafe {
+                self.painter.device.update_descriptor_sets(
+                    &[vk::WriteDescriptorSet::default()
+                        .dst_set(per_frame_data.descriptor_sets[0])
                         .dst_binding(1)
                         .descriptor_type(vk::DescriptorType::SAMPLER)
-                        .descriptor_count(1)
-                        .image_info(&[vk::DescriptorImageInfo::default().sampler(self.sampler)]),
-                    vk::WriteDescriptorSet::default()
-                        .dst_set(texture_dset)
-                        .dst_binding(1)
-                        .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
-                        .descriptor_count(textures_array.len() as _)
-                        .image_info(
-                            &textures_array
-                                .iter()
-                                .map(|(_, tex)| {
-                                    vk::DescriptorImageInfo::default()
-                                        .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
-                                        .image_view(tex.image_view)
-                                })
-                                .collect::<Vec<_>>(),
-                        ),
-                ],
-                &[],
-            );
-            // println!("number of textures written: {}", textures_array.len());
+                        .image_info(&image_infos)],
+                    &[],
+                );
+            }
+            per_frame_data.sampler_writes_pending = false;
         }
 
         Ok(())
     }
 
-    pub fn draw_meshes_command(&self, frame_number: usize) -> Result<GpuCommand, String> {
-        let frame_number = frame_number % self.per_frame_datas.len();
-        let per_frame_data = &self.per_frame_datas[frame_number];
+    fn bind_index_buffer_command<'a>(
+        per_frame_data: &'a PerFrameData,
+        idx_type: vk::IndexType,
+    ) -> GpuRenderPassCommand<'a> {
+        let buffer = if idx_type == vk::IndexType::UINT16 {
+            &per_frame_data.index_buffer_u16
+        } else {
+            &per_frame_data.index_buffer
+        };
+        GpuRenderPassCommand::BindIndexBuffer {
+            buffer,
+            offset: 0,
+            index_type: idx_type,
+        }
+    }
+
+    fn depth_prepass_command<'a>(
+        &'a self,
+        per_frame_data: &'a PerFrameData,
+    ) -> Result<GpuCommand<'a>, String> {
+        let (Some(depth_pipeline), Some(depth_render_output)) =
+            (&self.depth_pipeline, &per_frame_data.depth_render_output)
+        else {
+            return Err("at depth prepass: painter has no depth attachment".to_string());
+        };
+        let mut render_cmds = vec![
+            GpuRenderPassCommand::BindPipeline { pipeline: 0 },
+            GpuRenderPassCommand::BindVertexBuffers {
+                buffers: vec![(&per_frame_data.vertex_buffer, 0)],
+            },
+            GpuRenderPassCommand::BindShaderInput {
+                pipeline_layout: 0,
+                descriptor_sets: per_frame_data.descriptor_sets.clone(),
+            },
+        ];
+        let mut bound_idx_type = None;
+        for draw_param in &per_frame_data.next_draw_params {
+            // Transparent drawables must not hide what is behind them
+            let is_triangles = draw_param.topology == vk::PrimitiveTopology::TRIANGLE_LIST;
+            if !is_triangles || draw_param.transparent {
+                continue;
+            }
+            if bound_idx_type != Some(draw_param.idx_type) {
+                render_cmds.push(Self::bind_index_buffer_command(
+                    per_frame_data,
+                    draw_param.idx_type,
+                ));
+                bound_idx_type = Some(draw_param.idx_type);
+            }
+            unsafe {
+                render_cmds.push(depth_pipeline.make_push_constant_command(
+                    0,
+                    vk::ShaderStageFlags::VERTEX,
+                    0,
+                    [draw_param.obj_info].align_to::<u8>().1.to_vec(),
+                )?);
+            }
+            render_cmds.push(GpuRenderPassCommand::Draw {
+                count: draw_param.idx_count,
+                vertex_offset: draw_param.vert_offset,
+                index_offset: draw_param.idx_offset,
+                instance_count: 1,
+                first_instance: 0,
+            });
+        }
+        Ok(GpuCommand::RunRenderPass {
+            render_pass: depth_pipeline.render_pass,
+            render_output: depth_render_output,
+            clear_values: vec![vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: self.depth_clear_value,
+                    stencil: 0,
+                },
+            }],
+            pipelines: vec![depth_pipeline.pipeline],
+            pipeline_layouts: vec![depth_pipeline.pipeline_layout],
+            commands: render_cmds,
+        })
+    }
+
+    /// Copies of this frame's staged inputs, the optional depth prepass, then the render pass
+    /// drawing them. With a `LOAD` color pass the image is left in `ImageAccess::ShaderRead`,
+    /// where the next draw to it expects it.
+    pub fn draw_meshes_commands(&self, frame_number: usize) -> Result<Vec<GpuCommand>, String> {
+        let per_frame_data = &self.per_frame_datas[frame_number % self.per_frame_datas.len()];
+        let mut gpu_commands = self.draw_target_commands(per_frame_data)?;
+        if self.color_load_op == vk::AttachmentLoadOp::LOAD {
+            gpu_commands.push(GpuCommand::ImageAccessHint {
+                image: &per_frame_data.color_image,
+                access: ImageAccess::ShaderRead,
+            });
+        }
+        Ok(gpu_commands)
+    }
+
+    /// Same as `draw_meshes_commands` for a render target, leaving its image ready to be
+    /// sampled by passes recorded after these commands.
+    pub fn draw_render_target_commands(
+        &self,
+        render_target: RenderTargetID,
+        frame_number: usize,
+    ) -> Result<Vec<GpuCommand>, String> {
+        let per_frame_datas = &self
+            .render_targets
+            .get(render_target)
+            .ok_or("at draw render target: unknown render target".to_string())?
+            .per_frame_datas;
+        let per_frame_data = &per_frame_datas[frame_number % per_frame_datas.len()];
+        let mut gpu_commands = self.draw_target_commands(per_frame_data)?;
+        gpu_commands.push(GpuCommand::ImageAccessHint {
+            image: &per_frame_data.color_image,
+            access: ImageAccess::ShaderRead,
+        });
+        Ok(gpu_commands)
+    }
+
+    fn draw_target_commands<'a>(
+        &'a self,
+        per_frame_data: &'a PerFrameData,
+    ) -> Result<Vec<GpuCommand<'a>>, String> {
+        // Indices into the pipelines of the color pass
+        const TRIANGLE_PIPELINE: usize = 0;
+        const LINE_PIPELINE: usize = 1;
+        const DEPTH_EQUAL_PIPELINE: usize = 2;
+        const TRANSPARENT_PIPELINE: usize = 3;
+
         let mut render_cmds = vec![];
-        render_cmds.push(GpuRenderPassCommand::BindPipeline { pipeline: 0 });
         render_cmds.push(GpuRenderPassCommand::BindVertexBuffers {
-            buffers: vec![&per_frame_data.vertex_buffer],
-        });
-        render_cmds.push(GpuRenderPassCommand::BindIndexBuffer {
-            buffer: &per_frame_data.index_buffer,
+            buffers: vec![(&per_frame_data.vertex_buffer, 0)],
         });
         render_cmds.push(GpuRenderPassCommand::BindShaderInput {
             pipeline_layout: 0,
             descriptor_sets: per_frame_data.descriptor_sets.clone(),
         });
+        let depth_equal_pipeline = self
+            .depth_equal_pipeline
+            .as_ref()
+            .filter(|_| self.depth_prepass_enabled);
+        let mut bound_pipeline = None;
+        let mut bound_idx_type = None;
         for draw_param in &per_frame_data.next_draw_params {
+            let pipeline = match draw_param.topology {
+                vk::PrimitiveTopology::LINE_LIST => LINE_PIPELINE,
+                _ if draw_param.transparent => TRANSPARENT_PIPELINE,
+                _ if depth_equal_pipeline.is_some() => DEPTH_EQUAL_PIPELINE,
+                _ => TRIANGLE_PIPELINE,
+            };
+            if bound_pipeline != Some(pipeline) {
+                render_cmds.push(GpuRenderPassCommand::BindPipeline { pipeline });
+                bound_pipeline = Some(pipeline);
+            }
+            if bound_idx_type != Some(draw_param.idx_type) {
+                render_cmds.push(Self::bind_index_buffer_command(
+                    per_frame_data,
+                    draw_param.idx_type,
+                ));
+                bound_idx_type = Some(draw_param.idx_type);
+            }
             unsafe {
-                render_cmds.push(GpuRenderPassCommand::SetPushConstant {
-                    pipeline_layout: 0,
-                    data: [draw_param.obj_info].align_to::<u8>().1.to_vec(),
-                });
+                render_cmds.push(self.pipeline.make_push_constant_command(
+                    0,
+                    vk::ShaderStageFlags::VERTEX,
+                    0,
+                    [draw_param.obj_info].align_to::<u8>().1.to_vec(),
+                )?);
             }
             render_cmds.push(GpuRenderPassCommand::Draw {
                 count: draw_param.idx_count,
                 vertex_offset: draw_param.vert_offset,
                 index_offset: draw_param.idx_offset,
+                instance_count: 1,
+                first_instance: 0,
             });
         }
-        let gpu_command = GpuCommand::RunRenderPass {
-            render_pass: self.pipeline.render_pass,
-            render_output: &per_frame_data.render_output,
-            clear_values: vec![
-                vk::ClearValue {
-                    color: vk::ClearColorValue {
-                        float32: [1.0, 1.0, 0.0, 1.0],
-                    },
-                },
-                vk::ClearValue {
-                    depth_stencil: vk::ClearDepthStencilValue {
-                        depth: 1.0,
-                        stencil: 0,
-                    },
+        let mut gpu_commands = vec![GpuCommand::PushDebugLabel {
+            name: "mesh painter".to_string(),
+            color: [0.2, 0.6, 1.0, 1.0],
+        }];
+        gpu_commands.extend(per_frame_data.upload_commands());
+        if self.color_load_op == vk::AttachmentLoadOp::LOAD {
+            // First use in a recording would otherwise be taken as undefined contents. Init
+            // and every draw leave the image in this access
+            gpu_commands.push(GpuCommand::ImageAccessHint {
+                image: &per_frame_data.color_image,
+                access: ImageAccess::ShaderRead,
+            });
+        }
+        // The depth equal pipeline's render pass loads depth instead of clearing it, and is
+        // compatible with the framebuffer made for the main pipeline.
+        let render_pass = match depth_equal_pipeline {
+            Some(depth_equal_pipeline) => {
+                gpu_commands.push(GpuCommand::PushDebugLabel {
+                    name: "depth prepass".to_string(),
+                    color: [0.5, 0.5, 0.5, 1.0],
+                });
+                gpu_commands.push(self.depth_prepass_command(per_frame_data)?);
+                gpu_commands.push(GpuCommand::PopDebugLabel);
+                depth_equal_pipeline.render_pass
+            }
+            None => self.pipeline.render_pass,
+        };
+        let mut clear_values = vec![vk::ClearValue {
+            color: vk::ClearColorValue {
+                float32: self.clear_color,
+            },
+        }];
+        if per_frame_data.depth_image.is_some() {
+            clear_values.push(vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: self.depth_clear_value,
+                    stencil: 0,
                 },
+            });
+        }
+        gpu_commands.push(GpuCommand::RunRenderPass {
+            render_pass,
+            render_output: &per_frame_data.render_output,
+            clear_values,
+            // All pipelines share identical layouts, so inputs bound through the first
+            // stay valid for the others. Without a prepass the third one is never bound.
+            pipelines: vec![
+                self.pipeline.pipeline,
+                self.line_pipeline.pipeline,
+                depth_equal_pipeline.map_or(self.pipeline.pipeline, |pipeline| pipeline.pipeline),
+                self.transparent_pipeline.pipeline,
             ],
-            pipelines: vec![self.pipeline.pipeline],
             pipeline_layouts: vec![self.pipeline.pipeline_layout],
             commands: render_cmds,
-        };
-        Ok(gpu_command)
+        });
+        gpu_commands.push(GpuCommand::PopDebugLabel);
+        Ok(gpu_commands)
     }
 }
 
 impl Drop for MeshPainter {
     fn drop(&mut self) {
+        let _ = self
+            .painter
+            .wait_idle()
+            .inspect_err(|e| eprintln!("at wait for device idle: {e}"));
         let device = &self.painter.device;
+        self.texture_uploads.clear();
         self.textures_to_delete.clear();
         self.textures.clear();
         unsafe {
-            device.destroy_sampler(self.sampler, None);
+            for sampler in self.samplers.values() {
+                device.destroy_sampler(sampler.sampler, None);
+            }
         }
     }
 }
@@ -0,0 +1,140 @@
+use std::sync::Arc;
+
+use ash::vk;
+use include_bytes_aligned::include_bytes_aligned;
+use painter::{
+    ComputePipeline, GpuCommand, Image2d, ImageAccess, MipDownsample, Painter,
+    ShaderInputAllocator, ShaderInputBindingInfo, ShaderInputType, ShaderInputValue, ash,
+};
+
+static DOWNSAMPLE_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/mip_downsample.comp.spv");
+
+/// Sets the downsample pool holds, enough for the levels of a few large textures in flight.
+const MAX_DOWNSAMPLE_SETS: u32 = 256;
+
+/// Fills the mip chains of textures whose format can't be blitted with linear filtering, once
+/// their top level is uploaded. See `GpuCommand::DownsampleMipmaps`.
+pub struct MipGenerator {
+    painter: Arc<Painter>,
+    downsample_pipeline: ComputePipeline,
+    shader_input_allocator: ShaderInputAllocator,
+}
+
+impl MipGenerator {
+    /// Accesses images filled by `commands` need on top of their own.
+    pub const IMAGE_ACCESSES: [ImageAccess; 2] =
+        [ImageAccess::ShaderRead, ImageAccess::StorageWrite];
+
+    pub fn new(painter: Arc<Painter>) -> Result<Self, String> {
+        let downsample_pipeline = ComputePipeline::new(
+            painter.clone(),
+            DOWNSAMPLE_SHADER_CODE,
+            vec![vec![
+                ShaderInputBindingInfo {
+                    _type: ShaderInputType::SampledImage2d,
+                    count: 1,
+                    dynamic: false,
+                    stages: vk::ShaderStageFlags::COMPUTE,
+                },
+                ShaderInputBindingInfo {
+                    _type: ShaderInputType::StorageImage2d,
+                    count: 1,
+                    dynamic: false,
+                    stages: vk::ShaderStageFlags::COMPUTE,
+                },
+            ]],
+            vec![],
+        )
+        .map_err(|e| format!("at create mip downsample pipeline: {e}"))?;
+        let shader_input_allocator = ShaderInputAllocator::for_layouts(
+            painter.clone(),
+            &[(
+                &downsample_pipeline.shader_input_layouts[0],
+                MAX_DOWNSAMPLE_SETS,
+            )],
+        )
+        .map_err(|e| format!("at create mip downsample allocator: {e}"))?;
+        Ok(Self {
+            painter,
+            downsample_pipeline,
+            shader_input_allocator,
+        })
+    }
+
+    /// Whether textures of `format` get their levels downsampled. Formats that can be
+    /// filtered linearly are left to blits, and ones that can't be storage images, like
+    /// block compressed formats, stay a single level.
+    pub fn downsamples(&self, format: vk::Format) -> bool {
+        let features = unsafe {
+            self.painter
+                .instance
+                .get_physical_device_format_properties(self.painter.physical_device, format)
+                .optimal_tiling_features
+        };
+        let blit_features = vk::FormatFeatureFlags::BLIT_SRC
+            | vk::FormatFeatureFlags::BLIT_DST
+            | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR;
+        !features.contains(blit_features)
+            && features.contains(vk::FormatFeatureFlags::STORAGE_IMAGE)
+            && self
+                .painter
+                .supported_features()
+                .shader_storage_image_write_without_format
+    }
+
+    /// The command filling `image`'s levels, and the shader inputs it uses. They have to be
+    /// handed back with `free_shader_inputs` once the command has run.
+    pub fn commands<'a>(
+        &'a self,
+        image: &'a Image2d,
+    ) -> Result<(GpuCommand<'a>, Vec<vk::DescriptorSet>), String> {
+        let mut descriptor_sets = vec![];
+        for level in 1..image.mip_levels as usize {
+            let descriptor_set = match self
+                .downsample_pipeline
+                .make_shader_inputs(&self.shader_input_allocator)
+            {
+                Ok(mut descriptor_sets) => descriptor_sets.swap_remove(0),
+                Err(e) => {
+                    self.free_shader_inputs(descriptor_sets)?;
+                    return Err(format!("at allocate mip downsample inputs: {e}"));
+                }
+            };
+            self.painter.write_shader_input(
+                descriptor_set,
+                0,
+                0,
+                &ShaderInputValue::SampledImage2ds(vec![image.level_views[level - 1]]),
+            );
+            self.painter.write_shader_input(
+                descriptor_set,
+                1,
+                0,
+                &ShaderInputValue::StorageImage2ds(vec![image.level_views[level]]),
+            );
+            descriptor_sets.push(descriptor_set);
+        }
+        let downsample =
+            match MipDownsample::new(image, &self.downsample_pipeline, descriptor_sets.clone()) {
+                Ok(downsample) => downsample,
+                Err(e) => {
+                    self.free_shader_inputs(descriptor_sets)?;
+                    return Err(e);
+                }
+            };
+        Ok((
+            GpuCommand::DownsampleMipmaps { downsample },
+            descriptor_sets,
+        ))
+    }
+
+    pub fn free_shader_inputs(
+        &self,
+        descriptor_sets: Vec<vk::DescriptorSet>,
+    ) -> Result<(), String> {
+        for descriptor_set in descriptor_sets {
+            self.shader_input_allocator.free(descriptor_set)?;
+        }
+        Ok(())
+    }
+}
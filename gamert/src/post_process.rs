@@ -0,0 +1,216 @@
+use std::sync::Arc;
+
+use ash::vk;
+use include_bytes_aligned::include_bytes_aligned;
+use painter::{
+    ash, GAllocator, GpuCommand, GpuRenderPassCommand, Image2d, ImageAccess, Painter, RenderOutput,
+    RenderPipelineBuilder, ShaderInputAllocator, ShaderInputBindingInfo, ShaderInputType,
+    SingePassRenderPipeline,
+};
+
+static FULLSCREEN_VERTEX_SHADER_CODE: &[u8] =
+    include_bytes_aligned!(4, "shaders/fullscreen.vert.spv");
+/// Copies the input unchanged.
+pub static PASSTHROUGH_FRAGMENT_SHADER_CODE: &[u8] =
+    include_bytes_aligned!(4, "shaders/post_process_passthrough.frag.spv");
+
+struct PerFrameData {
+    descriptor_set: vk::DescriptorSet,
+    /// View the descriptor set points at, only rewritten when the input changes.
+    input_view: vk::ImageView,
+    output_image: Image2d,
+    render_output: RenderOutput,
+}
+
+/// Runs a fragment shader over every pixel of an intermediate image, sampling the previous
+/// pass' output. The fragment shader gets the uv at location 0, the input image at set 0
+/// binding 0 and a linear sampler at set 0 binding 1.
+pub struct PostProcessPass {
+    painter: Arc<Painter>,
+    pipeline: SingePassRenderPipeline,
+    sampler: vk::Sampler,
+    shader_input_allocator: ShaderInputAllocator,
+    per_frame_datas: Vec<PerFrameData>,
+    allocator: GAllocator,
+}
+
+impl PostProcessPass {
+    pub fn new(
+        painter: Arc<Painter>,
+        resolution: vk::Extent2D,
+        frame_count: usize,
+        output_format: vk::Format,
+        fragment_shader_code: &[u8],
+    ) -> Result<Self, String> {
+        let sampler = unsafe {
+            painter
+                .device
+                .create_sampler(
+                    &vk::SamplerCreateInfo::default()
+                        .mag_filter(vk::Filter::LINEAR)
+                        .min_filter(vk::Filter::LINEAR)
+                        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+                    None,
+                )
+                .map_err(|e| format!("at create sampler: {e}"))?
+        };
+
+        let pipeline = RenderPipelineBuilder::new()
+            .shader_inputs(vec![
+                ShaderInputBindingInfo {
+                    _type: ShaderInputType::SampledImage2d,
+                    count: 1,
+                    dynamic: false,
+                    stages: vk::ShaderStageFlags::FRAGMENT,
+                },
+                ShaderInputBindingInfo {
+                    _type: ShaderInputType::Sampler,
+                    count: 1,
+                    dynamic: false,
+                    stages: vk::ShaderStageFlags::FRAGMENT,
+                },
+            ])
+            // Every pixel is overwritten, so the old contents are never loaded
+            .color_attachment(
+                output_format,
+                vk::AttachmentLoadOp::DONT_CARE,
+                vk::AttachmentStoreOp::STORE,
+            )
+            .shaders(FULLSCREEN_VERTEX_SHADER_CODE, fragment_shader_code)
+            .build(painter.clone())
+            .map_err(|e| format!("at create post process pipeline: {e}"))?;
+
+        let shader_input_allocator = ShaderInputAllocator::for_frames(
+            painter.clone(),
+            &[&pipeline.shader_input_layouts[0]],
+            frame_count as u32,
+        )
+        .map_err(|e| format!("at create shader input allocator: {e}"))?;
+
+        let mut allocator =
+            GAllocator::new(painter.clone()).map_err(|e| format!("at create allocator: {e}"))?;
+
+        let per_frame_datas = (0..frame_count)
+            .map(|_| {
+                let descriptor_set = pipeline
+                    .make_shader_inputs(&shader_input_allocator)
+                    .map_err(|e| format!("at make shader inputs: {e}"))?
+                    .swap_remove(0);
+                unsafe {
+                    painter.device.update_descriptor_sets(
+                        &[vk::WriteDescriptorSet::default()
+                            .dst_set(descriptor_set)
+                            .dst_binding(1)
+                            .descriptor_type(vk::DescriptorType::SAMPLER)
+                            .descriptor_count(1)
+                            .image_info(&[vk::DescriptorImageInfo::default().sampler(sampler)])],
+                        &[],
+                    );
+                }
+                let output_image = painter
+                    .create_image_2d(
+                        output_format,
+                        resolution,
+                        vec![ImageAccess::PipelineAttachment, ImageAccess::TransferRead],
+                        Some(&mut allocator),
+                        Some(false),
+                        None,
+                    )
+                    .map_err(|e| format!("at create output image: {e}"))?;
+                let render_output = pipeline
+                    .create_render_output(vec![&output_image])
+                    .map_err(|e| format!("at create render output: {e}"))?;
+                Ok(PerFrameData {
+                    descriptor_set,
+                    input_view: vk::ImageView::null(),
+                    output_image,
+                    render_output,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Self {
+            painter,
+            pipeline,
+            sampler,
+            shader_input_allocator,
+            per_frame_datas,
+            allocator,
+        })
+    }
+
+    pub fn get_output_image(&self, frame_number: usize) -> &Image2d {
+        &self.per_frame_datas[frame_number % self.per_frame_datas.len()].output_image
+    }
+
+    /// Points this frame's descriptor set at `input`. Call before recording the frame, once
+    /// its previous submission has completed.
+    pub fn set_input(&mut self, frame_number: usize, input: &Image2d) {
+        let frame_number = frame_number % self.per_frame_datas.len();
+        let per_frame_data = &mut self.per_frame_datas[frame_number];
+        if per_frame_data.input_view == input.image_view {
+            return;
+        }
+        unsafe {
+            self.painter.device.update_descriptor_sets(
+                &[vk::WriteDescriptorSet::default()
+                    .dst_set(per_frame_data.descriptor_set)
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                    .descriptor_count(1)
+                    .image_info(&[vk::DescriptorImageInfo::default()
+                        .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                        .image_view(input.image_view)])],
+                &[],
+            );
+        }
+        per_frame_data.input_view = input.image_view;
+    }
+
+    /// `input` has to be the image passed to `set_input`, written earlier in the same
+    /// recording so it gets transitioned for sampling.
+    pub fn draw_commands<'a>(
+        &'a self,
+        frame_number: usize,
+        input: &'a Image2d,
+    ) -> Vec<GpuCommand<'a>> {
+        let per_frame_data = &self.per_frame_datas[frame_number % self.per_frame_datas.len()];
+        vec![
+            GpuCommand::ImageAccessHint {
+                image: input,
+                access: ImageAccess::ShaderRead,
+            },
+            GpuCommand::RunRenderPass {
+                render_pass: self.pipeline.render_pass,
+                render_output: &per_frame_data.render_output,
+                clear_values: vec![],
+                pipelines: vec![self.pipeline.pipeline],
+                pipeline_layouts: vec![self.pipeline.pipeline_layout],
+                commands: vec![
+                    GpuRenderPassCommand::BindPipeline { pipeline: 0 },
+                    GpuRenderPassCommand::BindShaderInput {
+                        pipeline_layout: 0,
+                        descriptor_sets: vec![per_frame_data.descriptor_set],
+                    },
+                    GpuRenderPassCommand::DrawVertices {
+                        count: 3,
+                        first_vertex: 0,
+                    },
+                ],
+            },
+        ]
+    }
+}
+
+impl Drop for PostProcessPass {
+    fn drop(&mut self) {
+        let _ = self
+            .painter
+            .wait_idle()
+            .inspect_err(|e| eprintln!("at wait for device idle: {e}"));
+        unsafe {
+            self.painter.device.destroy_sampler(self.sampler, None);
+        }
+    }
+}
@@ -1,9 +1,139 @@
 mod vertex;
 
+use ash::vk;
+
 pub use vertex::Vertex;
 
+#[derive(Debug, Clone)]
+pub enum Indices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+impl Indices {
+    pub fn len(&self) -> usize {
+        match self {
+            Indices::U16(indices) => indices.len(),
+            Indices::U32(indices) => indices.len(),
+        }
+    }
+
+    pub fn index_type(&self) -> vk::IndexType {
+        match self {
+            Indices::U16(_) => vk::IndexType::UINT16,
+            Indices::U32(_) => vk::IndexType::UINT32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: glam::Vec3,
+    pub max: glam::Vec3,
+}
+
+impl Aabb {
+    pub fn from_vertices(vertices: &[Vertex]) -> Self {
+        let mut min = glam::Vec3::splat(f32::MAX);
+        let mut max = glam::Vec3::splat(f32::MIN);
+        for vertex in vertices {
+            min = min.min(vertex.position.truncate());
+            max = max.max(vertex.position.truncate());
+        }
+        Self { min, max }
+    }
+
+    /// Smallest box holding this one after `transform`.
+    pub fn transformed(&self, transform: &glam::Mat4) -> Self {
+        let center = transform.transform_point3((self.min + self.max) * 0.5);
+        let half_extent = (self.max - self.min) * 0.5;
+        let half_extent = transform.x_axis.truncate().abs() * half_extent.x
+            + transform.y_axis.truncate().abs() * half_extent.y
+            + transform.z_axis.truncate().abs() * half_extent.z;
+        Self {
+            min: center - half_extent,
+            max: center + half_extent,
+        }
+    }
+
+    /// Tests against planes stored as `(normal, distance)` pointing into the frustum.
+    pub fn intersects_frustum(&self, planes: &[glam::Vec4; 6]) -> bool {
+        planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            let positive_vertex =
+                glam::Vec3::select(normal.cmpge(glam::Vec3::ZERO), self.max, self.min);
+            normal.dot(positive_vertex) + plane.w >= 0.0
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Mesh {
-    vertices: Vec<Vertex>,
-    indices: Vec<u32>,
+    pub(crate) vertices: Vec<Vertex>,
+    pub(crate) indices: Indices,
+    pub(crate) aabb: Aabb,
+    pub(crate) topology: vk::PrimitiveTopology,
+}
+
+impl Mesh {
+    pub fn new(vertices: Vec<Vertex>, indices: Indices) -> Self {
+        Self::with_topology(vertices, indices, vk::PrimitiveTopology::TRIANGLE_LIST)
+    }
+
+    pub fn with_topology(
+        vertices: Vec<Vertex>,
+        indices: Indices,
+        topology: vk::PrimitiveTopology,
+    ) -> Self {
+        let aabb = Aabb::from_vertices(&vertices);
+        Self {
+            vertices,
+            indices,
+            aabb,
+            topology,
+        }
+    }
+
+    /// Overwrites the tangents with ones computed from the UVs. Only triangle lists have
+    /// any to compute.
+    pub fn compute_tangents(&mut self) {
+        if self.topology == vk::PrimitiveTopology::TRIANGLE_LIST {
+            Vertex::compute_tangents(&mut self.vertices, &self.indices);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Aabb;
+
+    #[test]
+    fn transformed_box_holds_the_rotated_box() {
+        let aabb = Aabb {
+            min: glam::Vec3::new(-1.0, -2.0, -3.0),
+            max: glam::Vec3::new(1.0, 2.0, 3.0),
+        };
+        let transform = glam::Mat4::from_rotation_translation(
+            glam::Quat::from_rotation_z(std::f32::consts::FRAC_PI_2),
+            glam::Vec3::new(10.0, 0.0, 0.0),
+        );
+
+        let Aabb { min, max } = aabb.transformed(&transform);
+        assert!(min.abs_diff_eq(glam::Vec3::new(8.0, -1.0, -3.0), 1e-5));
+        assert!(max.abs_diff_eq(glam::Vec3::new(12.0, 1.0, 3.0), 1e-5));
+    }
+
+    #[test]
+    fn transformed_box_grows_for_diagonal_rotations() {
+        let aabb = Aabb {
+            min: glam::Vec3::new(-1.0, -1.0, 0.0),
+            max: glam::Vec3::new(1.0, 1.0, 0.0),
+        };
+        let transform = glam::Mat4::from_rotation_z(std::f32::consts::FRAC_PI_4);
+
+        let Aabb { min, max } = aabb.transformed(&transform);
+        let half_diagonal = std::f32::consts::SQRT_2;
+        assert!(min.abs_diff_eq(glam::Vec3::new(-half_diagonal, -half_diagonal, 0.0), 1e-5));
+        assert!(max.abs_diff_eq(glam::Vec3::new(half_diagonal, half_diagonal, 0.0), 1e-5));
+    }
 }
@@ -1,8 +1,87 @@
+use std::mem::offset_of;
+
+use ash::vk;
+
+use super::Indices;
+
+#[repr(C)]
 #[derive(Debug, Clone)]
 pub struct Vertex {
     pub position: glam::Vec4,
     pub normal: glam::Vec4,
-    // tangent: glam::Vec4,
-    // bitangent: glam::Vec4,
     pub tex_coords: glam::Vec4,
+    /// xyz is the tangent, w is the bitangent sign. Left zero to have `Mesh` compute it.
+    pub tangent: glam::Vec4,
+}
+
+impl Vertex {
+    pub fn get_binding_description() -> Vec<vk::VertexInputBindingDescription> {
+        vec![vk::VertexInputBindingDescription::default()
+            .binding(0)
+            .stride(size_of::<Self>() as u32)
+            .input_rate(vk::VertexInputRate::VERTEX)]
+    }
+
+    pub fn get_attribute_descriptions() -> Vec<vk::VertexInputAttributeDescription> {
+        [
+            offset_of!(Self, position),
+            offset_of!(Self, normal),
+            offset_of!(Self, tex_coords),
+            offset_of!(Self, tangent),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(location, offset)| {
+            vk::VertexInputAttributeDescription::default()
+                .binding(0)
+                .location(location as u32)
+                .format(vk::Format::R32G32B32A32_SFLOAT)
+                .offset(offset as u32)
+        })
+        .collect()
+    }
+
+    /// Fills in per vertex tangents from the triangle UVs, see `Mesh::compute_tangents`.
+    pub fn compute_tangents(vertices: &mut [Vertex], indices: &Indices) {
+        let indices: Vec<usize> = match indices {
+            Indices::U16(indices) => indices.iter().map(|&i| i as usize).collect(),
+            Indices::U32(indices) => indices.iter().map(|&i| i as usize).collect(),
+        };
+
+        let mut tangents = vec![glam::Vec3::ZERO; vertices.len()];
+        let mut bitangents = vec![glam::Vec3::ZERO; vertices.len()];
+        for triangle in indices.chunks_exact(3) {
+            let [i0, i1, i2] = [triangle[0], triangle[1], triangle[2]];
+            let edge1 = (vertices[i1].position - vertices[i0].position).truncate();
+            let edge2 = (vertices[i2].position - vertices[i0].position).truncate();
+            let duv1 = (vertices[i1].tex_coords - vertices[i0].tex_coords).truncate().truncate();
+            let duv2 = (vertices[i2].tex_coords - vertices[i0].tex_coords).truncate().truncate();
+
+            let det = duv1.x * duv2.y - duv2.x * duv1.y;
+            if det.abs() < f32::EPSILON {
+                continue;
+            }
+            let r = 1.0 / det;
+            let tangent = (edge1 * duv2.y - edge2 * duv1.y) * r;
+            let bitangent = (edge2 * duv1.x - edge1 * duv2.x) * r;
+            for i in [i0, i1, i2] {
+                tangents[i] += tangent;
+                bitangents[i] += bitangent;
+            }
+        }
+
+        for (i, vertex) in vertices.iter_mut().enumerate() {
+            let normal = vertex.normal.truncate();
+            // Gram-Schmidt against the normal, fall back to any perpendicular axis
+            let tangent = (tangents[i] - normal * normal.dot(tangents[i]))
+                .try_normalize()
+                .unwrap_or_else(|| normal.any_orthonormal_vector());
+            let sign = if normal.cross(tangent).dot(bitangents[i]) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            vertex.tangent = tangent.extend(sign);
+        }
+    }
 }
@@ -0,0 +1,19 @@
+#version 460 core
+
+layout (location = 0) in vec3 inPosition;
+layout (location = 1) in vec4 inColor;
+
+layout (location = 0) out vec4 outColor;
+
+layout(push_constant) uniform LineInfo {
+    mat4 view_proj;
+} line_info;
+
+vec4 invert_y_axis(vec4 v) {
+    return vec4(v.x, -v.y, v.z, v.w);
+}
+
+void main() {
+    outColor = inColor;
+    gl_Position = invert_y_axis(line_info.view_proj * vec4(inPosition, 1.0));
+}
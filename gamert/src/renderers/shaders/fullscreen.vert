@@ -0,0 +1,10 @@
+#version 460 core
+
+layout (location = 0) out vec2 outUV;
+
+void main() {
+    // One triangle covering the screen. (-1, -1), (-1, 3), (3, -1) is counter clockwise by
+    // Vulkan's signed area in framebuffer coordinates, so BACK culling keeps it
+    outUV = vec2(gl_VertexIndex & 2, (gl_VertexIndex << 1) & 2);
+    gl_Position = vec4(outUV * 2.0 - 1.0, 0.0, 1.0);
+}
@@ -5,14 +5,47 @@
 layout (location = 0) in vec3 inPosition;
 layout (location = 1) in vec2 inUV;
 layout (location = 2) flat in uint objId;
+layout (location = 3) in vec3 inNormal;
+layout (location = 4) in vec4 inTangent;
 
 layout (location = 0) out vec4 outFragColor;
 
-layout(std430, set = 0, binding = 2) buffer readonly ssbo3 { ObjectInfo object_buffer [];};
-layout(set = 1, binding = 0) uniform sampler samplers[1];
-layout(set = 2, binding = 0) uniform texture2D textures[];
+layout(std430, set = 0, binding = 0) buffer readonly ssbo1 {
+    Camera camera;
+    uint light_count;
+    PointLight lights[MAX_POINT_LIGHTS];
+};
+layout(set = 0, binding = 1) uniform sampler samplers[MAX_SAMPLERS];
+layout(std430, set = 0, binding = 2) buffer readonly ssbo2 { ObjectMaterial objects[]; };
+layout(set = 1, binding = 0) uniform texture2D textures[];
+
+const uint NO_TEXTURE = 0xFFFFFFFF;
+const vec3 LIGHT_DIR = normalize(vec3(0.3, 0.5, 1.0));
 
 void main() {
-    outFragColor = texture(sampler2D(textures[object_buffer[objId].tex_id], samplers[0]), inUV);
+    ObjectMaterial material = objects[objId];
+    vec4 color = texture(sampler2D(textures[nonuniformEXT(material.tex_id)], samplers[nonuniformEXT(material.sampler_id)]), inUV) * material.tint;
+    vec3 normal = normalize(inNormal);
+    vec3 light = vec3(1.0);
+    if (material.normal_tex_id != NO_TEXTURE) {
+        vec3 N = normal;
+        vec3 T = normalize(inTangent.xyz - N * dot(N, inTangent.xyz));
+        vec3 B = cross(N, T) * inTangent.w;
+        mat3 TBN = mat3(T, B, N);
+        vec3 tangent_normal = texture(sampler2D(textures[nonuniformEXT(material.normal_tex_id)], samplers[nonuniformEXT(material.sampler_id)]), inUV).xyz * 2.0 - 1.0;
+        normal = normalize(TBN * tangent_normal);
+        light = vec3(max(dot(normal, LIGHT_DIR), 0.0));
+    }
+    for (uint i = 0; i < light_count; i++) {
+        vec3 to_light = lights[i].pos.xyz - inPosition;
+        float dist = length(to_light);
+        // Smooth falloff reaching zero at the light's range
+        float attenuation = clamp(1.0 - dist / lights[i].props.x, 0.0, 1.0);
+        attenuation *= attenuation;
+        float n_dot_l = max(dot(normal, to_light / max(dist, 0.0001)), 0.0);
+        light += lights[i].color.rgb * n_dot_l * attenuation;
+    }
+    color.rgb *= light;
+    outFragColor = color;
     //outFragColor = vec4(1.0,1.0,1.0,1.0);
 }
@@ -2,38 +2,41 @@
 
 #include "mesh_painter_common.glsl"
 
+layout (location = 0) in vec4 inPosition;
+layout (location = 1) in vec4 inNormal;
+layout (location = 2) in vec4 inTexCoords;
+layout (location = 3) in vec4 inTangent;
+
 layout (location = 0) out vec3 outPosition;
 layout (location = 1) out vec2 outUV;
 layout (location = 2) flat out uint objId;
+layout (location = 3) out vec3 outNormal;
+layout (location = 4) out vec4 outTangent;
 
-layout(std430, set = 0, binding = 0) buffer readonly ssbo1 { GpuVertex vertex_buffer []; }; 
-layout(std430, set = 0, binding = 1) buffer readonly ssbo2 { Camera camera;};
+// The depth prepass and the EQUAL tested color pass must produce identical depths
+invariant gl_Position;
 
-vec4 invert_y_axis(vec4 v) {
-    return vec4(v.x, -v.y, v.z, v.w);
-}
+layout(std430, set = 0, binding = 0) buffer readonly ssbo1 { Camera camera;};
 
-vec3 getVertexPos(int index) {
-    return vec3(
-        vertex_buffer[index].pos[0],
-        vertex_buffer[index].pos[1],
-        vertex_buffer[index].pos[2]
-    );
-}
+layout(push_constant) uniform ObjectVertexInfo {
+    mat4 model;
+    uint obj_id;
+    uint mesh_id;
+} object_info;
 
-vec2 getVertexUv(int index) {
-    return vec2(
-        vertex_buffer[index].uv[0],
-        vertex_buffer[index].uv[1]
-    );
+vec4 invert_y_axis(vec4 v) {
+    return vec4(v.x, -v.y, v.z, v.w);
 }
 
 void main() {
-    vec3 inPosition = getVertexPos(gl_VertexIndex);
-    vec2 inUV = getVertexUv(gl_VertexIndex);
-    outPosition = inPosition;
-    outUV = inUV;
-    objId = vertex_buffer[gl_VertexIndex].obj_id;
-    gl_Position = invert_y_axis(camera.view_proj_mat * vec4(inPosition, 1.0));
+    vec4 world_position = object_info.model * vec4(inPosition.xyz, 1.0);
+    // Keeps normals perpendicular to the surface under non uniform scale
+    mat3 normal_mat = transpose(inverse(mat3(object_info.model)));
+    outPosition = world_position.xyz;
+    outUV = inTexCoords.xy;
+    objId = object_info.obj_id;
+    outNormal = normal_mat * inNormal.xyz;
+    outTangent = vec4(mat3(object_info.model) * inTangent.xyz, inTangent.w);
+    gl_Position = invert_y_axis(camera.view_proj_mat * world_position);
     // debugPrintfEXT("My vec is %v", gl_Position);
 }
@@ -8,17 +8,32 @@ struct Camera {
   mat4 view_proj_mat;
 };
 
+// Matches GpuPointLight in mesh_painter.rs, props.x is the range
 struct PointLight{
   vec4 pos;
   vec4 color;
   vec4 props;
 };
 
+const uint MAX_POINT_LIGHTS = 64;
+// Matches MAX_SAMPLERS in mesh_painter.rs
+const uint MAX_SAMPLERS = 16;
+
 struct ObjectInfo {
   uint sampler_id;
   uint tex_id;
 };
 
+// Matches GpuObjectFragmentInfo in mesh_painter.rs
+struct ObjectMaterial {
+  vec4 tint;
+  uint tex_id;
+  uint normal_tex_id;
+  float metallic;
+  float roughness;
+  uint sampler_id;
+};
+
 struct GpuVertex {
   float pos[3];
   float uv[2];
@@ -0,0 +1,12 @@
+#version 460 core
+
+layout (location = 0) in vec2 inUV;
+
+layout (location = 0) out vec4 outFragColor;
+
+layout(set = 0, binding = 0) uniform texture2D inputImage;
+layout(set = 0, binding = 1) uniform sampler inputSampler;
+
+void main() {
+    outFragColor = texture(sampler2D(inputImage, inputSampler), inUV);
+}
@@ -0,0 +1,12 @@
+#version 460 core
+
+layout (location = 0) in vec3 inDirection;
+
+layout (location = 0) out vec4 outFragColor;
+
+layout(set = 0, binding = 0) uniform textureCube skyboxImage;
+layout(set = 0, binding = 1) uniform sampler skyboxSampler;
+
+void main() {
+    outFragColor = texture(samplerCube(skyboxImage, skyboxSampler), inDirection);
+}
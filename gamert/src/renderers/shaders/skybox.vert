@@ -0,0 +1,31 @@
+#version 460 core
+
+layout (location = 0) out vec3 outDirection;
+
+layout(push_constant) uniform SkyboxInfo {
+    mat4 view_proj;
+    float far_depth;
+} skybox_info;
+
+// Corners of a [-1, 1] cube, indexed by their x, y and z sides as the bits 1, 2 and 4. Each
+// face is wound counter clockwise as seen from inside, so BACK culling keeps the inner faces
+const int CORNERS[36] = int[](
+    0, 1, 3, 0, 3, 2, // -Z
+    5, 4, 6, 5, 6, 7, // +Z
+    4, 0, 2, 4, 2, 6, // -X
+    1, 5, 7, 1, 7, 3, // +X
+    4, 5, 1, 4, 1, 0, // -Y
+    2, 3, 7, 2, 7, 6  // +Y
+);
+
+vec4 invert_y_axis(vec4 v) {
+    return vec4(v.x, -v.y, v.z, v.w);
+}
+
+void main() {
+    int corner = CORNERS[gl_VertexIndex];
+    outDirection = vec3(corner & 1, (corner >> 1) & 1, (corner >> 2) & 1) * 2.0 - 1.0;
+    vec4 position = invert_y_axis(skybox_info.view_proj * vec4(outDirection, 1.0));
+    // Pinned to the far plane, behind everything the mesh pass drew
+    gl_Position = vec4(position.xy, skybox_info.far_depth * position.w, position.w);
+}
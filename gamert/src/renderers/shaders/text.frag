@@ -0,0 +1,12 @@
+#version 460 core
+
+layout (location = 0) in vec2 inUV;
+layout (location = 1) in vec4 inColor;
+
+layout (location = 0) out vec4 outFragColor;
+
+layout(set = 0, binding = 0) uniform sampler2D fontAtlas;
+
+void main() {
+    outFragColor = texture(fontAtlas, inUV) * inColor;
+}
@@ -0,0 +1,18 @@
+#version 460 core
+
+layout (location = 0) in vec2 inPosition;
+layout (location = 1) in vec2 inUV;
+layout (location = 2) in vec4 inColor;
+
+layout (location = 0) out vec2 outUV;
+layout (location = 1) out vec4 outColor;
+
+layout(push_constant) uniform TextInfo {
+    mat4 projection;
+} text_info;
+
+void main() {
+    outUV = inUV;
+    outColor = inColor;
+    gl_Position = text_info.projection * vec4(inPosition, 0.0, 1.0);
+}
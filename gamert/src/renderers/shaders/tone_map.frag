@@ -0,0 +1,58 @@
+#version 460 core
+
+layout (location = 0) in vec2 inUV;
+
+layout (location = 0) out vec4 outFragColor;
+
+layout(set = 0, binding = 0) uniform texture2D inputImage;
+layout(set = 0, binding = 1) uniform sampler inputSampler;
+
+layout(push_constant) uniform ToneMapParams {
+    float exposure;
+    uint mode;
+    uint encode_srgb;
+    uint quarter_turns;
+} params;
+
+const uint TONE_MAP_NONE = 0;
+const uint TONE_MAP_REINHARD = 1;
+const uint TONE_MAP_ACES = 2;
+
+vec3 aces(vec3 x) {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), 0.0, 1.0);
+}
+
+vec3 linear_to_srgb(vec3 color) {
+    vec3 low = color * 12.92;
+    vec3 high = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(color, vec3(0.0031308)));
+}
+
+// Where the input pixel drawn at the target's uv comes from, with the input rotated clockwise
+vec2 rotated_uv(vec2 uv) {
+    if (params.quarter_turns == 1) {
+        return vec2(uv.y, 1.0 - uv.x);
+    } else if (params.quarter_turns == 2) {
+        return 1.0 - uv;
+    } else if (params.quarter_turns == 3) {
+        return vec2(1.0 - uv.y, uv.x);
+    }
+    return uv;
+}
+
+void main() {
+    vec4 color = texture(sampler2D(inputImage, inputSampler), rotated_uv(inUV));
+    vec3 exposed = color.rgb * params.exposure;
+    vec3 mapped;
+    if (params.mode == TONE_MAP_REINHARD) {
+        mapped = exposed / (1.0 + exposed);
+    } else if (params.mode == TONE_MAP_ACES) {
+        mapped = aces(exposed);
+    } else {
+        mapped = clamp(exposed, 0.0, 1.0);
+    }
+    if (params.encode_srgb != 0) {
+        mapped = linear_to_srgb(mapped);
+    }
+    outFragColor = vec4(mapped, color.a);
+}
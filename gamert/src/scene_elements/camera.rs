@@ -9,9 +9,11 @@ pub struct Camera {
 }
 
 impl Camera {
-    pub fn new(pos: glam::Vec4, look_at: glam::Vec4) -> Self {
+    /// Objects closer than `near` or farther than `far` are clipped. Depth precision depends
+    /// on the ratio between them, so keep `near` as large as the scene allows.
+    pub fn new(pos: glam::Vec4, look_at: glam::Vec4, near: f32, far: f32) -> Self {
         let view = glam::Mat4::look_at_rh(pos.xyz(), look_at.xyz(), glam::Vec3::new(0.0, 1.0, 0.0));
-        let proj = glam::Mat4::perspective_rh(90.0f32.to_radians(), 1.0, 0.1, 100.0);
+        let proj = glam::Mat4::perspective_rh(90.0f32.to_radians(), 1.0, near, far);
         let view_proj = proj * view;
         Self {
             pos,
@@ -19,4 +21,104 @@ impl Camera {
             view_proj,
         }
     }
+
+    /// Uses a reversed depth range, near maps to 1 and far to 0, which spreads float depth
+    /// precision evenly over distance. Needs a `GREATER` depth test and a 0.0 depth clear.
+    pub fn new_reverse_z(pos: glam::Vec4, look_at: glam::Vec4, near: f32, far: f32) -> Self {
+        let view = glam::Mat4::look_at_rh(pos.xyz(), look_at.xyz(), glam::Vec3::new(0.0, 1.0, 0.0));
+        let proj = glam::Mat4::perspective_rh(90.0f32.to_radians(), 1.0, far, near);
+        let view_proj = proj * view;
+        Self {
+            pos,
+            look_at,
+            view_proj,
+        }
+    }
+
+    /// Like `new`, with near and far fit tightly around the scene's bounding box so nothing
+    /// in it is clipped and depth precision isn't spent on empty space.
+    pub fn fit_to_bounds(
+        pos: glam::Vec4,
+        look_at: glam::Vec4,
+        scene_min: glam::Vec3,
+        scene_max: glam::Vec3,
+    ) -> Self {
+        let (near, far) = Self::depth_range_for_bounds(pos, look_at, scene_min, scene_max);
+        Self::new(pos, look_at, near, far)
+    }
+
+    /// Nearest and farthest view depth of the box corners, padded slightly. Corners behind the
+    /// camera are ignored, and near is kept to a small fraction of far for when the camera
+    /// is inside the box.
+    fn depth_range_for_bounds(
+        pos: glam::Vec4,
+        look_at: glam::Vec4,
+        scene_min: glam::Vec3,
+        scene_max: glam::Vec3,
+    ) -> (f32, f32) {
+        const MIN_NEAR: f32 = 0.001;
+        const MAX_FAR_TO_NEAR_RATIO: f32 = 10_000.0;
+
+        let forward = (look_at.xyz() - pos.xyz()).normalize_or(glam::Vec3::NEG_Z);
+        let (mut nearest, mut farthest) = (f32::MAX, MIN_NEAR);
+        for i in 0..8 {
+            let corner = glam::vec3(
+                if i & 1 == 0 { scene_min.x } else { scene_max.x },
+                if i & 2 == 0 { scene_min.y } else { scene_max.y },
+                if i & 4 == 0 { scene_min.z } else { scene_max.z },
+            );
+            let depth = (corner - pos.xyz()).dot(forward);
+            nearest = nearest.min(depth);
+            farthest = farthest.max(depth);
+        }
+        let far = farthest * 1.01;
+        let near = (nearest * 0.99)
+            .max(far / MAX_FAR_TO_NEAR_RATIO)
+            .max(MIN_NEAR);
+        (near, far.max(near * 2.0))
+    }
+
+    /// Frustum planes in world space, normals point inwards. Assumes a `[0, 1]` depth range.
+    pub fn frustum_planes(&self) -> [glam::Vec4; 6] {
+        let m = self.view_proj;
+        let planes = [
+            m.row(3) + m.row(0),
+            m.row(3) - m.row(0),
+            m.row(3) + m.row(1),
+            m.row(3) - m.row(1),
+            m.row(2),
+            m.row(3) - m.row(2),
+        ];
+        planes.map(|plane| plane / plane.truncate().length())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Camera;
+
+    #[test]
+    fn depth_range_fits_the_box_ahead() {
+        let (near, far) = Camera::depth_range_for_bounds(
+            glam::Vec4::new(0.0, 0.0, 10.0, 1.0),
+            glam::Vec4::new(0.0, 0.0, 0.0, 1.0),
+            glam::Vec3::splat(-1.0),
+            glam::Vec3::splat(1.0),
+        );
+        // Nearest corners are 9 away along the view direction, farthest ones 11
+        assert!((near - 9.0 * 0.99).abs() < 1e-4);
+        assert!((far - 11.0 * 1.01).abs() < 1e-4);
+    }
+
+    #[test]
+    fn depth_range_keeps_near_positive_inside_the_box() {
+        let (near, far) = Camera::depth_range_for_bounds(
+            glam::Vec4::new(0.0, 0.0, 0.0, 1.0),
+            glam::Vec4::new(0.0, 0.0, -1.0, 1.0),
+            glam::Vec3::splat(-100.0),
+            glam::Vec3::splat(100.0),
+        );
+        assert!((far - 100.0 * 1.01).abs() < 1e-3);
+        assert!((near - far / 10_000.0).abs() < 1e-6);
+    }
 }
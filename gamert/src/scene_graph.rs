@@ -0,0 +1,178 @@
+use painter::slotmap::{new_key_type, SlotMap};
+
+use crate::mesh_painter::{DrawableMeshAndTexture, MaterialID, MeshID};
+
+new_key_type! {
+    pub struct NodeId;
+}
+
+struct Node {
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    /// Relative to the parent, or world space for root nodes.
+    local: glam::Mat4,
+    drawable: DrawableMeshAndTexture,
+}
+
+/// Drawables arranged in a tree, each placed relative to its parent, so moving a node moves
+/// everything attached to it.
+#[derive(Default)]
+pub struct SceneGraph {
+    nodes: SlotMap<NodeId, Node>,
+    roots: Vec<NodeId>,
+}
+
+impl SceneGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_node(
+        &mut self,
+        parent: Option<NodeId>,
+        local: glam::Mat4,
+        mesh: MeshID,
+        material: MaterialID,
+    ) -> Result<NodeId, String> {
+        if let Some(parent) = parent {
+            if !self.nodes.contains_key(parent) {
+                return Err(format!("at add node: parent {parent:?} not found"));
+            }
+        }
+        let node_id = self.nodes.insert(Node {
+            parent,
+            children: vec![],
+            local,
+            drawable: DrawableMeshAndTexture::new(mesh, material),
+        });
+        match parent {
+            Some(parent) => self.nodes[parent].children.push(node_id),
+            None => self.roots.push(node_id),
+        }
+        Ok(node_id)
+    }
+
+    /// Removes the node along with all of its descendants.
+    pub fn remove_node(&mut self, node_id: NodeId) -> Result<(), String> {
+        let node = self
+            .nodes
+            .remove(node_id)
+            .ok_or(format!("at remove node: node {node_id:?} not found"))?;
+        let siblings = match node.parent {
+            Some(parent) => &mut self.nodes[parent].children,
+            None => &mut self.roots,
+        };
+        siblings.retain(|&sibling| sibling != node_id);
+        let mut to_remove = node.children;
+        while let Some(child) = to_remove.pop() {
+            if let Some(child) = self.nodes.remove(child) {
+                to_remove.extend(child.children);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set_local_transform(
+        &mut self,
+        node_id: NodeId,
+        local: glam::Mat4,
+    ) -> Result<(), String> {
+        let node = self
+            .nodes
+            .get_mut(node_id)
+            .ok_or(format!("at set transform: node {node_id:?} not found"))?;
+        node.local = local;
+        Ok(())
+    }
+
+    /// For the node's tint and material. Its `transform` is overwritten by `flatten`.
+    pub fn drawable_mut(&mut self, node_id: NodeId) -> Option<&mut DrawableMeshAndTexture> {
+        self.nodes.get_mut(node_id).map(|node| &mut node.drawable)
+    }
+
+    /// Drawables with their world transforms, parents before children, for
+    /// `MeshPainter::update_inputs`.
+    pub fn flatten(&self) -> Vec<DrawableMeshAndTexture> {
+        let mut drawables = Vec::with_capacity(self.nodes.len());
+        let mut stack = self
+            .roots
+            .iter()
+            .rev()
+            .map(|&root| (root, glam::Mat4::IDENTITY))
+            .collect::<Vec<_>>();
+        while let Some((node_id, parent_world)) = stack.pop() {
+            let node = &self.nodes[node_id];
+            let world = parent_world * node.local;
+            drawables.push(DrawableMeshAndTexture {
+                transform: world,
+                ..node.drawable
+            });
+            stack.extend(node.children.iter().rev().map(|&child| (child, world)));
+        }
+        drawables
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SceneGraph;
+    use crate::mesh_painter::{MaterialID, MeshID};
+
+    #[test]
+    fn children_are_placed_relative_to_their_parent() {
+        let mut scene_graph = SceneGraph::new();
+        let parent_local = glam::Mat4::from_rotation_translation(
+            glam::Quat::from_rotation_z(std::f32::consts::FRAC_PI_2),
+            glam::Vec3::new(1.0, 0.0, 0.0),
+        );
+        let child_local = glam::Mat4::from_translation(glam::Vec3::new(2.0, 0.0, 0.0));
+        let parent = scene_graph
+            .add_node(None, parent_local, MeshID::default(), MaterialID::default())
+            .unwrap();
+        scene_graph
+            .add_node(
+                Some(parent),
+                child_local,
+                MeshID::default(),
+                MaterialID::default(),
+            )
+            .unwrap();
+
+        let drawables = scene_graph.flatten();
+        assert_eq!(drawables.len(), 2);
+        assert_eq!(drawables[0].transform, parent_local);
+        // The child's offset along x is rotated onto the parent's y axis
+        let child_origin = drawables[1].transform.transform_point3(glam::Vec3::ZERO);
+        assert!(child_origin.abs_diff_eq(glam::Vec3::new(1.0, 2.0, 0.0), 1e-5));
+
+        scene_graph
+            .set_local_transform(parent, glam::Mat4::IDENTITY)
+            .unwrap();
+        let drawables = scene_graph.flatten();
+        assert_eq!(drawables[1].transform, child_local);
+    }
+
+    #[test]
+    fn removing_a_node_removes_its_descendants() {
+        let mut scene_graph = SceneGraph::new();
+        let parent = scene_graph
+            .add_node(
+                None,
+                glam::Mat4::IDENTITY,
+                MeshID::default(),
+                MaterialID::default(),
+            )
+            .unwrap();
+        scene_graph
+            .add_node(
+                Some(parent),
+                glam::Mat4::IDENTITY,
+                MeshID::default(),
+                MaterialID::default(),
+            )
+            .unwrap();
+
+        scene_graph.remove_node(parent).unwrap();
+        assert!(scene_graph.flatten().is_empty());
+    }
+}
@@ -0,0 +1,237 @@
+use std::sync::Arc;
+
+use ash::vk;
+use include_bytes_aligned::include_bytes_aligned;
+use painter::{
+    ash, GpuCommand, GpuRenderPassCommand, Image2d, Painter, RenderOutput, RenderPipelineBuilder,
+    ShaderInputAllocator, ShaderInputBindingInfo, ShaderInputType, ShaderInputValue,
+    SingePassRenderPipeline,
+};
+
+static VERTEX_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/skybox.vert.spv");
+static FRAGMENT_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/skybox.frag.spv");
+
+/// Must match the push constant block in skybox.vert
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct SkyboxParams {
+    /// Without the camera's translation, so the cube stays centered on it.
+    view_proj: glam::Mat4,
+    /// Clip space depth of the far plane, 0 with reverse Z.
+    far_depth: f32,
+}
+
+struct PerFrameData {
+    descriptor_set: vk::DescriptorSet,
+    params: SkyboxParams,
+    /// View the descriptor set points at, only rewritten when the cube changes.
+    cube_view: vk::ImageView,
+    /// Views the render output was made for, only recreated when the targets change.
+    target_views: (vk::ImageView, vk::ImageView),
+    render_output: Option<RenderOutput>,
+}
+
+/// Draws a cube image around the camera over the `MeshPainter` output, where nothing was
+/// drawn. The inside of a cube is drawn at the far plane and tested against the mesh pass
+/// depth with `LESS_OR_EQUAL`, or `GREATER_OR_EQUAL` with reverse Z, which needs a
+/// `MeshPainter` created with `AttachmentStoreOp::STORE`. The cube is made with
+/// `Painter::create_image_cube` and sampled in `+X, -X, +Y, -Y, +Z, -Z` face order.
+pub struct SkyboxPass {
+    painter: Arc<Painter>,
+    pipeline: SingePassRenderPipeline,
+    sampler: vk::Sampler,
+    shader_input_allocator: ShaderInputAllocator,
+    per_frame_datas: Vec<PerFrameData>,
+}
+
+impl SkyboxPass {
+    /// `reverse_z` has to be the mesh pass's, see `MeshPainter::new`.
+    pub fn new(
+        painter: Arc<Painter>,
+        frame_count: usize,
+        color_format: vk::Format,
+        depth_format: vk::Format,
+        reverse_z: bool,
+    ) -> Result<Self, String> {
+        let sampler = unsafe {
+            painter
+                .device
+                .create_sampler(
+                    &vk::SamplerCreateInfo::default()
+                        .mag_filter(vk::Filter::LINEAR)
+                        .min_filter(vk::Filter::LINEAR)
+                        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+                    None,
+                )
+                .map_err(|e| format!("at create sampler: {e}"))?
+        };
+
+        let (depth_compare_op, far_depth) = if reverse_z {
+            (vk::CompareOp::GREATER_OR_EQUAL, 0.0)
+        } else {
+            (vk::CompareOp::LESS_OR_EQUAL, 1.0)
+        };
+        let pipeline = RenderPipelineBuilder::new()
+            .shader_inputs(vec![
+                ShaderInputBindingInfo {
+                    _type: ShaderInputType::SampledCube,
+                    count: 1,
+                    dynamic: false,
+                    stages: vk::ShaderStageFlags::FRAGMENT,
+                },
+                ShaderInputBindingInfo {
+                    _type: ShaderInputType::Sampler,
+                    count: 1,
+                    dynamic: false,
+                    stages: vk::ShaderStageFlags::FRAGMENT,
+                },
+            ])
+            // Only pixels left at the cleared depth are filled in
+            .color_attachment(
+                color_format,
+                vk::AttachmentLoadOp::LOAD,
+                vk::AttachmentStoreOp::STORE,
+            )
+            .depth_attachment(
+                depth_format,
+                vk::AttachmentLoadOp::LOAD,
+                vk::AttachmentStoreOp::DONT_CARE,
+            )
+            .push_constants(
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                size_of::<SkyboxParams>() as u32,
+            )
+            .shaders(VERTEX_SHADER_CODE, FRAGMENT_SHADER_CODE)
+            .depth_test(depth_compare_op, false)
+            .build(painter.clone())
+            .map_err(|e| format!("at create skybox pipeline: {e}"))?;
+
+        let shader_input_allocator = ShaderInputAllocator::for_frames(
+            painter.clone(),
+            &[&pipeline.shader_input_layouts[0]],
+            frame_count as u32,
+        )
+        .map_err(|e| format!("at create shader input allocator: {e}"))?;
+
+        let per_frame_datas = (0..frame_count)
+            .map(|_| {
+                let descriptor_set = pipeline
+                    .make_shader_inputs(&shader_input_allocator)
+                    .map_err(|e| format!("at make shader inputs: {e}"))?
+                    .swap_remove(0);
+                painter.write_shader_input(
+                    descriptor_set,
+                    1,
+                    0,
+                    &ShaderInputValue::Samplers(vec![sampler]),
+                );
+                Ok(PerFrameData {
+                    descriptor_set,
+                    params: SkyboxParams {
+                        view_proj: glam::Mat4::IDENTITY,
+                        far_depth,
+                    },
+                    cube_view: vk::ImageView::null(),
+                    target_views: (vk::ImageView::null(), vk::ImageView::null()),
+                    render_output: None,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Self {
+            painter,
+            pipeline,
+            sampler,
+            shader_input_allocator,
+            per_frame_datas,
+        })
+    }
+
+    /// Sets the camera this frame's skybox is seen through, by its `view_proj` and position.
+    pub fn update_inputs(&mut self, frame_number: usize, view_proj: glam::Mat4, eye: glam::Vec3) {
+        let frame_number = frame_number % self.per_frame_datas.len();
+        self.per_frame_datas[frame_number].params.view_proj =
+            view_proj * glam::Mat4::from_translation(eye);
+    }
+
+    /// Points this frame's descriptor set at `cube` and its draw at the mesh pass's color and
+    /// depth images. `cube` has to be left in `ImageAccess::ShaderRead` by its upload. Call
+    /// before recording the frame, once its previous submission has completed.
+    pub fn set_images(
+        &mut self,
+        frame_number: usize,
+        cube: &Image2d,
+        color: &Image2d,
+        depth: &Image2d,
+    ) -> Result<(), String> {
+        let frame_number = frame_number % self.per_frame_datas.len();
+        let per_frame_data = &mut self.per_frame_datas[frame_number];
+        if per_frame_data.cube_view != cube.image_view {
+            self.painter.write_shader_input(
+                per_frame_data.descriptor_set,
+                0,
+                0,
+                &ShaderInputValue::SampledCubes(vec![cube.image_view]),
+            );
+            per_frame_data.cube_view = cube.image_view;
+        }
+        let target_views = (color.image_view, depth.image_view);
+        if per_frame_data.target_views != target_views {
+            per_frame_data.render_output = Some(
+                self.pipeline
+                    .create_render_output(vec![color, depth])
+                    .map_err(|e| format!("at create skybox render output: {e}"))?,
+            );
+            per_frame_data.target_views = target_views;
+        }
+        Ok(())
+    }
+
+    /// The targets set with `set_images` have to be written earlier in the same recording.
+    pub fn draw_commands(&self, frame_number: usize) -> Result<Vec<GpuCommand<'_>>, String> {
+        let per_frame_data = &self.per_frame_datas[frame_number % self.per_frame_datas.len()];
+        let render_output = per_frame_data
+            .render_output
+            .as_ref()
+            .ok_or("at skybox draw: no target set".to_string())?;
+        Ok(vec![GpuCommand::RunRenderPass {
+            render_pass: self.pipeline.render_pass,
+            render_output,
+            clear_values: vec![],
+            pipelines: vec![self.pipeline.pipeline],
+            pipeline_layouts: vec![self.pipeline.pipeline_layout],
+            commands: vec![
+                GpuRenderPassCommand::BindPipeline { pipeline: 0 },
+                GpuRenderPassCommand::BindShaderInput {
+                    pipeline_layout: 0,
+                    descriptor_sets: vec![per_frame_data.descriptor_set],
+                },
+                self.pipeline.make_push_constant_command(
+                    0,
+                    vk::ShaderStageFlags::VERTEX,
+                    0,
+                    unsafe { [per_frame_data.params].align_to::<u8>().1.to_vec() },
+                )?,
+                GpuRenderPassCommand::DrawVertices {
+                    count: 36,
+                    first_vertex: 0,
+                },
+            ],
+        }])
+    }
+}
+
+impl Drop for SkyboxPass {
+    fn drop(&mut self) {
+        let _ = self
+            .painter
+            .wait_idle()
+            .inspect_err(|e| eprintln!("at wait for device idle: {e}"));
+        unsafe {
+            self.painter.device.destroy_sampler(self.sampler, None);
+        }
+    }
+}
@@ -19,6 +19,8 @@ pub struct SwapchainManager {
     pub present_mode: vk::PresentModeKHR,
     pub surface_format: vk::SurfaceFormatKHR,
     pub surface_resolution: vk::Extent2D,
+    /// Only includes `STORAGE` when asked for, presenting just needs blits.
+    pub image_usage: vk::ImageUsageFlags,
     pub surface: vk::SurfaceKHR,
     pub gpu: vk::PhysicalDevice,
     pub swapchain: vk::SwapchainKHR,
@@ -74,12 +76,19 @@ impl SwapchainManager {
         swapchain_device: khr::swapchain::Device,
         present_mode: vk::PresentModeKHR,
         surface_format: vk::SurfaceFormatKHR,
+        storage_usage: bool,
     ) -> Result<Self, SwapchainManagerError> {
         unsafe {
             let surface_caps = surface_instance
                 .get_physical_device_surface_capabilities(gpu, surface)
                 .map_err(SwapchainManagerError::SurfaceCapsQueryError)?;
 
+            let mut image_usage =
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_DST;
+            if storage_usage {
+                image_usage |= vk::ImageUsageFlags::STORAGE;
+            }
+
             let swapchain_create_info = vk::SwapchainCreateInfoKHR::default()
                 .surface(surface)
                 .min_image_count(surface_caps.min_image_count)
@@ -87,11 +96,7 @@ impl SwapchainManager {
                 .image_color_space(surface_format.color_space)
                 .image_extent(surface_caps.current_extent)
                 .image_array_layers(1)
-                .image_usage(
-                    vk::ImageUsageFlags::COLOR_ATTACHMENT
-                        | vk::ImageUsageFlags::TRANSFER_DST
-                        | vk::ImageUsageFlags::STORAGE,
-                )
+                .image_usage(image_usage)
                 .pre_transform(surface_caps.current_transform)
                 .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
                 .present_mode(present_mode)
@@ -132,6 +137,7 @@ impl SwapchainManager {
                     present_mode,
                     surface_format,
                     surface_resolution: surface_caps.current_extent,
+                    image_usage,
                     surface,
                     gpu,
                     swapchain,
@@ -159,11 +165,7 @@ impl SwapchainManager {
                 .image_color_space(self.surface_format.color_space)
                 .image_extent(new_resolution)
                 .image_array_layers(1)
-                .image_usage(
-                    vk::ImageUsageFlags::COLOR_ATTACHMENT
-                        | vk::ImageUsageFlags::TRANSFER_DST
-                        | vk::ImageUsageFlags::STORAGE,
-                )
+                .image_usage(self.image_usage)
                 .pre_transform(surface_caps.current_transform)
                 .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
                 .present_mode(self.present_mode)
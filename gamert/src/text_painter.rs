@@ -0,0 +1,504 @@
+use std::{mem::offset_of, sync::Arc};
+
+use ash::vk;
+use include_bytes_aligned::include_bytes_aligned;
+use painter::{
+    ash, Buffer, CommandPoolKind, GAllocator, GpuCommand, GpuRenderPassCommand, Image2d,
+    ImageAccess, Painter, RenderOutput, RenderPipelineBuilder, ShaderInputAllocator,
+    ShaderInputBindingInfo, ShaderInputType, SingePassRenderPipeline,
+};
+
+static VERTEX_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/text.vert.spv");
+static FRAGMENT_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/text.frag.spv");
+
+/// Glyphs are 5x7 pixels, with a pixel of spacing to the right and below in both the atlas
+/// and laid out text.
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+const CELL_WIDTH: u32 = 6;
+const CELL_HEIGHT: u32 = 8;
+const ATLAS_COLUMNS: u32 = 16;
+const ATLAS_ROWS: u32 = GLYPHS.len().div_ceil(ATLAS_COLUMNS as usize) as u32;
+const FIRST_GLYPH: char = ' ';
+/// Glyphs drawable per frame, the rest of the queued text is dropped.
+const MAX_GLYPHS: usize = 4096;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct TextVertex {
+    /// In pixels from the top left of the target.
+    position: glam::Vec2,
+    uv: glam::Vec2,
+    color: glam::Vec4,
+}
+
+impl TextVertex {
+    fn get_binding_description() -> Vec<vk::VertexInputBindingDescription> {
+        vec![vk::VertexInputBindingDescription::default()
+            .binding(0)
+            .stride(size_of::<Self>() as u32)
+            .input_rate(vk::VertexInputRate::VERTEX)]
+    }
+
+    fn get_attribute_descriptions() -> Vec<vk::VertexInputAttributeDescription> {
+        [
+            (offset_of!(Self, position), vk::Format::R32G32_SFLOAT),
+            (offset_of!(Self, uv), vk::Format::R32G32_SFLOAT),
+            (offset_of!(Self, color), vk::Format::R32G32B32A32_SFLOAT),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(location, (offset, format))| {
+            vk::VertexInputAttributeDescription::default()
+                .binding(0)
+                .location(location as u32)
+                .format(format)
+                .offset(offset as u32)
+        })
+        .collect()
+    }
+}
+
+struct PerFrameData {
+    vertex_buffer: Buffer,
+    vertex_count: u32,
+    /// View the render output was made for, only recreated when the target changes.
+    target_view: vk::ImageView,
+    render_output: Option<RenderOutput>,
+}
+
+/// Draws text from a built in bitmap font over an already rendered image, for frame times
+/// and other debug info. Queue text with `draw_text`, then write it with `update_inputs` and
+/// draw it with `draw_commands`.
+pub struct TextPainter {
+    painter: Arc<Painter>,
+    pipeline: SingePassRenderPipeline,
+    sampler: vk::Sampler,
+    shader_input_allocator: ShaderInputAllocator,
+    descriptor_set: vk::DescriptorSet,
+    atlas: Image2d,
+    per_frame_datas: Vec<PerFrameData>,
+    queued_vertices: Vec<TextVertex>,
+    allocator: GAllocator,
+}
+
+impl TextPainter {
+    pub fn new(
+        painter: Arc<Painter>,
+        frame_count: usize,
+        target_format: vk::Format,
+    ) -> Result<Self, String> {
+        let sampler = unsafe {
+            painter
+                .device
+                .create_sampler(
+                    &vk::SamplerCreateInfo::default()
+                        .mag_filter(vk::Filter::NEAREST)
+                        .min_filter(vk::Filter::NEAREST)
+                        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+                    None,
+                )
+                .map_err(|e| format!("at create sampler: {e}"))?
+        };
+
+        let pipeline = RenderPipelineBuilder::new()
+            .shader_inputs(vec![ShaderInputBindingInfo {
+                _type: ShaderInputType::CombinedImageSampler,
+                count: 1,
+                dynamic: false,
+                stages: vk::ShaderStageFlags::FRAGMENT,
+            }])
+            // Text is drawn over what earlier passes rendered
+            .color_attachment(
+                target_format,
+                vk::AttachmentLoadOp::LOAD,
+                vk::AttachmentStoreOp::STORE,
+            )
+            .push_constants(
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                size_of::<glam::Mat4>() as u32,
+            )
+            .vertex_input(
+                TextVertex::get_binding_description(),
+                TextVertex::get_attribute_descriptions(),
+            )
+            .shaders(VERTEX_SHADER_CODE, FRAGMENT_SHADER_CODE)
+            .alpha_blending()
+            .build(painter.clone())
+            .map_err(|e| format!("at create text pipeline: {e}"))?;
+
+        let shader_input_allocator = ShaderInputAllocator::for_layouts(
+            painter.clone(),
+            &[(&pipeline.shader_input_layouts[0], 1)],
+        )
+        .map_err(|e| format!("at create shader input allocator: {e}"))?;
+
+        let mut allocator =
+            GAllocator::new(painter.clone()).map_err(|e| format!("at create allocator: {e}"))?;
+
+        let atlas = Self::upload_atlas(&painter, &mut allocator)?;
+
+        // The atlas never changes, so every frame shares one set
+        let descriptor_set = pipeline
+            .make_shader_inputs(&shader_input_allocator)
+            .map_err(|e| format!("at make shader inputs: {e}"))?
+            .swap_remove(0);
+        unsafe {
+            painter.device.update_descriptor_sets(
+                &[vk::WriteDescriptorSet::default()
+                    .dst_set(descriptor_set)
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .descriptor_count(1)
+                    .image_info(&[vk::DescriptorImageInfo::default()
+                        .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                        .image_view(atlas.image_view)
+                        .sampler(sampler)])],
+                &[],
+            );
+        }
+
+        let per_frame_datas = (0..frame_count)
+            .map(|_| {
+                let vertex_buffer = painter
+                    .create_buffer(
+                        (MAX_GLYPHS * 6 * size_of::<TextVertex>()) as u64,
+                        vk::BufferUsageFlags::VERTEX_BUFFER,
+                        Some(&mut allocator),
+                        Some(true),
+                    )
+                    .map_err(|e| format!("at create text vertex buffer: {e}"))?;
+                Ok(PerFrameData {
+                    vertex_buffer,
+                    vertex_count: 0,
+                    target_view: vk::ImageView::null(),
+                    render_output: None,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Self {
+            painter,
+            pipeline,
+            sampler,
+            shader_input_allocator,
+            descriptor_set,
+            atlas,
+            per_frame_datas,
+            queued_vertices: vec![],
+            allocator,
+        })
+    }
+
+    /// Rasterizes `GLYPHS` into a white atlas with the glyph coverage in alpha.
+    fn upload_atlas(painter: &Arc<Painter>, allocator: &mut GAllocator) -> Result<Image2d, String> {
+        let extent = vk::Extent2D {
+            width: ATLAS_COLUMNS * CELL_WIDTH,
+            height: ATLAS_ROWS * CELL_HEIGHT,
+        };
+        let mut pixels = vec![0u8; (extent.width * extent.height * 4) as usize];
+        for (glyph_idx, rows) in GLYPHS.iter().enumerate() {
+            let cell_x = glyph_idx as u32 % ATLAS_COLUMNS * CELL_WIDTH;
+            let cell_y = glyph_idx as u32 / ATLAS_COLUMNS * CELL_HEIGHT;
+            for (y, row) in rows.iter().enumerate() {
+                for x in 0..GLYPH_WIDTH {
+                    if row & (1 << (GLYPH_WIDTH - 1 - x)) == 0 {
+                        continue;
+                    }
+                    let pixel = ((cell_y + y as u32) * extent.width + cell_x + x) as usize * 4;
+                    pixels[pixel..pixel + 4].copy_from_slice(&[255; 4]);
+                }
+            }
+        }
+
+        let atlas = painter
+            .create_image_2d(
+                vk::Format::R8G8B8A8_UNORM,
+                extent,
+                vec![ImageAccess::TransferWrite, ImageAccess::ShaderRead],
+                Some(allocator),
+                Some(false),
+                None,
+            )
+            .map_err(|e| format!("at create font atlas: {e}"))?;
+        let staged = allocator
+            .stage_upload(&pixels)
+            .map_err(|e| format!("at stage font atlas: {e}"))?;
+        let command_pool = painter
+            .create_command_pool(CommandPoolKind::Transient)
+            .map_err(|e| format!("at create command pool: {e}"))?;
+        painter
+            .run_commands_blocking(
+                &command_pool,
+                &[
+                    GpuCommand::ImageAccessInit {
+                        image: &atlas,
+                        access: ImageAccess::TransferWrite,
+                    },
+                    GpuCommand::CopyBufferToImageComplete {
+                        buffer: allocator.staging_buffer(&staged),
+                        buffer_offset: staged.offset,
+                        image: &atlas,
+                    },
+                    GpuCommand::ImageAccessHint {
+                        image: &atlas,
+                        access: ImageAccess::ShaderRead,
+                    },
+                ],
+            )
+            .map_err(|e| format!("at upload font atlas: {e}"))?;
+        allocator.reset_staging();
+        Ok(atlas)
+    }
+
+    /// Queues `text` for the next `update_inputs`, with its top left corner at `x`, `y` in
+    /// pixels. Glyphs are `scale` times their 5x7 pixel size, `\n` starts a new line and
+    /// characters missing from the font show as `?`.
+    pub fn draw_text(&mut self, text: &str, x: f32, y: f32, scale: f32, color: glam::Vec4) {
+        let glyph_size = glam::vec2(GLYPH_WIDTH as f32, GLYPH_HEIGHT as f32) * scale;
+        let cell_uv = glam::vec2(1.0 / ATLAS_COLUMNS as f32, 1.0 / ATLAS_ROWS as f32);
+        let glyph_uv = cell_uv
+            * glam::vec2(
+                GLYPH_WIDTH as f32 / CELL_WIDTH as f32,
+                GLYPH_HEIGHT as f32 / CELL_HEIGHT as f32,
+            );
+        let mut pen = glam::vec2(x, y);
+        for ch in text.chars() {
+            if ch == '\n' {
+                pen = glam::vec2(x, pen.y + CELL_HEIGHT as f32 * scale);
+                continue;
+            }
+            let glyph_idx = (ch as usize)
+                .checked_sub(FIRST_GLYPH as usize)
+                .filter(|&glyph_idx| glyph_idx < GLYPHS.len())
+                .unwrap_or('?' as usize - FIRST_GLYPH as usize);
+            if ch != ' ' {
+                let uv = cell_uv
+                    * glam::vec2(
+                        (glyph_idx as u32 % ATLAS_COLUMNS) as f32,
+                        (glyph_idx as u32 / ATLAS_COLUMNS) as f32,
+                    );
+                let corner = |offset: glam::Vec2| TextVertex {
+                    position: pen + glyph_size * offset,
+                    uv: uv + glyph_uv * offset,
+                    color,
+                };
+                let top_left = corner(glam::vec2(0.0, 0.0));
+                let bottom_left = corner(glam::vec2(0.0, 1.0));
+                let bottom_right = corner(glam::vec2(1.0, 1.0));
+                let top_right = corner(glam::vec2(1.0, 0.0));
+                // Counter clockwise on screen, so the quads survive back face culling
+                self.queued_vertices.extend([
+                    top_left,
+                    bottom_left,
+                    bottom_right,
+                    bottom_right,
+                    top_right,
+                    top_left,
+                ]);
+            }
+            pen.x += CELL_WIDTH as f32 * scale;
+        }
+    }
+
+    /// Writes the text queued since the last call into this frame's vertex buffer. Call once
+    /// the frame's previous submission has completed.
+    pub fn update_inputs(&mut self, frame_number: usize) -> Result<(), String> {
+        let frame_number = frame_number % self.per_frame_datas.len();
+        let per_frame_data = &mut self.per_frame_datas[frame_number];
+        self.queued_vertices.truncate(MAX_GLYPHS * 6);
+        unsafe {
+            per_frame_data
+                .vertex_buffer
+                .write_to_mem(self.queued_vertices.as_slice().align_to::<u8>().1)
+                .map_err(|e| format!("at write text vertices: {e}"))?;
+        }
+        per_frame_data.vertex_count = self.queued_vertices.len() as u32;
+        self.queued_vertices.clear();
+        Ok(())
+    }
+
+    /// Points this frame's draw at `target`. Call before recording the frame, once its
+    /// previous submission has completed.
+    pub fn set_target(&mut self, frame_number: usize, target: &Image2d) -> Result<(), String> {
+        let frame_number = frame_number % self.per_frame_datas.len();
+        let per_frame_data = &mut self.per_frame_datas[frame_number];
+        if per_frame_data.target_view == target.image_view {
+            return Ok(());
+        }
+        per_frame_data.render_output = Some(
+            self.pipeline
+                .create_render_output(vec![target])
+                .map_err(|e| format!("at create text render output: {e}"))?,
+        );
+        per_frame_data.target_view = target.image_view;
+        Ok(())
+    }
+
+    /// The target set with `set_target` has to be written earlier in the same recording,
+    /// its contents are kept under the text.
+    pub fn draw_commands(&self, frame_number: usize) -> Vec<GpuCommand<'_>> {
+        let per_frame_data = &self.per_frame_datas[frame_number % self.per_frame_datas.len()];
+        let Some(render_output) = &per_frame_data.render_output else {
+            return vec![];
+        };
+        if per_frame_data.vertex_count == 0 {
+            return vec![];
+        }
+        // Pixels map to the y down clip space with no flip
+        let projection = glam::Mat4::orthographic_rh(
+            0.0,
+            render_output.extent.width as f32,
+            0.0,
+            render_output.extent.height as f32,
+            -1.0,
+            1.0,
+        );
+        vec![GpuCommand::RunRenderPass {
+            render_pass: self.pipeline.render_pass,
+            render_output,
+            clear_values: vec![],
+            pipelines: vec![self.pipeline.pipeline],
+            pipeline_layouts: vec![self.pipeline.pipeline_layout],
+            commands: vec![
+                GpuRenderPassCommand::BindPipeline { pipeline: 0 },
+                GpuRenderPassCommand::BindShaderInput {
+                    pipeline_layout: 0,
+                    descriptor_sets: vec![self.descriptor_set],
+                },
+                GpuRenderPassCommand::SetPushConstant {
+                    pipeline_layout: 0,
+                    stage_flags: vk::ShaderStageFlags::VERTEX,
+                    offset: 0,
+                    data: projection
+                        .to_cols_array()
+                        .iter()
+                        .flat_map(|value| value.to_ne_bytes())
+                        .collect(),
+                },
+                GpuRenderPassCommand::BindVertexBuffers {
+                    buffers: vec![(&per_frame_data.vertex_buffer, 0)],
+                },
+                GpuRenderPassCommand::DrawVertices {
+                    count: per_frame_data.vertex_count,
+                    first_vertex: 0,
+                },
+            ],
+        }]
+    }
+}
+
+impl Drop for TextPainter {
+    fn drop(&mut self) {
+        let _ = self
+            .painter
+            .wait_idle()
+            .inspect_err(|e| eprintln!("at wait for device idle: {e}"));
+        unsafe {
+            self.painter.device.destroy_sampler(self.sampler, None);
+        }
+    }
+}
+
+/// Printable ASCII from `FIRST_GLYPH` on, one row per pixel row with the leftmost pixel in
+/// the highest of the low `GLYPH_WIDTH` bits.
+#[rustfmt::skip]
+const GLYPHS: [[u8; GLYPH_HEIGHT as usize]; 95] = [
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000], // ' '
+    [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000, 0b00100], // '!'
+    [0b01010, 0b01010, 0b01010, 0b00000, 0b00000, 0b00000, 0b00000], // '"'
+    [0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010], // '#'
+    [0b00100, 0b01111, 0b10100, 0b01110, 0b00101, 0b11110, 0b00100], // '$'
+    [0b11000, 0b11001, 0b00010, 0b00100, 0b01000, 0b10011, 0b00011], // '%'
+    [0b01100, 0b10010, 0b10100, 0b01000, 0b10101, 0b10010, 0b01101], // '&'
+    [0b00100, 0b00100, 0b00100, 0b00000, 0b00000, 0b00000, 0b00000], // '\''
+    [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010], // '('
+    [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000], // ')'
+    [0b00000, 0b00100, 0b10101, 0b01110, 0b10101, 0b00100, 0b00000], // '*'
+    [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000], // '+'
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000], // ','
+    [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000], // '-'
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100], // '.'
+    [0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000], // '/'
+    [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110], // '0'
+    [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110], // '1'
+    [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111], // '2'
+    [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110], // '3'
+    [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010], // '4'
+    [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110], // '5'
+    [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110], // '6'
+    [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000], // '7'
+    [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110], // '8'
+    [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100], // '9'
+    [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000], // ':'
+    [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b00100, 0b01000], // ';'
+    [0b00010, 0b00100, 0b01000, 0b10000, 0b01000, 0b00100, 0b00010], // '<'
+    [0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000], // '='
+    [0b01000, 0b00100, 0b00010, 0b00001, 0b00010, 0b00100, 0b01000], // '>'
+    [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100], // '?'
+    [0b01110, 0b10001, 0b00001, 0b01101, 0b10101, 0b10101, 0b01110], // '@'
+    [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001], // 'A'
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110], // 'B'
+    [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110], // 'C'
+    [0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100], // 'D'
+    [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111], // 'E'
+    [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000], // 'F'
+    [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111], // 'G'
+    [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001], // 'H'
+    [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110], // 'I'
+    [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100], // 'J'
+    [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001], // 'K'
+    [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111], // 'L'
+    [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001], // 'M'
+    [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001], // 'N'
+    [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110], // 'O'
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000], // 'P'
+    [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101], // 'Q'
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001], // 'R'
+    [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110], // 'S'
+    [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100], // 'T'
+    [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110], // 'U'
+    [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100], // 'V'
+    [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010], // 'W'
+    [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001], // 'X'
+    [0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100], // 'Y'
+    [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111], // 'Z'
+    [0b01110, 0b01000, 0b01000, 0b01000, 0b01000, 0b01000, 0b01110], // '['
+    [0b00000, 0b10000, 0b01000, 0b00100, 0b00010, 0b00001, 0b00000], // '\\'
+    [0b01110, 0b00010, 0b00010, 0b00010, 0b00010, 0b00010, 0b01110], // ']'
+    [0b00100, 0b01010, 0b10001, 0b00000, 0b00000, 0b00000, 0b00000], // '^'
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111], // '_'
+    [0b01000, 0b00100, 0b00010, 0b00000, 0b00000, 0b00000, 0b00000], // '`'
+    [0b00000, 0b00000, 0b01110, 0b00001, 0b01111, 0b10001, 0b01111], // 'a'
+    [0b10000, 0b10000, 0b10110, 0b11001, 0b10001, 0b10001, 0b11110], // 'b'
+    [0b00000, 0b00000, 0b01110, 0b10000, 0b10000, 0b10001, 0b01110], // 'c'
+    [0b00001, 0b00001, 0b01101, 0b10011, 0b10001, 0b10001, 0b01111], // 'd'
+    [0b00000, 0b00000, 0b01110, 0b10001, 0b11111, 0b10000, 0b01110], // 'e'
+    [0b00110, 0b01001, 0b01000, 0b11100, 0b01000, 0b01000, 0b01000], // 'f'
+    [0b00000, 0b01111, 0b10001, 0b10001, 0b01111, 0b00001, 0b01110], // 'g'
+    [0b10000, 0b10000, 0b10110, 0b11001, 0b10001, 0b10001, 0b10001], // 'h'
+    [0b00100, 0b00000, 0b01100, 0b00100, 0b00100, 0b00100, 0b01110], // 'i'
+    [0b00010, 0b00000, 0b00110, 0b00010, 0b00010, 0b10010, 0b01100], // 'j'
+    [0b10000, 0b10000, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010], // 'k'
+    [0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110], // 'l'
+    [0b00000, 0b00000, 0b11010, 0b10101, 0b10101, 0b10001, 0b10001], // 'm'
+    [0b00000, 0b00000, 0b10110, 0b11001, 0b10001, 0b10001, 0b10001], // 'n'
+    [0b00000, 0b00000, 0b01110, 0b10001, 0b10001, 0b10001, 0b01110], // 'o'
+    [0b00000, 0b00000, 0b11110, 0b10001, 0b11110, 0b10000, 0b10000], // 'p'
+    [0b00000, 0b00000, 0b01101, 0b10011, 0b01111, 0b00001, 0b00001], // 'q'
+    [0b00000, 0b00000, 0b10110, 0b11001, 0b10000, 0b10000, 0b10000], // 'r'
+    [0b00000, 0b00000, 0b01110, 0b10000, 0b01110, 0b00001, 0b11110], // 's'
+    [0b01000, 0b01000, 0b11100, 0b01000, 0b01000, 0b01001, 0b00110], // 't'
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b10001, 0b10011, 0b01101], // 'u'
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100], // 'v'
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b10101, 0b10101, 0b01010], // 'w'
+    [0b00000, 0b00000, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001], // 'x'
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b01111, 0b00001, 0b01110], // 'y'
+    [0b00000, 0b00000, 0b11111, 0b00010, 0b00100, 0b01000, 0b11111], // 'z'
+    [0b00010, 0b00100, 0b00100, 0b01000, 0b00100, 0b00100, 0b00010], // '{'
+    [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100], // '|'
+    [0b01000, 0b00100, 0b00100, 0b00010, 0b00100, 0b00100, 0b01000], // '}'
+    [0b00000, 0b00000, 0b01000, 0b10101, 0b00010, 0b00000, 0b00000], // '~'
+];
@@ -0,0 +1,267 @@
+use std::sync::Arc;
+
+use ash::vk;
+use include_bytes_aligned::include_bytes_aligned;
+use painter::{
+    ash, GpuCommand, GpuRenderPassCommand, Image2d, ImageAccess, Painter, RenderOutput,
+    RenderPipelineBuilder, ShaderInputAllocator, ShaderInputBindingInfo, ShaderInputType,
+    SingePassRenderPipeline,
+};
+
+static FULLSCREEN_VERTEX_SHADER_CODE: &[u8] =
+    include_bytes_aligned!(4, "shaders/fullscreen.vert.spv");
+static FRAGMENT_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/tone_map.frag.spv");
+
+/// Curve mapping exposed colors to the displayable `[0, 1]` range.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToneMapMode {
+    /// Clamps, like a plain copy.
+    None = 0,
+    Reinhard = 1,
+    /// Narkowicz's fit of the ACES filmic curve.
+    Aces = 2,
+}
+
+/// Must match the push constant block in tone_map.frag
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct ToneMapParams {
+    exposure: f32,
+    mode: u32,
+    /// Set for `UNORM` targets, `SRGB` ones encode on write.
+    encode_srgb: u32,
+    /// Clockwise quarter turns the input is rotated by onto the target.
+    quarter_turns: u32,
+}
+
+struct PerFrameData {
+    descriptor_set: vk::DescriptorSet,
+    /// View the descriptor set points at, only rewritten when the input changes.
+    input_view: vk::ImageView,
+    /// View the render output was made for, only recreated when the target changes.
+    target_view: vk::ImageView,
+    render_output: Option<RenderOutput>,
+}
+
+/// Copies a linear color image to the target, usually a sheet, scaled by the exposure and
+/// tone mapped, then gamma encoded if the target format doesn't do it itself. Can also rotate
+/// the image for sheets presented rotated.
+pub struct ToneMapPass {
+    painter: Arc<Painter>,
+    pipeline: SingePassRenderPipeline,
+    sampler: vk::Sampler,
+    shader_input_allocator: ShaderInputAllocator,
+    per_frame_datas: Vec<PerFrameData>,
+    params: ToneMapParams,
+}
+
+impl ToneMapPass {
+    /// `target_is_srgb` is whether `target_format` encodes to sRGB on write, like
+    /// `Sheets::is_srgb` tells for sheets. Otherwise the pass encodes itself.
+    pub fn new(
+        painter: Arc<Painter>,
+        frame_count: usize,
+        target_format: vk::Format,
+        target_is_srgb: bool,
+    ) -> Result<Self, String> {
+        let sampler = unsafe {
+            painter
+                .device
+                .create_sampler(
+                    &vk::SamplerCreateInfo::default()
+                        .mag_filter(vk::Filter::LINEAR)
+                        .min_filter(vk::Filter::LINEAR)
+                        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+                    None,
+                )
+                .map_err(|e| format!("at create sampler: {e}"))?
+        };
+
+        let pipeline = RenderPipelineBuilder::new()
+            .shader_inputs(vec![
+                ShaderInputBindingInfo {
+                    _type: ShaderInputType::SampledImage2d,
+                    count: 1,
+                    dynamic: false,
+                    stages: vk::ShaderStageFlags::FRAGMENT,
+                },
+                ShaderInputBindingInfo {
+                    _type: ShaderInputType::Sampler,
+                    count: 1,
+                    dynamic: false,
+                    stages: vk::ShaderStageFlags::FRAGMENT,
+                },
+            ])
+            // Every pixel is overwritten, so the old contents are never loaded
+            .color_attachment(
+                target_format,
+                vk::AttachmentLoadOp::DONT_CARE,
+                vk::AttachmentStoreOp::STORE,
+            )
+            .push_constants(
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                size_of::<ToneMapParams>() as u32,
+            )
+            .shaders(FULLSCREEN_VERTEX_SHADER_CODE, FRAGMENT_SHADER_CODE)
+            .build(painter.clone())
+            .map_err(|e| format!("at create tone map pipeline: {e}"))?;
+
+        let shader_input_allocator = ShaderInputAllocator::for_frames(
+            painter.clone(),
+            &[&pipeline.shader_input_layouts[0]],
+            frame_count as u32,
+        )
+        .map_err(|e| format!("at create shader input allocator: {e}"))?;
+
+        let per_frame_datas = (0..frame_count)
+            .map(|_| {
+                let descriptor_set = pipeline
+                    .make_shader_inputs(&shader_input_allocator)
+                    .map_err(|e| format!("at make shader inputs: {e}"))?
+                    .swap_remove(0);
+                unsafe {
+                    painter.device.update_descriptor_sets(
+                        &[vk::WriteDescriptorSet::default()
+                            .dst_set(descriptor_set)
+                            .dst_binding(1)
+                            .descriptor_type(vk::DescriptorType::SAMPLER)
+                            .descriptor_count(1)
+                            .image_info(&[vk::DescriptorImageInfo::default().sampler(sampler)])],
+                        &[],
+                    );
+                }
+                Ok(PerFrameData {
+                    descriptor_set,
+                    input_view: vk::ImageView::null(),
+                    target_view: vk::ImageView::null(),
+                    render_output: None,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Self {
+            painter,
+            pipeline,
+            sampler,
+            shader_input_allocator,
+            per_frame_datas,
+            params: ToneMapParams {
+                exposure: 1.0,
+                mode: ToneMapMode::None as u32,
+                encode_srgb: !target_is_srgb as u32,
+                quarter_turns: 0,
+            },
+        })
+    }
+
+    pub fn set_mode(&mut self, mode: ToneMapMode) {
+        self.params.mode = mode as u32;
+    }
+
+    /// Colors are multiplied by `exposure` before tone mapping.
+    pub fn set_exposure(&mut self, exposure: f32) {
+        self.params.exposure = exposure;
+    }
+
+    /// Rotates the input clockwise onto the target, for sheets presented rotated, see
+    /// `Sheets::quarter_turns`. The input should then be `Sheets::logical_extent` sized.
+    pub fn set_quarter_turns(&mut self, quarter_turns: u32) {
+        self.params.quarter_turns = quarter_turns % 4;
+    }
+
+    /// Points this frame's descriptor set at `input` and its draw at `target`. Call before
+    /// recording the frame, once its previous submission has completed.
+    pub fn set_images(
+        &mut self,
+        frame_number: usize,
+        input: &Image2d,
+        target: &Image2d,
+    ) -> Result<(), String> {
+        let frame_number = frame_number % self.per_frame_datas.len();
+        let per_frame_data = &mut self.per_frame_datas[frame_number];
+        if per_frame_data.input_view != input.image_view {
+            unsafe {
+                self.painter.device.update_descriptor_sets(
+                    &[vk::WriteDescriptorSet::default()
+                        .dst_set(per_frame_data.descriptor_set)
+                        .dst_binding(0)
+                        .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                        .descriptor_count(1)
+                        .image_info(&[vk::DescriptorImageInfo::default()
+                            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                            .image_view(input.image_view)])],
+                    &[],
+                );
+            }
+            per_frame_data.input_view = input.image_view;
+        }
+        if per_frame_data.target_view != target.image_view {
+            per_frame_data.render_output = Some(
+                self.pipeline
+                    .create_render_output(vec![target])
+                    .map_err(|e| format!("at create tone map render output: {e}"))?,
+            );
+            per_frame_data.target_view = target.image_view;
+        }
+        Ok(())
+    }
+
+    /// `input` has to be the image passed to `set_images`, written earlier in the same
+    /// recording so it gets transitioned for sampling.
+    pub fn draw_commands<'a>(
+        &'a self,
+        frame_number: usize,
+        input: &'a Image2d,
+    ) -> Result<Vec<GpuCommand<'a>>, String> {
+        let per_frame_data = &self.per_frame_datas[frame_number % self.per_frame_datas.len()];
+        let render_output = per_frame_data
+            .render_output
+            .as_ref()
+            .ok_or("at tone map draw: no target set".to_string())?;
+        Ok(vec![
+            GpuCommand::ImageAccessHint {
+                image: input,
+                access: ImageAccess::ShaderRead,
+            },
+            GpuCommand::RunRenderPass {
+                render_pass: self.pipeline.render_pass,
+                render_output,
+                clear_values: vec![],
+                pipelines: vec![self.pipeline.pipeline],
+                pipeline_layouts: vec![self.pipeline.pipeline_layout],
+                commands: vec![
+                    GpuRenderPassCommand::BindPipeline { pipeline: 0 },
+                    GpuRenderPassCommand::BindShaderInput {
+                        pipeline_layout: 0,
+                        descriptor_sets: vec![per_frame_data.descriptor_set],
+                    },
+                    GpuRenderPassCommand::SetPushConstant {
+                        pipeline_layout: 0,
+                        stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                        offset: 0,
+                        data: unsafe { [self.params].align_to::<u8>().1.to_vec() },
+                    },
+                    GpuRenderPassCommand::DrawVertices {
+                        count: 3,
+                        first_vertex: 0,
+                    },
+                ],
+            },
+        ])
+    }
+}
+
+impl Drop for ToneMapPass {
+    fn drop(&mut self) {
+        let _ = self
+            .painter
+            .wait_idle()
+            .inspect_err(|e| eprintln!("at wait for device idle: {e}"));
+        unsafe {
+            self.painter.device.destroy_sampler(self.sampler, None);
+        }
+    }
+}